@@ -0,0 +1,8 @@
+-- every connection so far came in through the SSH tarpit
+ALTER TABLE connections
+ADD COLUMN protocol TEXT NOT NULL DEFAULT 'ssh';
+
+-- the default only exists to backfill the rows above, every future insert supplies the protocol
+ALTER TABLE connections
+ALTER COLUMN protocol
+DROP DEFAULT;
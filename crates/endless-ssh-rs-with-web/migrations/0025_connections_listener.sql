@@ -0,0 +1,3 @@
+-- NULL for connections stored before there could be more than one listener
+ALTER TABLE connections
+ADD COLUMN listener TEXT;
@@ -0,0 +1,3 @@
+-- the identification line the client sent, NULL when not captured
+ALTER TABLE connections
+ADD COLUMN client_ident TEXT;
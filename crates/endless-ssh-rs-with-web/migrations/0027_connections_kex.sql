@@ -0,0 +1,8 @@
+-- what the client offered in its KEXINIT, NULL outside of key exchange mode
+ALTER TABLE connections
+ADD COLUMN hassh TEXT,
+ADD COLUMN kex_algorithms TEXT,
+ADD COLUMN host_key_algorithms TEXT,
+ADD COLUMN encryption_algorithms TEXT,
+ADD COLUMN mac_algorithms TEXT,
+ADD COLUMN compression_algorithms TEXT;
@@ -0,0 +1,18 @@
+-- every login attempt the SSH honeypot rejected, linked to its connection by (connected_at, connection_id)
+-- no foreign key, connections is a hypertable and its chunks are dropped by the retention policy
+CREATE TABLE
+    auth_attempts (
+        id BIGSERIAL PRIMARY KEY,
+        connection_id BIGINT NOT NULL,
+        connected_at TIMESTAMPTZ NOT NULL,
+        attempted_at TIMESTAMPTZ NOT NULL,
+        ip_address INET NOT NULL,
+        method TEXT NOT NULL,
+        username TEXT NOT NULL,
+        password TEXT,
+        key_fingerprint TEXT
+    );
+
+CREATE INDEX auth_attempts_connection_idx ON auth_attempts (connected_at, connection_id);
+
+CREATE INDEX auth_attempts_attempted_at_idx ON auth_attempts (attempted_at DESC);
@@ -0,0 +1,4 @@
+-- how the listener timed its lines, NULL for connections stored before delays could vary
+ALTER TABLE connections
+ADD COLUMN delay_strategy TEXT,
+ADD COLUMN delay INTERVAL;
@@ -0,0 +1,4 @@
+-- why the connection ended, NULL for connections stored before; eviction_reason is only set for evicted ones
+ALTER TABLE connections
+ADD COLUMN disconnect_reason TEXT,
+ADD COLUMN eviction_reason TEXT;
@@ -0,0 +1,8 @@
+-- what TCP_INFO told of the connection as it ended, NULL when it couldn't tell, and for connections stored before;
+-- times in microseconds, the congestion window in segments
+ALTER TABLE connections
+ADD COLUMN rtt_us INTEGER,
+ADD COLUMN rtt_var_us INTEGER,
+ADD COLUMN mss INTEGER,
+ADD COLUMN retransmits INTEGER,
+ADD COLUMN congestion_window INTEGER;
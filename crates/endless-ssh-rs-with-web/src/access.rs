@@ -0,0 +1,94 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+/// What happens to a new connection, depending on where it comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Tarpit,
+    /// Closed right away, it doesn't even take a slot.
+    Close,
+    /// Trapped like any other, but left out of the dashboard and the statistics.
+    Unrecorded,
+}
+
+/// Our own sources, like monitoring probes, are trapped without being recorded, unwanted ones closed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessLists {
+    /// Sources trapped without being recorded.
+    pub allow: Vec<IpNet>,
+    /// Sources closed right away.
+    pub deny: Vec<IpNet>,
+}
+
+/// The prefix length of the most specific of `networks` that holds `ip`.
+fn longest_match(networks: &[IpNet], ip: IpAddr) -> Option<u8> {
+    networks
+        .iter()
+        .filter(|network| network.contains(&ip))
+        .map(IpNet::prefix_len)
+        .max()
+}
+
+impl AccessLists {
+    /// The most specific network holding `ip` decides, the denylist wins a tie. IPv4-mapped addresses, as seen on
+    /// dual-stack sockets, count as IPv4.
+    pub fn check(&self, ip: IpAddr) -> Access {
+        let ip = ip.to_canonical();
+
+        match (
+            longest_match(&self.allow, ip),
+            longest_match(&self.deny, ip),
+        ) {
+            (None, None) => Access::Tarpit,
+            (Some(allow), Some(deny)) if allow > deny => Access::Unrecorded,
+            (Some(_), None) => Access::Unrecorded,
+            (_, Some(_)) => Access::Close,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::access::{Access, AccessLists};
+
+    #[test]
+    fn most_specific_network_decides() {
+        let lists = AccessLists {
+            allow: vec![
+                "10.1.2.0/24".parse().unwrap(),
+                "192.0.2.0/24".parse().unwrap(),
+            ],
+            deny: vec![
+                "10.0.0.0/8".parse().unwrap(),
+                "192.0.2.0/24".parse().unwrap(),
+            ],
+        };
+
+        let check = |ip: &str| lists.check(ip.parse().unwrap());
+
+        assert_eq!(check("10.1.2.3"), Access::Unrecorded);
+        assert_eq!(check("10.1.3.3"), Access::Close);
+        // a tie goes to the denylist
+        assert_eq!(check("192.0.2.1"), Access::Close);
+        assert_eq!(check("198.51.100.1"), Access::Tarpit);
+        assert_eq!(check("2001:db8::1"), Access::Tarpit);
+    }
+
+    #[test]
+    fn checks_ipv4_mapped_addresses_as_ipv4() {
+        let lists = AccessLists {
+            allow: vec!["10.0.0.0/8".parse().unwrap()],
+            deny: Vec::new(),
+        };
+
+        assert_eq!(
+            lists.check(IpAddr::V6(Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped())),
+            Access::Unrecorded
+        );
+    }
+}
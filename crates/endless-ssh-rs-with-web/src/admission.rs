@@ -0,0 +1,434 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::num::NonZeroU8;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use time::SignedDuration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// Sources remembered per listener, beyond this the least recently trapped are forgotten.
+const HISTORY_CAPACITY: usize = 1 << 16;
+
+/// What a listener does with a new client when all of its slots are taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AdmissionPolicy {
+    /// Turn the new client away.
+    #[default]
+    Refuse,
+    /// Disconnect the client that's been trapped the longest.
+    EvictOldest,
+    /// Disconnect the client whose source was trapped the longest in earlier sessions, a repeat offender is worth less.
+    EvictLowestValue,
+    /// Turn the new client away, and keep `percent` (1 to 100) of the slots for sources never seen before.
+    ReserveNew { percent: NonZeroU8 },
+}
+
+impl AdmissionPolicy {
+    /// The slots out of `slots` only never seen sources get.
+    pub fn reserved(self, slots: usize) -> usize {
+        match self {
+            AdmissionPolicy::Refuse
+            | AdmissionPolicy::EvictOldest
+            | AdmissionPolicy::EvictLowestValue => 0,
+            AdmissionPolicy::ReserveNew { percent } => {
+                slots.saturating_mul(usize::from(percent.get())) / 100
+            },
+        }
+    }
+}
+
+impl Display for AdmissionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            AdmissionPolicy::Refuse => f.write_str("refuse"),
+            AdmissionPolicy::EvictOldest => f.write_str("evict-oldest"),
+            AdmissionPolicy::EvictLowestValue => f.write_str("evict-lowest-value"),
+            AdmissionPolicy::ReserveNew { percent } => write!(f, "reserve-new:{}", percent),
+        }
+    }
+}
+
+impl FromStr for AdmissionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "refuse" => Ok(AdmissionPolicy::Refuse),
+            None if s == "evict-oldest" => Ok(AdmissionPolicy::EvictOldest),
+            None if s == "evict-lowest-value" => Ok(AdmissionPolicy::EvictLowestValue),
+            Some(("reserve-new", percent)) => percent
+                .parse::<NonZeroU8>()
+                .ok()
+                .filter(|percent| percent.get() <= 100)
+                .map(|percent| AdmissionPolicy::ReserveNew { percent })
+                .ok_or_else(|| "`reserve-new` expects a percentage from 1 to 100".to_owned()),
+            _ => Err(format!(
+                "Unknown admission policy `{}`, expected `refuse`, `evict-oldest`, `evict-lowest-value` or `reserve-new:PERCENT`",
+                s
+            )),
+        }
+    }
+}
+
+/// Why a client was evicted, stored with its connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    Oldest,
+    LowestValue,
+}
+
+impl EvictionReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EvictionReason::Oldest => "oldest",
+            EvictionReason::LowestValue => "lowest value",
+        }
+    }
+}
+
+impl Display for EvictionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How to end a session early.
+#[derive(Debug)]
+pub enum Evict {
+    /// The client has a task of its own, cancelling this ends it.
+    Task(CancellationToken),
+    /// The client is held by a scheduler, under this token.
+    Scheduled(u64),
+}
+
+#[derive(Debug)]
+struct Session {
+    ip: IpAddr,
+    connected_at: Instant,
+    /// `None` while the session can't be evicted, in its handshake, or once it is.
+    evict: Option<Evict>,
+    evicted: Option<EvictionReason>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Source {
+    /// The time it was trapped in the sessions it ended.
+    trapped: SignedDuration,
+    last_seen: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    next_id: u64,
+    sessions: BTreeMap<u64, Session>,
+    /// Every source trapped, see [`SessionSlot::remember_source`].
+    history: BTreeMap<IpAddr, Source>,
+    /// The sources in `history`, least recently trapped first.
+    by_last_seen: BTreeSet<(Instant, IpAddr)>,
+}
+
+/// A listener's current sessions, and the sources it has seen.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    registry: Mutex<Registry>,
+}
+
+impl Sessions {
+    /// Opens a session for `ip`, `true` when the source was never trapped before.
+    pub fn open(self: &Arc<Self>, ip: IpAddr) -> (SessionSlot, bool) {
+        let ip = ip.to_canonical();
+
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let first_seen = !registry.history.contains_key(&ip);
+
+        let id = registry.next_id;
+
+        registry.next_id += 1;
+        registry.sessions.insert(
+            id,
+            Session {
+                ip,
+                connected_at: Instant::now(),
+                evict: None,
+                evicted: None,
+            },
+        );
+
+        (
+            SessionSlot {
+                sessions: Arc::clone(self),
+                id,
+            },
+            first_seen,
+        )
+    }
+
+    /// Picks the session to end for `reason`, and marks it evicted. `None` when no session can be evicted.
+    pub fn evict(&self, reason: EvictionReason) -> Option<Evict> {
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        let &mut Registry {
+            ref mut sessions,
+            ref history,
+            ..
+        } = &mut *registry;
+
+        let candidates = sessions
+            .values_mut()
+            .filter(|session| session.evict.is_some());
+
+        let session = match reason {
+            EvictionReason::Oldest => candidates.min_by_key(|session| session.connected_at),
+            EvictionReason::LowestValue => candidates.max_by_key(|session| {
+                (
+                    history
+                        .get(&session.ip)
+                        .map(|source| source.trapped)
+                        .unwrap_or_default(),
+                    Reverse(session.connected_at),
+                )
+            }),
+        }?;
+
+        session.evicted = Some(reason);
+
+        session.evict.take()
+    }
+}
+
+/// A session on its listener, until closed or dropped.
+#[derive(Debug)]
+pub struct SessionSlot {
+    sessions: Arc<Sessions>,
+    id: u64,
+}
+
+impl SessionSlot {
+    /// From now on, the session can be ended with `evict`.
+    pub fn evictable(&self, evict: Evict) {
+        let mut registry = self
+            .sessions
+            .registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(session) = registry.sessions.get_mut(&self.id)
+            && session.evicted.is_none()
+        {
+            session.evict = Some(evict);
+        }
+    }
+
+    /// Remembers the session's source, once the client is admitted, a source turned away isn't.
+    pub fn remember_source(&self) {
+        let mut registry = self
+            .sessions
+            .registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let &mut Registry {
+            ref sessions,
+            ref mut history,
+            ref mut by_last_seen,
+            ..
+        } = &mut *registry;
+
+        let Some(ip) = sessions.get(&self.id).map(|session| session.ip) else {
+            return;
+        };
+
+        let now = Instant::now();
+
+        if let Some(source) = history.get_mut(&ip) {
+            by_last_seen.remove(&(source.last_seen, ip));
+
+            source.last_seen = now;
+        } else {
+            if history.len() >= HISTORY_CAPACITY
+                && let Some((_, forgotten)) = by_last_seen.pop_first()
+            {
+                history.remove(&forgotten);
+            }
+
+            history.insert(
+                ip,
+                Source {
+                    trapped: SignedDuration::ZERO,
+                    last_seen: now,
+                },
+            );
+        }
+
+        by_last_seen.insert((now, ip));
+    }
+
+    /// Ends the session, counting `time_spent` towards its source. Returns why it was evicted, if it was.
+    pub fn close(self, time_spent: SignedDuration) -> Option<EvictionReason> {
+        let mut registry = self
+            .sessions
+            .registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let session = registry.sessions.remove(&self.id)?;
+
+        if let Some(source) = registry.history.get_mut(&session.ip) {
+            source.trapped = source.trapped.saturating_add(time_spent);
+        }
+
+        session.evicted
+    }
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        self.sessions
+            .registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sessions
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::num::NonZeroU8;
+    use std::sync::Arc;
+
+    use pretty_assertions::{assert_eq, assert_matches};
+    use time::SignedDuration;
+
+    use crate::admission::{AdmissionPolicy, Evict, EvictionReason, HISTORY_CAPACITY, Sessions};
+
+    const FIRST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const SECOND: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn parses_admission_policy() {
+        for policy in [
+            AdmissionPolicy::Refuse,
+            AdmissionPolicy::EvictOldest,
+            AdmissionPolicy::EvictLowestValue,
+            AdmissionPolicy::ReserveNew {
+                percent: NonZeroU8::new(25).unwrap(),
+            },
+        ] {
+            assert_eq!(policy.to_string().parse(), Ok(policy));
+        }
+
+        assert_matches!("reserve-new:101".parse::<AdmissionPolicy>(), Err(_));
+        assert_matches!("reserve-new".parse::<AdmissionPolicy>(), Err(_));
+        assert_matches!("evict".parse::<AdmissionPolicy>(), Err(_));
+    }
+
+    #[test]
+    fn remembers_sources() {
+        let sessions = Arc::new(Sessions::default());
+
+        let (first, first_seen) = sessions.open(FIRST);
+        assert!(first_seen);
+
+        first.remember_source();
+
+        let (_second, first_seen) = sessions.open(FIRST);
+        assert!(!first_seen);
+
+        drop(first);
+
+        let (_third, first_seen) = sessions.open(SECOND);
+        assert!(first_seen);
+    }
+
+    #[test]
+    fn forgets_sources_turned_away() {
+        let sessions = Arc::new(Sessions::default());
+
+        let (refused, _) = sessions.open(FIRST);
+        drop(refused);
+
+        let (_again, first_seen) = sessions.open(FIRST);
+        assert!(first_seen);
+    }
+
+    #[test]
+    fn forgets_least_recently_trapped_sources() {
+        let sessions = Arc::new(Sessions::default());
+
+        // the highest address is trapped first, and again last
+        let (highest, _) = sessions.open(SECOND);
+        highest.remember_source();
+
+        let (lowest, _) = sessions.open(FIRST);
+        lowest.remember_source();
+
+        for index in 0..HISTORY_CAPACITY - 2 {
+            let ip = IpAddr::V6(Ipv6Addr::from(u128::try_from(index).unwrap() + 1));
+            let (session, _) = sessions.open(ip);
+
+            session.remember_source();
+        }
+
+        highest.remember_source();
+
+        let (newcomer, _) = sessions.open(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        newcomer.remember_source();
+
+        assert!(!sessions.open(SECOND).1);
+        assert!(sessions.open(FIRST).1);
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let sessions = Arc::new(Sessions::default());
+
+        let (oldest, _) = sessions.open(FIRST);
+        let (newest, _) = sessions.open(SECOND);
+        let (in_handshake, _) = sessions.open(SECOND);
+
+        oldest.evictable(Evict::Scheduled(1));
+        newest.evictable(Evict::Scheduled(2));
+
+        assert_matches!(
+            sessions.evict(EvictionReason::Oldest),
+            Some(Evict::Scheduled(1))
+        );
+        // it's on its way out
+        assert_matches!(
+            sessions.evict(EvictionReason::Oldest),
+            Some(Evict::Scheduled(2))
+        );
+        assert_matches!(sessions.evict(EvictionReason::Oldest), None);
+
+        assert_eq!(
+            oldest.close(SignedDuration::ZERO),
+            Some(EvictionReason::Oldest)
+        );
+        assert_eq!(in_handshake.close(SignedDuration::ZERO), None);
+    }
+
+    #[test]
+    fn evicts_repeat_offenders_first() {
+        let sessions = Arc::new(Sessions::default());
+
+        let (earlier, _) = sessions.open(SECOND);
+        earlier.remember_source();
+        earlier.close(SignedDuration::minutes(5));
+
+        let (first, _) = sessions.open(FIRST);
+        let (repeat_offender, _) = sessions.open(SECOND);
+
+        first.evictable(Evict::Scheduled(1));
+        repeat_offender.evictable(Evict::Scheduled(2));
+
+        assert_matches!(
+            sessions.evict(EvictionReason::LowestValue),
+            Some(Evict::Scheduled(2))
+        );
+    }
+}
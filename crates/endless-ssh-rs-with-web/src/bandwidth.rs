@@ -0,0 +1,319 @@
+use std::num::{NonZeroU64, NonZeroU128};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+/// Budgets are kept in billionths of a byte, so refilling every few milliseconds doesn't round away.
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// The budget holds at least a line, longer ones could never be sent.
+const MIN_BURST: u64 = 256;
+
+/// The rate is measured over windows this long.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// What the bandwidth limiter is up to, part of [`crate::scheduler::EngineStats`].
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct BandwidthStats {
+    /// Bytes per second, `null` when unlimited.
+    #[cfg_attr(test, ts(type = "number | null"))]
+    pub limit: Option<u64>,
+    /// Bytes per second sent to clients, over the last second, what isn't limited included.
+    #[cfg_attr(test, ts(type = "number"))]
+    pub bytes_per_second: u64,
+    /// `bytes_per_second`, in percent of `limit`, `null` when unlimited.
+    pub utilisation: Option<u8>,
+    /// Lines held back because the budget was spent, since we started.
+    #[cfg_attr(test, ts(type = "number"))]
+    pub deferred_lines: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// What may still be sent, in billionths of a byte.
+    budget: u128,
+    refilled_at: Instant,
+    window_started_at: Instant,
+    /// What was sent before the window started.
+    window_start_sent: u64,
+    /// Measured over the last full window.
+    bytes_per_second: u64,
+    deferred_lines: u64,
+}
+
+/// A token bucket every scheduler draws from before sending, so the tarpit's egress stays under `limit`.
+#[derive(Debug)]
+pub struct Bandwidth {
+    /// Bytes per second, unlimited when `None`.
+    limit: Option<NonZeroU64>,
+    /// Bytes sent since we started, counted without the lock, so unlimited sends never take it.
+    sent: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+/// Bytes taken from the budget for a send, what isn't sent goes back with [`Reservation::settle`].
+#[must_use]
+#[derive(Debug)]
+pub struct Reservation<'b> {
+    bandwidth: &'b Bandwidth,
+    bytes: usize,
+}
+
+impl Reservation<'_> {
+    /// `sent` of the reserved bytes went out.
+    pub fn settle(self, sent: usize) {
+        self.bandwidth.settle(self.bytes, sent);
+    }
+}
+
+fn capacity(limit: NonZeroU64) -> u128 {
+    u128::from(limit.get().max(MIN_BURST)) * NANOS_PER_SECOND
+}
+
+impl Bandwidth {
+    pub fn new(limit: Option<NonZeroU64>) -> Self {
+        let now = Instant::now();
+
+        Self {
+            limit,
+            sent: AtomicU64::new(0),
+            bucket: Mutex::new(Bucket {
+                budget: limit.map_or(0, capacity),
+                refilled_at: now,
+                window_started_at: now,
+                window_start_sent: 0,
+                bytes_per_second: 0,
+                deferred_lines: 0,
+            }),
+        }
+    }
+
+    /// Takes `bytes` from the budget, or tells how long until there are enough of them.
+    pub fn reserve(&self, bytes: usize) -> Result<Reservation<'_>, Duration> {
+        self.reserve_at(bytes, Instant::now())
+    }
+
+    fn reserve_at(&self, bytes: usize, now: Instant) -> Result<Reservation<'_>, Duration> {
+        let Some(limit) = self.limit else {
+            return Ok(Reservation {
+                bandwidth: self,
+                bytes,
+            });
+        };
+
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_nanos();
+
+        bucket.budget = bucket
+            .budget
+            .saturating_add(elapsed.saturating_mul(limit.get().into()))
+            .min(capacity(limit));
+        bucket.refilled_at = now;
+
+        let wanted = u128::try_from(bytes)
+            .unwrap_or(u128::MAX)
+            .saturating_mul(NANOS_PER_SECOND);
+
+        if let Some(left) = bucket.budget.checked_sub(wanted) {
+            bucket.budget = left;
+
+            Ok(Reservation {
+                bandwidth: self,
+                bytes,
+            })
+        } else {
+            bucket.deferred_lines += 1;
+
+            let wait = (wanted - bucket.budget).div_ceil(NonZeroU128::from(limit).get());
+
+            Err(Duration::from_nanos(
+                u64::try_from(wait).unwrap_or(u64::MAX),
+            ))
+        }
+    }
+
+    /// Counts bytes sent without a reservation, they show up in the rate but take nothing from the budget.
+    pub fn record(&self, sent: usize) {
+        self.sent
+            .fetch_add(u64::try_from(sent).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    fn settle(&self, reserved: usize, sent: usize) {
+        self.record(sent);
+
+        let Some(limit) = self.limit else {
+            return;
+        };
+
+        let unused = u128::try_from(reserved.saturating_sub(sent)).unwrap_or(u128::MAX);
+
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        bucket.budget = bucket
+            .budget
+            .saturating_add(unused.saturating_mul(NANOS_PER_SECOND))
+            .min(capacity(limit));
+    }
+
+    pub fn stats(&self) -> BandwidthStats {
+        self.stats_at(Instant::now())
+    }
+
+    fn stats_at(&self, now: Instant) -> BandwidthStats {
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        roll(&mut bucket, self.sent.load(Ordering::Relaxed), now);
+
+        let limit = self.limit.map(NonZeroU64::get);
+
+        BandwidthStats {
+            limit,
+            bytes_per_second: bucket.bytes_per_second,
+            utilisation: limit.map(|limit| {
+                u8::try_from((bucket.bytes_per_second.saturating_mul(100) / limit).min(100))
+                    .expect("capped at 100")
+            }),
+            deferred_lines: bucket.deferred_lines,
+        }
+    }
+}
+
+/// Starts a new window once the current one is over, measuring what was `sent` over it, however long it was.
+fn roll(bucket: &mut Bucket, sent: u64, now: Instant) {
+    let elapsed = now.saturating_duration_since(bucket.window_started_at);
+
+    if elapsed < WINDOW {
+        return;
+    }
+
+    let window_bytes = sent.saturating_sub(bucket.window_start_sent);
+    let elapsed = elapsed.as_nanos();
+
+    // rounded
+    bucket.bytes_per_second =
+        u64::try_from((u128::from(window_bytes) * NANOS_PER_SECOND + elapsed / 2) / elapsed)
+            .unwrap_or(u64::MAX);
+    bucket.window_started_at = now;
+    bucket.window_start_sent = sent;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+    use std::time::Duration;
+
+    use pretty_assertions::{assert_eq, assert_matches};
+    use tokio::time::Instant;
+
+    use crate::bandwidth::Bandwidth;
+
+    #[test]
+    fn defers_once_the_budget_is_spent() {
+        let bandwidth = Bandwidth::new(NonZeroU64::new(1000));
+
+        let now = Instant::now();
+
+        bandwidth.reserve_at(600, now).unwrap().settle(600);
+
+        // 400 left, 200 more take 200ms to come back
+        assert_eq!(
+            bandwidth.reserve_at(600, now).unwrap_err(),
+            Duration::from_millis(200)
+        );
+        assert_matches!(
+            bandwidth.reserve_at(600, now + Duration::from_millis(200)),
+            Ok(_)
+        );
+        assert_eq!(bandwidth.stats_at(now).deferred_lines, 1);
+    }
+
+    #[test]
+    fn counts_recorded_bytes_without_limiting_them() {
+        let bandwidth = Bandwidth::new(NonZeroU64::new(1000));
+
+        let now = Instant::now();
+
+        bandwidth.record(500);
+
+        assert_matches!(bandwidth.reserve_at(1000, now), Ok(_));
+        assert_eq!(
+            bandwidth
+                .stats_at(now + Duration::from_secs(1))
+                .bytes_per_second,
+            500
+        );
+    }
+
+    #[test]
+    fn gives_back_what_was_not_sent() {
+        let bandwidth = Bandwidth::new(NonZeroU64::new(1000));
+
+        let now = Instant::now();
+
+        bandwidth.reserve_at(1000, now).unwrap().settle(10);
+
+        assert_matches!(bandwidth.reserve_at(990, now), Ok(_));
+        assert_matches!(bandwidth.reserve_at(1, now), Err(_));
+    }
+
+    #[test]
+    fn measures_without_the_lock_when_unlimited() {
+        let bandwidth = Bandwidth::new(None);
+
+        let start = Instant::now();
+
+        // taking it again would deadlock
+        let bucket = bandwidth.bucket.lock().unwrap();
+
+        bandwidth.reserve_at(500, start).unwrap().settle(500);
+
+        drop(bucket);
+
+        assert_eq!(
+            bandwidth
+                .stats_at(start + Duration::from_secs(1))
+                .bytes_per_second,
+            500
+        );
+    }
+
+    #[test]
+    fn never_defers_when_unlimited() {
+        let bandwidth = Bandwidth::new(None);
+
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert_matches!(bandwidth.reserve_at(usize::MAX, now), Ok(_));
+        }
+
+        assert_eq!(bandwidth.stats_at(now).utilisation, None);
+    }
+
+    #[test]
+    fn measures_utilisation() {
+        let bandwidth = Bandwidth::new(NonZeroU64::new(1000));
+
+        let start = Instant::now();
+
+        bandwidth.settle(500, 500);
+
+        let stats = bandwidth.stats_at(start + Duration::from_secs(1));
+
+        assert_eq!(stats.bytes_per_second, 500);
+        assert_eq!(stats.utilisation, Some(50));
+
+        // nothing sent since
+        assert_eq!(
+            bandwidth
+                .stats_at(start + Duration::from_secs(3))
+                .bytes_per_second,
+            0
+        );
+    }
+}
@@ -1,18 +1,38 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::ffi::OsString;
-use std::net::SocketAddr;
-use std::num::NonZeroU8;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64};
+use std::path::PathBuf;
 use std::time::Duration;
 
 use clap::builder::TypedValueParser as _;
 use clap::error::ErrorKind;
-use clap::{ArgAction, Parser, value_parser};
+use clap::parser::ValueSource;
+use clap::{
+    ArgAction, ArgMatches, CommandFactory as _, FromArgMatches as _, Parser, ValueEnum as _,
+    value_parser,
+};
 use color_eyre::eyre;
+use ipnet::IpNet;
+use serde::Deserialize;
 
+use crate::access::AccessLists;
+use crate::admission::AdmissionPolicy;
 use crate::config::{
     Config, DEFAULT_DELAY_MS, DEFAULT_HTTP_LISTEN_ADDRESS, DEFAULT_MAX_CLIENTS,
-    DEFAULT_MAX_LINE_LENGTH, DEFAULT_SSH_LISTEN_ADDRESS,
+    DEFAULT_MAX_LINE_LENGTH, DEFAULT_PROTOCOL, DEFAULT_SSH_HOST_KEY, DEFAULT_SSH_LISTEN_ADDRESS,
+    ListenerConfig,
 };
+use crate::config_file::{self, ConfigFile};
+use crate::delay::DelayStrategy;
+use crate::line::generator::LineSource;
+use crate::privileges::Privileges;
+use crate::protocol::Protocol;
+use crate::sandbox::Sandbox;
+use crate::socket_options::{DEFAULT_RECEIVE_BUFFER, SocketOptions};
+use crate::source_limits::{DEFAULT_IPV6_SUBNET_PREFIX, ExcessAction, SourceLimits};
 
 fn delay_parser(value: &str) -> Result<Duration, clap::Error> {
     let timeout_ms = value
@@ -22,21 +42,268 @@ fn delay_parser(value: &str) -> Result<Duration, clap::Error> {
     Ok(Duration::from_millis(timeout_ms))
 }
 
+fn max_line_length_parser(value: &str) -> Result<NonZeroU8, clap::Error> {
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|length| *length >= 3)
+        .and_then(NonZeroU8::new)
+        .ok_or_else(|| clap::Error::new(ErrorKind::ValueValidation))
+}
+
+fn ipv6_subnet_prefix_parser(value: &str) -> Result<u8, clap::Error> {
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|prefix| matches!(prefix, 48 | 64))
+        .ok_or_else(|| clap::Error::new(ErrorKind::ValueValidation))
+}
+
+/// A network, or a single address.
+fn network_parser(value: &str) -> Result<IpNet, clap::Error> {
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| clap::Error::new(ErrorKind::ValueValidation))
+}
+
+/// A `--listener`, or a `[[listener]]` of the configuration file, every setting it doesn't specify falls back to the global one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ListenerSpec {
+    pub address: SocketAddr,
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "config_file::milliseconds")]
+    pub delay: Option<Duration>,
+    #[serde(default, deserialize_with = "config_file::parsed")]
+    pub delay_strategy: Option<DelayStrategy>,
+    pub max_clients: Option<NonZeroU32>,
+    pub max_clients_per_ip: Option<NonZeroU32>,
+    pub max_clients_per_subnet: Option<NonZeroU32>,
+    #[serde(default, deserialize_with = "config_file::ipv6_subnet_prefix")]
+    pub ipv6_subnet_prefix: Option<u8>,
+    #[serde(default, deserialize_with = "config_file::parsed")]
+    pub excess_action: Option<ExcessAction>,
+    #[serde(default, deserialize_with = "config_file::parsed")]
+    pub admission_policy: Option<AdmissionPolicy>,
+    /// In seconds, `0` means no limit.
+    pub max_duration: Option<u64>,
+    /// `0` means no limit.
+    pub max_bytes: Option<u64>,
+    #[serde(default, deserialize_with = "config_file::max_line_length")]
+    pub max_line_length: Option<NonZeroU8>,
+    #[serde(default, deserialize_with = "config_file::parsed")]
+    pub protocol: Option<Protocol>,
+    pub capture_client_ident: Option<bool>,
+    #[serde(default, deserialize_with = "config_file::parsed")]
+    pub line_generator: Option<LineSource>,
+    pub proxy_protocol: Option<bool>,
+    /// `0` means the kernel's default, as for the other sizes.
+    pub receive_buffer: Option<u32>,
+    pub send_buffer: Option<u32>,
+    pub max_segment_size: Option<u16>,
+    pub window_clamp: Option<u32>,
+    pub tcp_nodelay: Option<bool>,
+    pub ip_tos: Option<u8>,
+    pub listen_backlog: Option<u32>,
+    /// In seconds.
+    pub linger: Option<u64>,
+    pub tcp_keepalive: Option<bool>,
+    /// In seconds.
+    pub keepalive_idle: Option<u32>,
+    /// In seconds.
+    pub keepalive_interval: Option<u32>,
+    pub keepalive_count: Option<u8>,
+    /// In milliseconds.
+    pub tcp_user_timeout: Option<u32>,
+}
+
+/// Every setting falls back to the global one.
+impl Default for ListenerSpec {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_SSH_LISTEN_ADDRESS,
+            name: None,
+            delay: None,
+            delay_strategy: None,
+            max_clients: None,
+            max_clients_per_ip: None,
+            max_clients_per_subnet: None,
+            ipv6_subnet_prefix: None,
+            excess_action: None,
+            admission_policy: None,
+            max_duration: None,
+            max_bytes: None,
+            max_line_length: None,
+            protocol: None,
+            capture_client_ident: None,
+            line_generator: None,
+            proxy_protocol: None,
+            receive_buffer: None,
+            send_buffer: None,
+            max_segment_size: None,
+            window_clamp: None,
+            tcp_nodelay: None,
+            ip_tos: None,
+            listen_backlog: None,
+            linger: None,
+            tcp_keepalive: None,
+            keepalive_idle: None,
+            keepalive_interval: None,
+            keepalive_count: None,
+            tcp_user_timeout: None,
+        }
+    }
+}
+
+/// Parses `ADDRESS[,key=value]...`, e.g. `[::]:22,protocol=ssh,delay=5000`.
+#[expect(clippy::too_many_lines, reason = "One arm per setting")]
+fn listener_parser(value: &str) -> Result<ListenerSpec, clap::Error> {
+    fn invalid(message: String) -> clap::Error {
+        clap::Error::raw(ErrorKind::ValueValidation, message)
+    }
+
+    let mut parts = value.split(',');
+
+    let address = parts.next().unwrap_or_default();
+
+    let mut spec = ListenerSpec {
+        address: address
+            .parse()
+            .map_err(|_| invalid(format!("Invalid listener address `{}`", address)))?,
+        ..ListenerSpec::default()
+    };
+
+    for part in parts {
+        let Some((key, value)) = part.split_once('=') else {
+            return Err(invalid(format!(
+                "Listener setting `{}` is not a `key=value` pair",
+                part
+            )));
+        };
+
+        let invalid_value = || invalid(format!("Invalid value `{}` for listener `{}`", value, key));
+
+        match key {
+            "name" => spec.name = Some(value.to_owned()),
+            "delay" => spec.delay = Some(delay_parser(value).map_err(|_| invalid_value())?),
+            "delay-strategy" => {
+                spec.delay_strategy = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-clients" => {
+                spec.max_clients = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-clients-per-ip" => {
+                spec.max_clients_per_ip = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-clients-per-subnet" => {
+                spec.max_clients_per_subnet = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "ipv6-subnet-prefix" => {
+                spec.ipv6_subnet_prefix =
+                    Some(ipv6_subnet_prefix_parser(value).map_err(|_| invalid_value())?);
+            },
+            "excess-action" => {
+                spec.excess_action = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "admission-policy" => {
+                spec.admission_policy = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-duration" => {
+                spec.max_duration = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-bytes" => {
+                spec.max_bytes = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-line-length" => {
+                spec.max_line_length =
+                    Some(max_line_length_parser(value).map_err(|_| invalid_value())?);
+            },
+            "protocol" => {
+                spec.protocol =
+                    Some(Protocol::from_str(value, false).map_err(|_| invalid_value())?);
+            },
+            "capture-client-ident" => {
+                spec.capture_client_ident = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "line-generator" => {
+                spec.line_generator = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "proxy-protocol" => {
+                spec.proxy_protocol = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "receive-buffer" => {
+                spec.receive_buffer = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "send-buffer" => {
+                spec.send_buffer = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "max-segment-size" => {
+                spec.max_segment_size = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "window-clamp" => {
+                spec.window_clamp = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "tcp-nodelay" => {
+                spec.tcp_nodelay = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "ip-tos" => {
+                spec.ip_tos = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "listen-backlog" => {
+                spec.listen_backlog = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "linger" => {
+                spec.linger = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "tcp-keepalive" => {
+                spec.tcp_keepalive = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "keepalive-idle" => {
+                spec.keepalive_idle = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "keepalive-interval" => {
+                spec.keepalive_interval = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "keepalive-count" => {
+                spec.keepalive_count = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            "tcp-user-timeout" => {
+                spec.tcp_user_timeout = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            _ => return Err(invalid(format!("Unknown listener setting `{}`", key))),
+        }
+    }
+
+    Ok(spec)
+}
+
 #[derive(Debug, Parser)]
 #[command(disable_help_flag = true)]
+#[expect(clippy::struct_excessive_bools, reason = "Those are switches")]
 pub struct Cli {
     #[clap(
         short = 'd',
         long = "delay",
+        env,
         default_value = DEFAULT_DELAY_MS.to_string(),
         help = "Message millisecond delay",
         value_parser = delay_parser
     )]
     delay: Duration,
 
+    #[clap(
+        long,
+        env,
+        default_value = "fixed",
+        help = "How the delay varies, `fixed`, `jitter:SPREAD`, `exponential`, `pareto:SHAPE` or `backoff:FACTOR`"
+    )]
+    delay_strategy: DelayStrategy,
+
     #[clap(
         short = 'l',
         long = "max-line-length",
+        env,
         default_value_t = DEFAULT_MAX_LINE_LENGTH,
         help = "Maximum banner line length (3-255)",
         value_parser = value_parser!(u8).range(3..=255).try_map(NonZeroU8::try_from)
@@ -46,19 +313,270 @@ pub struct Cli {
     #[clap(
         short = 'm',
         long = "max-clients",
+        env,
         default_value_t = DEFAULT_MAX_CLIENTS,
         help = "Maximum number of clients"
     )]
-    max_clients: NonZeroU8,
+    max_clients: NonZeroU32,
+
+    #[clap(
+        long,
+        env,
+        help = "Maximum number of clients from a single address, unlimited when unset"
+    )]
+    max_clients_per_ip: Option<NonZeroU32>,
+
+    #[clap(
+        long,
+        env,
+        help = "Maximum number of clients from a single IPv4 /24 or IPv6 subnet, unlimited when unset"
+    )]
+    max_clients_per_subnet: Option<NonZeroU32>,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = DEFAULT_IPV6_SUBNET_PREFIX,
+        help = "Prefix length IPv6 clients are grouped by, 48 or 64",
+        value_parser = ipv6_subnet_prefix_parser
+    )]
+    ipv6_subnet_prefix: u8,
+
+    #[clap(
+        long,
+        env,
+        default_value = "close",
+        help = "What to do with clients over a per address or per subnet limit, `close`, `reset` or `tarpit`"
+    )]
+    excess_action: ExcessAction,
+
+    #[clap(
+        long,
+        env,
+        default_value = "refuse",
+        help = "What happens to new clients when all slots are taken, `refuse`, `evict-oldest`, `evict-lowest-value` or `reserve-new:PERCENT`"
+    )]
+    admission_policy: AdmissionPolicy,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Seconds a client stays trapped before it's let go, 0 for no limit"
+    )]
+    max_duration: u64,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Bytes a client is sent before it's let go, 0 for no limit"
+    )]
+    max_bytes: u64,
+
+    #[clap(
+        short = 'p',
+        long,
+        env,
+        value_enum,
+        default_value_t = DEFAULT_PROTOCOL,
+        help = "Tarpit protocol"
+    )]
+    protocol: Protocol,
+
+    #[clap(
+        long,
+        env,
+        action = ArgAction::SetTrue,
+        help = "Read and store the identification line of SSH clients"
+    )]
+    capture_client_ident: bool,
+
+    #[clap(
+        long,
+        env,
+        default_value = "random",
+        help = "Where SSH banner lines come from, `random`, `corpus:PATH` or `markov:PATH`"
+    )]
+    line_generator: LineSource,
+
+    #[clap(
+        long,
+        env,
+        action = ArgAction::SetTrue,
+        help = "Take the client's address from the PROXY protocol v1 or v2 header of connections from trusted proxies"
+    )]
+    proxy_protocol: bool,
+
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Networks or addresses of the proxies whose PROXY headers are trusted, comma-separated",
+        value_parser = network_parser
+    )]
+    trusted_proxies: Vec<IpNet>,
+
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Networks or addresses that are trapped, but left out of the dashboard and the statistics, comma-separated",
+        value_parser = network_parser
+    )]
+    allowlist: Vec<IpNet>,
+
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Networks or addresses whose connections are closed right away, comma-separated",
+        value_parser = network_parser
+    )]
+    denylist: Vec<IpNet>,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = DEFAULT_RECEIVE_BUFFER.get(),
+        help = "Receive buffer of the tarpit sockets, in bytes, 0 for the kernel's default"
+    )]
+    receive_buffer: u32,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Send buffer of the tarpit sockets, in bytes, 0 for the kernel's default"
+    )]
+    send_buffer: u32,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Largest TCP segment the tarpit accepts, in bytes, 0 for the kernel's default"
+    )]
+    max_segment_size: u16,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Largest TCP window the tarpit advertises, in bytes, 0 for the kernel's default"
+    )]
+    window_clamp: u32,
+
+    #[clap(
+        long,
+        env,
+        action = ArgAction::SetTrue,
+        help = "Disable Nagle's algorithm on the tarpit sockets"
+    )]
+    tcp_nodelay: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "IP type of service, or IPv6 traffic class, of the tarpit's packets"
+    )]
+    ip_tos: Option<u8>,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Connections waiting to be accepted by a listener, 0 for the default"
+    )]
+    listen_backlog: u32,
+
+    #[clap(
+        long,
+        env,
+        help = "Seconds closing a tarpit connection waits for unsent data, 0 resets it instead"
+    )]
+    linger: Option<u64>,
+
+    #[clap(
+        long,
+        env,
+        action = ArgAction::SetTrue,
+        help = "Probe idle tarpit connections with TCP keepalives, to drop clients that vanished without a FIN"
+    )]
+    tcp_keepalive: bool,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Seconds a tarpit connection is idle before the first keepalive probe, 0 for the kernel's default, implies --tcp-keepalive"
+    )]
+    keepalive_idle: u32,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Seconds between keepalive probes, 0 for the kernel's default, implies --tcp-keepalive"
+    )]
+    keepalive_interval: u32,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Unanswered keepalive probes before a client times out, 0 for the kernel's default, implies --tcp-keepalive"
+    )]
+    keepalive_count: u8,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Milliseconds sent data may go unacknowledged before a client times out, 0 for the kernel's default"
+    )]
+    tcp_user_timeout: u32,
 
     #[clap(
         long,
         env,
         default_value_t = DEFAULT_SSH_LISTEN_ADDRESS,
-        help = "SSH listen address"
+        help = "SSH listen address, used when no listeners are declared"
     )]
     ssh_listen_address: SocketAddr,
 
+    #[clap(
+        long = "listener",
+        env = "LISTENERS",
+        value_delimiter = ' ',
+        help = "Tarpit listener, as `ADDRESS[,name=..][,protocol=..][,delay=..][,delay-strategy=..][,max-line-length=..][,max-clients=..][,max-clients-per-ip=..][,max-clients-per-subnet=..][,ipv6-subnet-prefix=..][,excess-action=..][,admission-policy=..][,max-duration=..][,max-bytes=..][,capture-client-ident=..][,line-generator=..][,proxy-protocol=..][,receive-buffer=..][,send-buffer=..][,max-segment-size=..][,window-clamp=..][,tcp-nodelay=..][,ip-tos=..][,listen-backlog=..][,linger=..][,tcp-keepalive=..][,keepalive-idle=..][,keepalive-interval=..][,keepalive-count=..][,tcp-user-timeout=..]`, repeatable",
+        value_parser = listener_parser
+    )]
+    listeners: Vec<ListenerSpec>,
+
+    #[clap(
+        long,
+        env,
+        default_value = DEFAULT_SSH_HOST_KEY,
+        help = "Host key of the SSH honeypot, generated when it doesn't exist"
+    )]
+    ssh_host_key: PathBuf,
+
+    #[clap(
+        long,
+        env,
+        help = "Number of tasks driving the trapped clients, one per CPU by default"
+    )]
+    schedulers: Option<NonZeroU16>,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = 0,
+        help = "Bytes per second all trapped clients get together, lines wait when it's used up, 0 for no limit"
+    )]
+    max_bandwidth: u64,
+
     #[clap(
         long,
         env,
@@ -67,6 +585,68 @@ pub struct Cli {
     )]
     http_listen_address: SocketAddr,
 
+    #[clap(
+        long,
+        env,
+        help = "Vite dev server the dashboard is proxied to, instead of serving `dist`"
+    )]
+    front_end_proxy: Option<String>,
+
+    #[clap(long, env, hide_env_values = true, help = "PostgreSQL connection URL")]
+    database_url: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        hide_env_values = true,
+        help = "MaxMind license key to download the GeoLite2 database with, GeoIP lookup is disabled without it"
+    )]
+    maxmind_license_key: Option<String>,
+
+    #[clap(
+        long,
+        env = "RUN_AS_USER",
+        help = "User to switch to once the sockets are bound, e.g. to bind port 22 as root"
+    )]
+    user: Option<String>,
+
+    #[clap(
+        long,
+        env = "RUN_AS_GROUP",
+        help = "Group to switch to along with `--user`, the user's primary group by default"
+    )]
+    group: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Directory to chroot to before switching to `--user`, holding `dist` and the GeoIP database"
+    )]
+    chroot: Option<PathBuf>,
+
+    #[clap(
+        long,
+        env,
+        default_value_t = Sandbox::Off,
+        help = "Lock the daemon down once started, `off`, `enforce` or `audit` to only log what would be killed"
+    )]
+    sandbox: Sandbox,
+
+    #[clap(
+        short = 'c',
+        long,
+        env = "CONFIG_FILE",
+        help = "TOML configuration file, overridden by the environment and the command line"
+    )]
+    config: Option<PathBuf>,
+
+    #[clap(
+        long,
+        action = ArgAction::SetTrue,
+        help = "Print the effective configuration, as TOML, and exit"
+    )]
+    print_config: bool,
+
     #[clap(
         short = 'h',
         long = "help",
@@ -77,17 +657,341 @@ pub struct Cli {
 }
 
 impl From<Cli> for Config {
-    fn from(matches: Cli) -> Self {
+    fn from(mut matches: Cli) -> Self {
+        let specs = if matches.listeners.is_empty() {
+            vec![ListenerSpec {
+                address: matches.ssh_listen_address,
+                ..ListenerSpec::default()
+            }]
+        } else {
+            mem::take(&mut matches.listeners)
+        };
+
+        let listeners = specs
+            .into_iter()
+            .map(|spec| {
+                let socket_options = matches.socket_options(&spec);
+
+                ListenerConfig {
+                    name: spec.name.unwrap_or_else(|| spec.address.to_string()),
+                    address: spec.address,
+                    delay: spec.delay.unwrap_or(matches.delay),
+                    delay_strategy: spec.delay_strategy.unwrap_or(matches.delay_strategy),
+                    max_clients: spec.max_clients.unwrap_or(matches.max_clients),
+                    source_limits: SourceLimits {
+                        per_ip: spec.max_clients_per_ip.or(matches.max_clients_per_ip),
+                        per_subnet: spec
+                            .max_clients_per_subnet
+                            .or(matches.max_clients_per_subnet),
+                        ipv6_prefix: spec
+                            .ipv6_subnet_prefix
+                            .unwrap_or(matches.ipv6_subnet_prefix),
+                        action: spec.excess_action.unwrap_or(matches.excess_action),
+                    },
+                    admission_policy: spec.admission_policy.unwrap_or(matches.admission_policy),
+                    max_duration: NonZeroU64::new(
+                        spec.max_duration.unwrap_or(matches.max_duration),
+                    )
+                    .map(|max_duration| Duration::from_secs(max_duration.get())),
+                    max_bytes: NonZeroU64::new(spec.max_bytes.unwrap_or(matches.max_bytes)),
+                    max_line_length: spec.max_line_length.unwrap_or(matches.max_line_length),
+                    protocol: spec.protocol.unwrap_or(matches.protocol),
+                    capture_client_ident: spec
+                        .capture_client_ident
+                        .unwrap_or(matches.capture_client_ident),
+                    line_generator: spec
+                        .line_generator
+                        .unwrap_or_else(|| matches.line_generator.clone()),
+                    proxy_protocol: spec.proxy_protocol.unwrap_or(matches.proxy_protocol),
+                    trusted_proxies: matches.trusted_proxies.clone(),
+                    access: AccessLists {
+                        allow: matches.allowlist.clone(),
+                        deny: matches.denylist.clone(),
+                    },
+                    socket_options,
+                }
+            })
+            .collect();
+
         Config {
-            delay: matches.delay,
             http_listen_address: matches.http_listen_address,
-            max_clients: matches.max_clients,
-            max_line_length: matches.max_line_length,
-            ssh_listen_address: matches.ssh_listen_address,
+            listeners,
+            ssh_host_key: matches.ssh_host_key,
+            schedulers: matches.schedulers,
+            max_bandwidth: NonZeroU64::new(matches.max_bandwidth),
+            front_end_proxy: matches.front_end_proxy,
+            database_url: matches.database_url,
+            maxmind_license_key: matches.maxmind_license_key,
+            privileges: matches.user.map(|user| Privileges {
+                user,
+                group: matches.group,
+                chroot: matches.chroot,
+            }),
+            sandbox: matches.sandbox,
+            file: matches.config,
+            print_and_exit: matches.print_config,
         }
     }
 }
 
+/// Sets `value` to `from_file`, unless `id` was given on the command line or in the environment.
+fn layer<T>(value: &mut T, from_file: Option<T>, matches: &ArgMatches, id: &str) {
+    let overridden = matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    );
+
+    if let Some(from_file) = from_file
+        && !overridden
+    {
+        *value = from_file;
+    }
+}
+
+impl Cli {
+    /// The socket options of the listener `spec` declares, its own or else the global ones.
+    fn socket_options(&self, spec: &ListenerSpec) -> SocketOptions {
+        // any of these means keepalive is wanted
+        let keepalive_idle = NonZeroU32::new(spec.keepalive_idle.unwrap_or(self.keepalive_idle))
+            .map(|idle| Duration::from_secs(idle.get().into()));
+        let keepalive_interval =
+            NonZeroU32::new(spec.keepalive_interval.unwrap_or(self.keepalive_interval))
+                .map(|interval| Duration::from_secs(interval.get().into()));
+        let keepalive_count = NonZeroU8::new(spec.keepalive_count.unwrap_or(self.keepalive_count));
+
+        SocketOptions {
+            receive_buffer: NonZeroU32::new(spec.receive_buffer.unwrap_or(self.receive_buffer)),
+            send_buffer: NonZeroU32::new(spec.send_buffer.unwrap_or(self.send_buffer)),
+            max_segment_size: NonZeroU16::new(
+                spec.max_segment_size.unwrap_or(self.max_segment_size),
+            ),
+            window_clamp: NonZeroU32::new(spec.window_clamp.unwrap_or(self.window_clamp)),
+            nodelay: spec.tcp_nodelay.unwrap_or(self.tcp_nodelay),
+            tos: spec.ip_tos.or(self.ip_tos),
+            backlog: NonZeroU32::new(spec.listen_backlog.unwrap_or(self.listen_backlog)),
+            linger: spec.linger.or(self.linger).map(Duration::from_secs),
+            keepalive: spec.tcp_keepalive.unwrap_or(self.tcp_keepalive)
+                || keepalive_idle.is_some()
+                || keepalive_interval.is_some()
+                || keepalive_count.is_some(),
+            keepalive_idle,
+            keepalive_interval,
+            keepalive_count,
+            user_timeout: NonZeroU32::new(spec.tcp_user_timeout.unwrap_or(self.tcp_user_timeout))
+                .map(|timeout| Duration::from_millis(timeout.get().into())),
+        }
+    }
+
+    /// Layers `file` beneath the command line and the environment.
+    #[expect(clippy::too_many_lines, reason = "One line per setting")]
+    fn merge(&mut self, file: ConfigFile, matches: &ArgMatches) {
+        layer(&mut self.delay, file.delay, matches, "delay");
+        layer(
+            &mut self.delay_strategy,
+            file.delay_strategy,
+            matches,
+            "delay_strategy",
+        );
+        layer(
+            &mut self.max_line_length,
+            file.max_line_length,
+            matches,
+            "max_line_length",
+        );
+        layer(
+            &mut self.max_clients,
+            file.max_clients,
+            matches,
+            "max_clients",
+        );
+        layer(
+            &mut self.max_clients_per_ip,
+            file.max_clients_per_ip.map(Some),
+            matches,
+            "max_clients_per_ip",
+        );
+        layer(
+            &mut self.max_clients_per_subnet,
+            file.max_clients_per_subnet.map(Some),
+            matches,
+            "max_clients_per_subnet",
+        );
+        layer(
+            &mut self.ipv6_subnet_prefix,
+            file.ipv6_subnet_prefix,
+            matches,
+            "ipv6_subnet_prefix",
+        );
+        layer(
+            &mut self.excess_action,
+            file.excess_action,
+            matches,
+            "excess_action",
+        );
+        layer(
+            &mut self.admission_policy,
+            file.admission_policy,
+            matches,
+            "admission_policy",
+        );
+        layer(
+            &mut self.max_duration,
+            file.max_duration,
+            matches,
+            "max_duration",
+        );
+        layer(&mut self.max_bytes, file.max_bytes, matches, "max_bytes");
+        layer(&mut self.protocol, file.protocol, matches, "protocol");
+        layer(
+            &mut self.capture_client_ident,
+            file.capture_client_ident,
+            matches,
+            "capture_client_ident",
+        );
+        layer(
+            &mut self.line_generator,
+            file.line_generator,
+            matches,
+            "line_generator",
+        );
+        layer(
+            &mut self.proxy_protocol,
+            file.proxy_protocol,
+            matches,
+            "proxy_protocol",
+        );
+        layer(
+            &mut self.trusted_proxies,
+            file.trusted_proxies,
+            matches,
+            "trusted_proxies",
+        );
+        layer(&mut self.allowlist, file.allowlist, matches, "allowlist");
+        layer(&mut self.denylist, file.denylist, matches, "denylist");
+        layer(
+            &mut self.receive_buffer,
+            file.receive_buffer,
+            matches,
+            "receive_buffer",
+        );
+        layer(
+            &mut self.send_buffer,
+            file.send_buffer,
+            matches,
+            "send_buffer",
+        );
+        layer(
+            &mut self.max_segment_size,
+            file.max_segment_size,
+            matches,
+            "max_segment_size",
+        );
+        layer(
+            &mut self.window_clamp,
+            file.window_clamp,
+            matches,
+            "window_clamp",
+        );
+        layer(
+            &mut self.tcp_nodelay,
+            file.tcp_nodelay,
+            matches,
+            "tcp_nodelay",
+        );
+        layer(&mut self.ip_tos, file.ip_tos.map(Some), matches, "ip_tos");
+        layer(
+            &mut self.listen_backlog,
+            file.listen_backlog,
+            matches,
+            "listen_backlog",
+        );
+        layer(&mut self.linger, file.linger.map(Some), matches, "linger");
+        layer(
+            &mut self.tcp_keepalive,
+            file.tcp_keepalive,
+            matches,
+            "tcp_keepalive",
+        );
+        layer(
+            &mut self.keepalive_idle,
+            file.keepalive_idle,
+            matches,
+            "keepalive_idle",
+        );
+        layer(
+            &mut self.keepalive_interval,
+            file.keepalive_interval,
+            matches,
+            "keepalive_interval",
+        );
+        layer(
+            &mut self.keepalive_count,
+            file.keepalive_count,
+            matches,
+            "keepalive_count",
+        );
+        layer(
+            &mut self.tcp_user_timeout,
+            file.tcp_user_timeout,
+            matches,
+            "tcp_user_timeout",
+        );
+        layer(
+            &mut self.ssh_listen_address,
+            file.ssh_listen_address,
+            matches,
+            "ssh_listen_address",
+        );
+        layer(&mut self.listeners, file.listeners, matches, "listeners");
+        layer(
+            &mut self.ssh_host_key,
+            file.ssh_host_key,
+            matches,
+            "ssh_host_key",
+        );
+        layer(
+            &mut self.schedulers,
+            file.schedulers.map(Some),
+            matches,
+            "schedulers",
+        );
+        layer(
+            &mut self.max_bandwidth,
+            file.max_bandwidth,
+            matches,
+            "max_bandwidth",
+        );
+        layer(
+            &mut self.http_listen_address,
+            file.http_listen_address,
+            matches,
+            "http_listen_address",
+        );
+        layer(
+            &mut self.front_end_proxy,
+            file.front_end_proxy.map(Some),
+            matches,
+            "front_end_proxy",
+        );
+        layer(
+            &mut self.database_url,
+            file.database_url.map(Some),
+            matches,
+            "database_url",
+        );
+        layer(
+            &mut self.maxmind_license_key,
+            file.maxmind_license_key.map(Some),
+            matches,
+            "maxmind_license_key",
+        );
+        layer(&mut self.user, file.user.map(Some), matches, "user");
+        layer(&mut self.group, file.group.map(Some), matches, "group");
+        layer(&mut self.chroot, file.chroot.map(Some), matches, "chroot");
+        layer(&mut self.sandbox, file.sandbox, matches, "sandbox");
+    }
+}
+
 pub fn parse_cli() -> Result<Config, eyre::Error> {
     parse_cli_from(env::args_os())
 }
@@ -97,19 +1001,63 @@ where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
-    Ok(Cli::try_parse_from(from)?.into())
+    let matches = Cli::command().try_get_matches_from(from)?;
+    let mut cli = Cli::from_arg_matches(&matches)?;
+
+    if let Some(path) = cli.config.as_deref() {
+        let file = ConfigFile::load(path)?;
+
+        cli.merge(file, &matches);
+    }
+
+    // nobody to switch to
+    if cli.user.is_none() && (cli.group.is_some() || cli.chroot.is_some()) {
+        return Err(eyre::eyre!("`--group` and `--chroot` need `--user`"));
+    }
+
+    let config: Config = cli.into();
+
+    // the name is what ties a stored connection to its listener
+    let mut names = BTreeSet::new();
+
+    for listener in &config.listeners {
+        if !names.insert(listener.name.as_str()) {
+            return Err(eyre::eyre!("Duplicate listener name `{}`", listener.name));
+        }
+
+        // nobody could tell us who the client is
+        if listener.proxy_protocol && listener.trusted_proxies.is_empty() {
+            return Err(eyre::eyre!(
+                "Listener `{}` takes PROXY headers, but no trusted proxies are configured",
+                listener.name
+            ));
+        }
+    }
+
+    Ok(config)
 }
 
 #[cfg(test)]
 mod tests {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-    use std::num::NonZeroU8;
+    use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64};
+    use std::path::PathBuf;
+    use std::time::Duration;
 
     use color_eyre::eyre;
     use pretty_assertions::{assert_eq, assert_matches};
 
     use super::parse_cli_from;
-    use crate::config::Config;
+    use crate::access::AccessLists;
+    use crate::admission::AdmissionPolicy;
+    use crate::config::{Config, DEFAULT_SSH_LISTEN_ADDRESS, ListenerConfig};
+    use crate::delay::DelayStrategy;
+    use crate::line::generator::LineSource;
+    use crate::privileges::Privileges;
+    use crate::protocol::Protocol;
+    use crate::sandbox::Sandbox;
+    use crate::socket_options::SocketOptions;
+    use crate::source_limits::{ExcessAction, SourceLimits};
 
     fn parse_factory(input: &'static str) -> Result<Config, eyre::Report> {
         // fake input
@@ -118,6 +1066,13 @@ mod tests {
         parse_cli_from(command_line)
     }
 
+    fn with_listener(listener: ListenerConfig) -> Config {
+        Config {
+            listeners: vec![listener],
+            ..Config::default()
+        }
+    }
+
     #[test]
     fn bad_cli_options_1() {
         let result = parse_factory("foo bar");
@@ -138,10 +1093,10 @@ mod tests {
     fn parses_delay() {
         let result = parse_factory("endless-ssh-rs --delay 100");
 
-        let expected_config = Config {
+        let expected_config = with_listener(ListenerConfig {
             delay: std::time::Duration::from_millis(100),
-            ..Config::default()
-        };
+            ..ListenerConfig::default()
+        });
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), expected_config);
@@ -151,23 +1106,38 @@ mod tests {
     fn parses_max_clients() {
         let result = parse_factory("endless-ssh-rs --max-clients 50");
 
-        let expected_config = Config {
-            max_clients: NonZeroU8::new(50).unwrap(),
-            ..Config::default()
-        };
+        let expected_config = with_listener(ListenerConfig {
+            max_clients: NonZeroU32::new(50).unwrap(),
+            ..ListenerConfig::default()
+        });
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), expected_config);
     }
 
+    #[test]
+    fn parses_max_clients_beyond_255() {
+        let result = parse_factory("endless-ssh-rs --max-clients 50000 --schedulers 2");
+
+        let expected_config = Config {
+            schedulers: NonZeroU16::new(2),
+            ..with_listener(ListenerConfig {
+                max_clients: NonZeroU32::new(50000).unwrap(),
+                ..ListenerConfig::default()
+            })
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
     #[test]
     fn parses_max_line_length() {
         let result = parse_factory("endless-ssh-rs --max-line-length 70");
 
-        let expected_config = Config {
+        let expected_config = with_listener(ListenerConfig {
             max_line_length: NonZeroU8::new(70).unwrap(),
-            ..Config::default()
-        };
+            ..ListenerConfig::default()
+        });
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), expected_config);
@@ -188,14 +1158,33 @@ mod tests {
         assert_matches!(result, Err(_));
     }
 
+    #[test]
+    fn parses_protocol() {
+        let result = parse_factory("endless-ssh-rs --protocol http");
+
+        let expected_config = with_listener(ListenerConfig {
+            protocol: Protocol::Http,
+            ..ListenerConfig::default()
+        });
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn rejects_unknown_protocol() {
+        let result = parse_factory("endless-ssh-rs --protocol gopher");
+
+        assert_matches!(result, Err(_));
+    }
+
     #[test]
     fn parses_ssh_listen_address() {
         let result = parse_factory("endless-ssh-rs --ssh-listen-address 127.0.0.1:2000");
 
-        let expected_config = Config {
-            ssh_listen_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 2000),
-            ..Config::default()
-        };
+        let expected_config = with_listener(ListenerConfig::new(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            2000,
+        )));
 
         assert_matches!(result, Ok(config) if config == expected_config);
     }
@@ -227,7 +1216,396 @@ mod tests {
 
         let expected_ssh = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2223);
 
-        assert_matches!(result, Ok(config) if config.ssh_listen_address == expected_ssh);
+        assert_matches!(result, Ok(config) if config.listeners == [ListenerConfig::new(expected_ssh)]);
+    }
+
+    #[test]
+    fn parses_listeners() {
+        let result = parse_factory(
+            "endless-ssh-rs --delay 500 --listener [::]:22,protocol=ssh --listener 0.0.0.0:80,name=web,protocol=http,delay=100,max-line-length=64,max-clients=8",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    delay: std::time::Duration::from_millis(500),
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    name: "web".to_owned(),
+                    delay: std::time::Duration::from_millis(100),
+                    max_clients: NonZeroU32::new(8).unwrap(),
+                    max_line_length: NonZeroU8::new(64).unwrap(),
+                    protocol: Protocol::Http,
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 80))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_capture_client_ident() {
+        let result = parse_factory(
+            "endless-ssh-rs --capture-client-ident --listener [::]:22 --listener [::]:2222,capture-client-ident=false",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    capture_client_ident: true,
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222)),
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_delay_strategy() {
+        let result = parse_factory(
+            "endless-ssh-rs --delay-strategy pareto:1.5 --listener [::]:22 --listener [::]:2222,delay-strategy=jitter:0.2",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    delay_strategy: DelayStrategy::Pareto { shape: 1.5 },
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    delay_strategy: DelayStrategy::Jitter { spread: 0.2 },
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_line_generator() {
+        let result = parse_factory(
+            "endless-ssh-rs --line-generator corpus:/etc/motd --listener [::]:22 --listener [::]:2222,line-generator=markov:notices.txt",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    line_generator: LineSource::Corpus("/etc/motd".into()),
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    line_generator: LineSource::Markov("notices.txt".into()),
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_source_limits() {
+        let result = parse_factory(
+            "endless-ssh-rs --max-clients-per-ip 2 --excess-action reset --listener [::]:22 --listener [::]:2222,max-clients-per-ip=3,max-clients-per-subnet=8,ipv6-subnet-prefix=48,excess-action=tarpit",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    source_limits: SourceLimits {
+                        per_ip: NonZeroU32::new(2),
+                        action: ExcessAction::Reset,
+                        ..SourceLimits::default()
+                    },
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    source_limits: SourceLimits {
+                        per_ip: NonZeroU32::new(3),
+                        per_subnet: NonZeroU32::new(8),
+                        ipv6_prefix: 48,
+                        action: ExcessAction::Tarpit,
+                    },
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn rejects_zero_source_limits() {
+        let result = parse_factory("endless-ssh-rs --max-clients-per-ip 0");
+
+        assert_matches!(result, Err(_));
+
+        let result = parse_factory("endless-ssh-rs --listener [::]:2222,max-clients-per-subnet=0");
+
+        assert_matches!(result, Err(_));
+    }
+
+    #[test]
+    fn rejects_invalid_ipv6_subnet_prefix() {
+        let result = parse_factory("endless-ssh-rs --ipv6-subnet-prefix 56");
+
+        assert_matches!(result, Err(_));
+    }
+
+    #[test]
+    fn parses_admission_policy() {
+        let result = parse_factory(
+            "endless-ssh-rs --admission-policy evict-oldest --listener [::]:22 --listener [::]:2222,admission-policy=reserve-new:25",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    admission_policy: AdmissionPolicy::EvictOldest,
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    admission_policy: AdmissionPolicy::ReserveNew {
+                        percent: NonZeroU8::new(25).unwrap(),
+                    },
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_session_limits() {
+        let result = parse_factory(
+            "endless-ssh-rs --max-duration 3600 --listener [::]:22 --listener [::]:2222,max-duration=0,max-bytes=4096",
+        );
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    max_duration: Some(Duration::from_secs(3600)),
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    max_bytes: NonZeroU64::new(4096),
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_socket_options() {
+        let result = parse_factory(
+            "endless-ssh-rs --send-buffer 4096 --tcp-nodelay --listener [::]:22,receive-buffer=0,ip-tos=16,linger=0",
+        );
+
+        let expected_config = with_listener(ListenerConfig {
+            socket_options: SocketOptions {
+                receive_buffer: None,
+                send_buffer: NonZeroU32::new(4096),
+                nodelay: true,
+                tos: Some(16),
+                linger: Some(Duration::ZERO),
+                ..SocketOptions::default()
+            },
+            ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+        });
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn keepalive_settings_imply_keepalive() {
+        let result = parse_factory(
+            "endless-ssh-rs --keepalive-idle 60 --tcp-user-timeout 30000 --listener [::]:22 --listener [::]:23,keepalive-idle=0",
+        );
+
+        let config = result.unwrap();
+
+        assert_eq!(
+            config.listeners[0].socket_options,
+            SocketOptions {
+                keepalive: true,
+                keepalive_idle: Some(Duration::from_secs(60)),
+                user_timeout: Some(Duration::from_secs(30)),
+                ..SocketOptions::default()
+            }
+        );
+        assert_eq!(
+            config.listeners[1].socket_options,
+            SocketOptions {
+                user_timeout: Some(Duration::from_secs(30)),
+                ..SocketOptions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parses_proxy_protocol() {
+        let result = parse_factory(
+            "endless-ssh-rs --trusted-proxies 10.0.0.0/8,192.0.2.1 --listener [::]:22,proxy-protocol=true --listener [::]:2222",
+        );
+
+        let trusted_proxies = vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "192.0.2.1/32".parse().unwrap(),
+        ];
+
+        let expected_config = Config {
+            listeners: vec![
+                ListenerConfig {
+                    proxy_protocol: true,
+                    trusted_proxies: trusted_proxies.clone(),
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+                },
+                ListenerConfig {
+                    trusted_proxies,
+                    ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2222))
+                },
+            ],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_access_lists() {
+        let result = parse_factory(
+            "endless-ssh-rs --allowlist 192.0.2.1,2001:db8::/32 --denylist 10.0.0.0/8",
+        );
+
+        let expected_config = Config {
+            listeners: vec![ListenerConfig {
+                access: AccessLists {
+                    allow: vec![
+                        "192.0.2.1/32".parse().unwrap(),
+                        "2001:db8::/32".parse().unwrap(),
+                    ],
+                    deny: vec!["10.0.0.0/8".parse().unwrap()],
+                },
+                ..ListenerConfig::new(DEFAULT_SSH_LISTEN_ADDRESS)
+            }],
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn parses_privileges() {
+        let result = parse_factory("endless-ssh-rs --user endless --chroot /var/lib/endless");
+
+        let expected_config = Config {
+            privileges: Some(Privileges {
+                user: "endless".to_owned(),
+                group: None,
+                chroot: Some(PathBuf::from("/var/lib/endless")),
+            }),
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+
+        assert_matches!(
+            parse_factory("endless-ssh-rs --chroot /var/lib/endless"),
+            Err(error) if error.to_string().contains("--user")
+        );
+    }
+
+    #[test]
+    fn parses_sandbox() {
+        let result = parse_factory("endless-ssh-rs --sandbox audit");
+
+        let expected_config = Config {
+            sandbox: Sandbox::Audit,
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+
+        assert_matches!(
+            parse_factory("endless-ssh-rs --sandbox strict"),
+            Err(error) if error.to_string().contains("strict")
+        );
+    }
+
+    #[test]
+    fn rejects_proxy_protocol_without_trusted_proxies() {
+        let result = parse_factory("endless-ssh-rs --proxy-protocol");
+
+        assert_matches!(result, Err(_));
+    }
+
+    #[test]
+    fn parses_ssh_host_key() {
+        let result = parse_factory(
+            "endless-ssh-rs --ssh-host-key /var/lib/endless-ssh-rs/host_key --listener [::]:22,protocol=ssh-honeypot",
+        );
+
+        let expected_config = Config {
+            listeners: vec![ListenerConfig {
+                protocol: Protocol::SshHoneypot,
+                ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+            }],
+            ssh_host_key: "/var/lib/endless-ssh-rs/host_key".into(),
+            ..Config::default()
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn listeners_replace_ssh_listen_address() {
+        let result = parse_factory(
+            "endless-ssh-rs --ssh-listen-address 127.0.0.1:2000 --listener 127.0.0.1:2222",
+        );
+
+        let expected_config = with_listener(ListenerConfig::new(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            2222,
+        )));
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn rejects_unknown_listener_setting() {
+        let result = parse_factory("endless-ssh-rs --listener [::]:22,colour=blue");
+
+        assert_matches!(result, Err(_));
+    }
+
+    #[test]
+    fn rejects_invalid_listener_setting() {
+        let result = parse_factory("endless-ssh-rs --listener [::]:22,max-line-length=2");
+
+        assert_matches!(result, Err(_));
+    }
+
+    #[test]
+    fn rejects_duplicate_listener_names() {
+        let result =
+            parse_factory("endless-ssh-rs --listener [::]:22,name=a --listener [::]:2222,name=a");
+
+        assert_matches!(result, Err(_));
     }
 
     #[test]
@@ -236,4 +1614,48 @@ mod tests {
 
         assert_matches!(result, Err(_));
     }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let path = std::env::temp_dir().join(format!(
+            "endless-ssh-rs-config-{:016x}.toml",
+            ::rand::random::<u64>()
+        ));
+
+        std::fs::write(
+            &path,
+            "delay = 5000\nmax-clients = 100\n\n[[listener]]\naddress = \"[::]:22\"\nname = \"ssh\"\nprotocol = \"http\"\n",
+        )
+        .unwrap();
+
+        let result = parse_cli_from([
+            "endless-ssh-rs",
+            "--config",
+            path.to_str().unwrap(),
+            "--max-clients",
+            "50",
+        ]);
+
+        std::fs::remove_file(&path).unwrap();
+
+        let expected_config = Config {
+            file: Some(path),
+            ..with_listener(ListenerConfig {
+                name: "ssh".to_owned(),
+                protocol: Protocol::Http,
+                delay: std::time::Duration::from_secs(5),
+                max_clients: NonZeroU32::new(50).unwrap(),
+                ..ListenerConfig::new(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 22))
+            })
+        };
+
+        assert_matches!(result, Ok(config) if config == expected_config);
+    }
+
+    #[test]
+    fn rejects_missing_config_file() {
+        let result = parse_factory("endless-ssh-rs --config /nonexistent/endless-ssh-rs.toml");
+
+        assert_matches!(result, Err(_));
+    }
 }
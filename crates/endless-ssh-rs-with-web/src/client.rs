@@ -1,226 +1,329 @@
+use std::fmt::Display;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
-use std::os::fd::{AsRawFd, FromRawFd as _, OwnedFd};
+use std::os::fd::AsFd as _;
 use std::sync::Arc;
 
+use russh::server;
 use time::{OffsetDateTime, SignedDuration};
-use tokio::io::unix::{AsyncFd, AsyncFdReadyGuard};
 use tokio::net::TcpStream;
 use tokio::sync::OwnedSemaphorePermit;
 use tokio::sync::mpsc::Sender;
-use tokio::time::{Instant, sleep};
+use tokio::sync::watch;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, event};
 
-use crate::config::Config;
+use crate::admission::{Evict, EvictionReason, SessionSlot};
+use crate::config::ListenerConfig;
+use crate::delay::DelayStrategy;
 use crate::events::ClientEvent;
-use crate::sender;
-
-const INTERESTED_EVENTS: u32 = (libc::EPOLLRDHUP | libc::EPOLLERR | libc::EPOLLHUP).cast_unsigned();
+use crate::honeypot::{self, AuthAttempt};
+use crate::line::generator::LineGenerator;
+use crate::listener::ListenerSettings;
+use crate::protocol::Handshake;
+use crate::protocol::ssh::ClientKex;
+use crate::scheduler::{Engine, TrappedClient};
+use crate::source_limits::SourceSlot;
+use crate::tcp_metrics::TcpMetrics;
 
 pub struct ClientContext {
     pub cancellation_token: CancellationToken,
     pub internal_events_tx: Sender<ClientEvent>,
+    /// Set for `ssh-honeypot` listeners, which hand the client to russh instead of dripping.
+    pub honeypot: Option<Arc<server::Config>>,
+    pub lines: Arc<LineGenerator>,
+    /// Where clients go once their handshake is done.
+    pub engine: Arc<Engine>,
 }
 
-/// Creates an epoll fd that monitors `socket_fd` for `EPOLLRDHUP | EPOLLERR | EPOLLHUP`,
-/// but NOT `EPOLLIN`. This lets us detect disconnects without spending
-/// time reading what the client sends.
-fn make_disconnect_epoll(socket_fd: i32) -> Result<OwnedFd, std::io::Error> {
-    // SAFETY: syscall
-    let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
-    if epfd < 0 {
-        return Err(std::io::Error::last_os_error());
-    }
+/// A client's place on its listener, given back by [`release`].
+pub struct ClientSlot {
+    pub addr: SocketAddr,
+    pub connected_at: OffsetDateTime,
+    pub config: Arc<ListenerConfig>,
+    /// The listener's settings, `config` is replaced when they're reloaded.
+    pub updates: watch::Receiver<ListenerSettings>,
+    pub permit: OwnedSemaphorePermit,
+    pub source: SourceSlot,
+    pub session: SessionSlot,
+    /// Whether the dashboard and the statistics hear of the client, see [`crate::access::Access::Unrecorded`].
+    pub recorded: bool,
+}
 
-    // SAFETY: epfd is a valid fd we now own; wrap it immediately so it is closed
-    // on any subsequent error path
-    let owned_epfd = unsafe { OwnedFd::from_raw_fd(epfd) };
-
-    // SAFETY: epoll_event is a C struct, zero is a valid bit pattern for it
-    let mut ev: libc::epoll_event = unsafe { std::mem::zeroed() };
-    ev.events = INTERESTED_EVENTS;
-
-    // SAFETY: owned_epfd holds a valid epoll fd; socket_fd is a valid fd owned
-    // by the caller; ev is fully initialized
-    let ret = unsafe {
-        libc::epoll_ctl(
-            owned_epfd.as_raw_fd(),
-            libc::EPOLL_CTL_ADD,
-            socket_fd,
-            &raw mut ev,
-        )
-    };
-    if ret != 0 {
-        return Err(std::io::Error::last_os_error());
+/// Why a client's connection ended, stored with it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// It hung up, or sending to it failed.
+    #[default]
+    ClientGone,
+    /// The kernel gave up on it, its keepalive probes or the data we sent went unanswered.
+    TimedOut,
+    /// It made room for a new client, see [`crate::admission::AdmissionPolicy`].
+    Evicted(EvictionReason),
+    /// It stayed its listener's max duration.
+    MaxDurationReached,
+    /// It was sent its listener's max bytes.
+    MaxBytesReached,
+    /// We shut down.
+    Shutdown,
+}
+
+impl DisconnectReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DisconnectReason::ClientGone => "client gone",
+            DisconnectReason::TimedOut => "timed out",
+            DisconnectReason::Evicted(_) => "evicted",
+            DisconnectReason::MaxDurationReached => "max duration reached",
+            DisconnectReason::MaxBytesReached => "max bytes reached",
+            DisconnectReason::Shutdown => "shutdown",
+        }
     }
 
-    Ok(owned_epfd)
+    pub fn eviction_reason(self) -> Option<EvictionReason> {
+        match self {
+            DisconnectReason::Evicted(reason) => Some(reason),
+            DisconnectReason::ClientGone
+            | DisconnectReason::TimedOut
+            | DisconnectReason::MaxDurationReached
+            | DisconnectReason::MaxBytesReached
+            | DisconnectReason::Shutdown => None,
+        }
+    }
 }
 
-async fn await_async_epfd<T>(async_fd: Option<&'_ AsyncFd<T>>) -> AsyncFdReadyGuard<'_, T>
-where
-    T: AsRawFd,
-{
-    let Some(fd) = async_fd else {
-        return std::future::pending().await;
-    };
-
-    match fd.readable().await {
-        Ok(guard) => std::future::ready(guard).await,
-        Err(error) => {
-            event!(Level::TRACE, ?error, "epoll readable failed");
+impl From<ErrorKind> for DisconnectReason {
+    /// Why a socket that failed with `error` ended.
+    fn from(error: ErrorKind) -> Self {
+        if error == ErrorKind::TimedOut {
+            DisconnectReason::TimedOut
+        } else {
+            DisconnectReason::ClientGone
+        }
+    }
+}
 
-            std::future::pending().await
-        },
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
-async fn listen_forever(
-    mut stream: TcpStream,
-    addr: SocketAddr,
-    connected_at: OffsetDateTime,
-    config: &Config,
+/// Whatever the protocol needs to do before we start dripping, `Err` when the client is gone.
+async fn handshake(
+    stream: &mut TcpStream,
+    config: &ListenerConfig,
     context: &ClientContext,
-) -> (SignedDuration, usize) {
-    // use monotonic time to measure elapsed time of how long client is connected
-    let connected_instant = Instant::now();
-    let mut send_next = connected_instant + config.delay;
-    let mut time_spent = SignedDuration::ZERO;
-    let mut bytes_sent = 0_usize;
-
-    // register the stream for disconnect events only, failures are non-fatal and
-    // disable disconnect detection for this client (i.e. falls back to send-based detection)
-    let async_epfd: Option<AsyncFd<OwnedFd>> = match make_disconnect_epoll(stream.as_raw_fd())
-        .and_then(|epfd| AsyncFd::new(epfd))
-    {
-        Ok(fd) => Some(fd),
-        Err(error) => {
-            event!(Level::WARN, %addr, ?error, "epoll setup failed, disconnect detection disabled");
-
-            None
+) -> Result<Handshake, ()> {
+    tokio::select! {
+        biased;
+        () = context.cancellation_token.cancelled() => {
+            Err(())
         },
-    };
-
-    loop {
-        let wait_started_at = Instant::now();
-
-        if wait_started_at < send_next {
-            let until_ready = send_next.duration_since(wait_started_at);
-
-            event!(Level::TRACE, %addr, ?until_ready, "Scheduled client");
-
-            let guard = tokio::select! {
-                biased;
-                () = context.cancellation_token.cancelled() => {
-                    return (time_spent, bytes_sent);
-                },
-                guard = await_async_epfd::<OwnedFd>(async_epfd.as_ref()) => {
-                    Some(guard)
-                },
-                () = sleep(until_ready) => {
-                    None
-                }
-            };
-
-            if let Some(mut guard) = guard {
-                // SAFETY: all zeroes are valid for epoll_event
-                let mut ev: libc::epoll_event = unsafe { std::mem::zeroed() };
+        result = config.protocol.handshake(stream, config.delay, config.capture_client_ident) => {
+            result
+        },
+    }
+}
 
-                // SAFETY: async_epfd holds a valid epoll fd, ev is a valid output buffer
-                let n = unsafe {
-                    // only one event
-                    const EVENTS: i32 = 1;
+/// What we learned about a client, and what it cost it.
+#[derive(Default)]
+pub struct Trapped {
+    pub time_spent: SignedDuration,
+    pub bytes_sent: usize,
+    pub client_ident: Option<String>,
+    pub client_kex: Option<ClientKex>,
+    pub auth_attempts: Vec<AuthAttempt>,
+    pub disconnect_reason: DisconnectReason,
+    /// Read from the socket as the client leaves.
+    pub tcp_metrics: Option<TcpMetrics>,
+}
 
-                    // don't block, the fd is ready based on this future firing
-                    const TIMEOUT: i32 = 0;
+/// Broadcasts the new client for the dashboard.
+async fn announce(
+    slot: &ClientSlot,
+    context: &ClientContext,
+    client_ident: Option<String>,
+    hassh: Option<String>,
+) {
+    if !slot.recorded {
+        return;
+    }
 
-                    libc::epoll_wait(guard.get_ref().as_raw_fd(), &raw mut ev, EVENTS, TIMEOUT)
-                };
+    let _r = context
+        .internal_events_tx
+        .send(ClientEvent::Connected {
+            addr: slot.addr,
+            subnet: slot.source.subnet(),
+            sources: slot.source.counts(),
+            listener: slot.config.name.clone(),
+            protocol: slot.config.protocol,
+            client_ident,
+            hassh,
+            connected_at: slot.connected_at,
+        })
+        .await;
+}
 
-                guard.clear_ready();
+/// Handshake, then hand the client to the schedulers, which drip until it leaves.
+async fn tarpit(mut stream: TcpStream, slot: ClientSlot, context: ClientContext) {
+    // use monotonic time to measure elapsed time of how long client is connected
+    let connected_instant = Instant::now();
 
-                if n < 0 {
-                    let error = std::io::Error::last_os_error();
+    let handshake = handshake(&mut stream, &slot.config, &context).await;
 
-                    if error.raw_os_error() == Some(libc::EINTR) {
-                        event!(Level::TRACE, %addr, "epoll_wait interrupted (EINTR), retrying");
-                    } else {
-                        event!(Level::WARN, %addr, ?error, "epoll_wait failed, retrying");
-                    }
+    // the client was trapped during the handshake as well
+    let handshake_time =
+        SignedDuration::try_from(connected_instant.elapsed()).unwrap_or(SignedDuration::MAX);
 
-                    continue;
-                }
+    let (sent, client_ident, client_kex) = match handshake {
+        Ok(Handshake {
+            sent,
+            client_ident,
+            client_kex,
+        }) => (Some(sent), client_ident, client_kex),
+        Err(()) => (None, None, None),
+    };
 
-                if n > 0 && ev.events & INTERESTED_EVENTS != 0 {
-                    let partial = wait_started_at.elapsed();
+    // now that we know who the client claims to be, broadcast for the dashboard
+    announce(
+        &slot,
+        &context,
+        client_ident.clone(),
+        client_kex
+            .as_ref()
+            .map(|client_kex| client_kex.hassh.clone()),
+    )
+    .await;
+
+    let delay_strategy = slot.config.delay_strategy;
+    let addr = slot.addr;
+
+    let Some(sent) = sent else {
+        event!(Level::TRACE, %addr, "Client gone during handshake");
+
+        let trapped = Trapped {
+            time_spent: handshake_time,
+            disconnect_reason: if context.cancellation_token.is_cancelled() {
+                DisconnectReason::Shutdown
+            } else {
+                DisconnectReason::ClientGone
+            },
+            tcp_metrics: TcpMetrics::read(stream.as_fd()),
+            ..Trapped::default()
+        };
 
-                    time_spent += partial;
+        release(slot, trapped, delay_strategy, &context.internal_events_tx);
 
-                    event!(Level::TRACE, %addr, %connected_at, %time_spent, bytes_sent, send_next = %(connected_at + time_spent + config.delay), "Client gone");
+        return;
+    };
 
-                    return (time_spent, bytes_sent);
-                }
+    // the handshake isn't limited, but shows up in the rate
+    context.engine.bandwidth().record(sent);
+
+    // a failed handover closes the socket, so they're read beforehand
+    let tcp_metrics = TcpMetrics::read(stream.as_fd());
+
+    // the schedulers write without blocking, and don't need tokio's reactor for that
+    match stream.into_std() {
+        Ok(stream) => {
+            context.engine.trap(TrappedClient::new(
+                stream,
+                slot,
+                context.lines,
+                handshake_time,
+                sent,
+                client_ident,
+                client_kex,
+            ));
+        },
+        Err(error) => {
+            event!(Level::WARN, %addr, ?error, "Failed to hand client over");
+
+            let trapped = Trapped {
+                time_spent: handshake_time,
+                bytes_sent: sent,
+                client_ident,
+                client_kex,
+                tcp_metrics,
+                ..Trapped::default()
+            };
 
-                // if n == 0 (no events yet) or n > 0 with no matching flags
-                // means spurious wakeup, so we just retry
-                continue;
-            }
-        }
+            release(slot, trapped, delay_strategy, &context.internal_events_tx);
+        },
+    }
+}
 
-        event!(Level::DEBUG, %addr, "Processing client");
+pub async fn handle_client(stream: TcpStream, slot: ClientSlot, mut context: ClientContext) {
+    // it has its slot, its source counts as seen from now on
+    slot.session.remember_source();
 
-        let send_result = tokio::select! {
-            biased;
-            () = context.cancellation_token.cancelled() => {
-                return (time_spent, bytes_sent);
-            },
-            result = sender::sendline(&mut stream, config.max_line_length.get().into()) => {
-                result
-            },
-        };
+    if let Some(server_config) = context.honeypot.clone() {
+        announce(&slot, &context, None, None).await;
 
-        if let Ok(sent) = send_result {
-            time_spent += config.delay;
-            bytes_sent += sent;
+        // the client keeps its task, and is evicted by cancelling it
+        let shutdown = context.cancellation_token.clone();
 
-            // try_send: a full channel drops this update, but the next one has the updated running total
-            let _r = context
-                .internal_events_tx
-                .try_send(ClientEvent::BytesSent { addr, bytes_sent });
+        context.cancellation_token = shutdown.child_token();
 
-            send_next = Instant::now() + config.delay;
-        } else {
-            // Send failed, ergo client is gone. If epoll was active it would have
-            // fired during the wait had the client disconnected then, so we
-            // can credit the full delay. Without epoll we have no proof the
-            // client was alive through the wait, so we don't count it.
-            if async_epfd.is_some() {
-                time_spent += config.delay;
-            }
+        slot.session
+            .evictable(Evict::Task(context.cancellation_token.clone()));
 
-            event!(Level::TRACE, %addr, %time_spent, bytes_sent, "Client gone");
+        let delay_strategy = slot.config.delay_strategy;
+        let mut trapped = honeypot::trap(stream, &slot, server_config, &context).await;
 
-            return (time_spent, bytes_sent);
+        if shutdown.is_cancelled() {
+            trapped.disconnect_reason = DisconnectReason::Shutdown;
         }
+
+        release(slot, trapped, delay_strategy, &context.internal_events_tx);
+    } else {
+        tarpit(stream, slot, context).await;
     }
 }
 
-pub async fn handle_client(
-    stream: TcpStream,
-    addr: SocketAddr,
-    connected_at: OffsetDateTime,
-    permit: OwnedSemaphorePermit,
-    config: Arc<Config>,
-    context: ClientContext,
+/// Gives the client's slot back, and stores what we learned.
+pub fn release(
+    slot: ClientSlot,
+    trapped: Trapped,
+    delay_strategy: DelayStrategy,
+    internal_events_tx: &Sender<ClientEvent>,
 ) {
-    let (time_spent, bytes_sent) =
-        listen_forever(stream, addr, connected_at, &config, &context).await;
+    let ClientSlot {
+        addr,
+        connected_at,
+        config,
+        permit,
+        source,
+        session,
+        recorded,
+        ..
+    } = slot;
+
+    let Trapped {
+        time_spent,
+        bytes_sent,
+        client_ident,
+        client_kex,
+        auth_attempts,
+        disconnect_reason,
+        tcp_metrics,
+    } = trapped;
+
+    // an evicted client mostly looks like one that hung up, or that we shut down
+    let disconnect_reason = session
+        .close(time_spent)
+        .map_or(disconnect_reason, DisconnectReason::Evicted);
 
     event!(
         Level::INFO,
         %addr,
         %time_spent,
         bytes_sent,
+        auth_attempts = auth_attempts.len(),
+        %disconnect_reason,
+        ?tcp_metrics,
         "Dropping client...",
     );
 
@@ -229,19 +332,36 @@ pub async fn handle_client(
     let available_slots = permit.semaphore().available_permits();
 
     drop(permit);
+    drop(source);
 
     event!(Level::INFO, available_slots = available_slots + 1);
 
-    let internal_events_tx = context.internal_events_tx.clone();
+    if !recorded {
+        return;
+    }
+
+    let internal_events_tx = internal_events_tx.clone();
+    let listener = config.name.clone();
+    let protocol = config.protocol;
+    let delay = config.delay;
 
     tokio::spawn(async move {
         if let Err(error) = internal_events_tx
             .send(ClientEvent::Disconnected {
                 addr,
+                listener,
+                protocol,
+                client_ident,
+                client_kex: client_kex.map(Box::new),
+                auth_attempts,
+                delay_strategy,
+                delay,
+                disconnect_reason,
                 connected_at,
                 disconnected_at,
                 time_spent,
                 bytes_sent,
+                tcp_metrics,
             })
             .await
         {
@@ -1,24 +1,89 @@
+use std::fmt::Display;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-use std::num::{NonZeroU8, NonZeroU32};
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64};
+use std::path::PathBuf;
 use std::time::Duration;
 
+use ipnet::IpNet;
 use tracing::{Level, event};
 
+use crate::access::AccessLists;
+use crate::admission::AdmissionPolicy;
+use crate::delay::DelayStrategy;
+use crate::line::generator::LineSource;
+use crate::privileges::Privileges;
+use crate::protocol::Protocol;
+use crate::sandbox::Sandbox;
+use crate::socket_options::SocketOptions;
+use crate::source_limits::SourceLimits;
+
 pub const DEFAULT_DELAY_MS: NonZeroU32 = NonZeroU32::new(10000).unwrap();
 pub const DEFAULT_MAX_LINE_LENGTH: NonZeroU8 = NonZeroU8::new(32).unwrap();
-pub const DEFAULT_MAX_CLIENTS: NonZeroU8 = NonZeroU8::new(64).unwrap();
+pub const DEFAULT_MAX_CLIENTS: NonZeroU32 = NonZeroU32::new(64).unwrap();
+pub const DEFAULT_PROTOCOL: Protocol = Protocol::Ssh;
 pub const DEFAULT_SSH_LISTEN_ADDRESS: SocketAddr =
     SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 2223);
 pub const DEFAULT_HTTP_LISTEN_ADDRESS: SocketAddr =
     SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 3000);
+pub const DEFAULT_SSH_HOST_KEY: &str = "ssh_host_ed25519_key";
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub struct Config {
-    pub delay: Duration,
     pub http_listen_address: SocketAddr,
-    pub max_clients: NonZeroU8,
+    pub listeners: Vec<ListenerConfig>,
+    /// Host key of the `ssh-honeypot` listeners, generated when it doesn't exist yet.
+    pub ssh_host_key: PathBuf,
+    /// Tasks driving the trapped clients of all listeners, one per CPU when `None`.
+    pub schedulers: Option<NonZeroU16>,
+    /// Bytes per second all trapped clients get together, unlimited when `None`.
+    pub max_bandwidth: Option<NonZeroU64>,
+    /// Where the dashboard comes from in development, `dist` is served when `None`.
+    pub front_end_proxy: Option<String>,
+    pub database_url: Option<String>,
+    /// Clients aren't located when `None`.
+    pub maxmind_license_key: Option<String>,
+    /// Dropped once the sockets are bound, kept when `None`.
+    pub privileges: Option<Privileges>,
+    /// Installed once the privileges are dropped.
+    pub sandbox: Sandbox,
+    /// Where the configuration was read from, besides the environment and the command line.
+    pub file: Option<PathBuf>,
+    /// Print the effective configuration and exit, instead of starting.
+    pub print_and_exit: bool,
+}
+
+/// A single tarpit socket, and how it treats the clients it traps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerConfig {
+    /// Stored with every connection this listener traps.
+    pub name: String,
+    pub address: SocketAddr,
+    pub delay: Duration,
+    /// How `delay` varies from line to line.
+    pub delay_strategy: DelayStrategy,
+    pub max_clients: NonZeroU32,
+    /// Caps per address and per subnet, within `max_clients`.
+    pub source_limits: SourceLimits,
+    /// What happens to new clients once all `max_clients` slots are taken.
+    pub admission_policy: AdmissionPolicy,
+    /// How long a client stays trapped before it's let go, unlimited when `None`.
+    pub max_duration: Option<Duration>,
+    /// How many bytes a client is sent before it's let go, unlimited when `None`.
+    pub max_bytes: Option<NonZeroU64>,
     pub max_line_length: NonZeroU8,
-    pub ssh_listen_address: SocketAddr,
+    pub protocol: Protocol,
+    /// Read and store the identification line SSH clients send, see [`crate::protocol::ssh::read_identification`].
+    pub capture_client_ident: bool,
+    /// Where the banner lines of `ssh` listeners come from.
+    pub line_generator: LineSource,
+    /// Take the client's address from the PROXY header connections start with, see [`crate::proxy_protocol::read_header`].
+    pub proxy_protocol: bool,
+    /// The only sources whose PROXY headers are believed.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Sources trapped without being recorded, and sources turned away, see [`AccessLists::check`].
+    pub access: AccessLists,
+    /// Set on the listening socket, the connections it accepts inherit them.
+    pub socket_options: SocketOptions,
 }
 
 impl Default for Config {
@@ -30,23 +95,134 @@ impl Default for Config {
 impl Config {
     pub fn new() -> Self {
         Self {
-            delay: Duration::from_millis(DEFAULT_DELAY_MS.get().into()),
-            max_line_length: DEFAULT_MAX_LINE_LENGTH,
-            max_clients: DEFAULT_MAX_CLIENTS,
             http_listen_address: DEFAULT_HTTP_LISTEN_ADDRESS,
-            ssh_listen_address: DEFAULT_SSH_LISTEN_ADDRESS,
+            listeners: vec![ListenerConfig::default()],
+            ssh_host_key: PathBuf::from(DEFAULT_SSH_HOST_KEY),
+            schedulers: None,
+            max_bandwidth: None,
+            front_end_proxy: None,
+            database_url: None,
+            maxmind_license_key: None,
+            privileges: None,
+            sandbox: Sandbox::Off,
+            file: None,
+            print_and_exit: false,
         }
     }
 
     pub fn log(&self) {
-        event!(Level::INFO, "Delay: {}ms", self.delay.as_millis());
-        event!(Level::INFO, "MaxLineLength: {}", self.max_line_length);
-        event!(Level::INFO, "MaxClients: {}", self.max_clients);
         event!(
             Level::INFO,
             "HttpListenAddress: {}",
             self.http_listen_address
         );
-        event!(Level::INFO, "SshListenAddress: {}", self.ssh_listen_address);
+
+        event!(Level::INFO, "SshHostKey: {}", self.ssh_host_key.display());
+
+        event!(
+            Level::INFO,
+            "Schedulers: {}",
+            self.schedulers.map_or_else(
+                || "one per CPU".to_owned(),
+                |schedulers| schedulers.to_string()
+            )
+        );
+
+        event!(
+            Level::INFO,
+            "MaxBandwidth: {}",
+            self.max_bandwidth.map_or_else(
+                || "unlimited".to_owned(),
+                |max_bandwidth| format!("{} bytes/s", max_bandwidth)
+            )
+        );
+
+        if let Some(privileges) = self.privileges.as_ref() {
+            event!(
+                Level::INFO,
+                "User: {}, Group: {}, Chroot: {}",
+                privileges.user,
+                privileges.group.as_deref().unwrap_or("the user's"),
+                privileges
+                    .chroot
+                    .as_ref()
+                    .map_or_else(|| "none".to_owned(), |chroot| chroot.display().to_string())
+            );
+        }
+
+        event!(Level::INFO, "Sandbox: {}", self.sandbox);
+
+        for listener in &self.listeners {
+            listener.log();
+        }
+    }
+}
+
+fn unlimited_or(limit: Option<impl Display>) -> String {
+    limit.map_or_else(|| "unlimited".to_owned(), |limit| limit.to_string())
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        ListenerConfig::new(DEFAULT_SSH_LISTEN_ADDRESS)
+    }
+}
+
+impl ListenerConfig {
+    pub fn new(address: SocketAddr) -> Self {
+        Self {
+            name: address.to_string(),
+            address,
+            delay: Duration::from_millis(DEFAULT_DELAY_MS.get().into()),
+            delay_strategy: DelayStrategy::Fixed,
+            max_clients: DEFAULT_MAX_CLIENTS,
+            source_limits: SourceLimits::default(),
+            admission_policy: AdmissionPolicy::Refuse,
+            max_duration: None,
+            max_bytes: None,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            protocol: DEFAULT_PROTOCOL,
+            capture_client_ident: false,
+            line_generator: LineSource::Random,
+            proxy_protocol: false,
+            trusted_proxies: Vec::new(),
+            access: AccessLists::default(),
+            socket_options: SocketOptions::default(),
+        }
+    }
+
+    /// `max_clients`, as semaphore permits.
+    pub fn slots(&self) -> usize {
+        usize::try_from(self.max_clients.get()).unwrap_or(usize::MAX)
+    }
+
+    pub fn log(&self) {
+        event!(
+            Level::INFO,
+            listener = %self.name,
+            "Address: {}, Protocol: {}, Delay: {}ms, DelayStrategy: {}, MaxLineLength: {}, MaxClients: {}, MaxClientsPerIp: {}, MaxClientsPerSubnet: {}, Ipv6SubnetPrefix: {}, ExcessAction: {}, AdmissionPolicy: {}, MaxDuration: {}, MaxBytes: {}, CaptureClientIdent: {}, LineGenerator: {}, ProxyProtocol: {}, TrustedProxies: {:?}, Allowlist: {:?}, Denylist: {:?}",
+            self.address,
+            self.protocol,
+            self.delay.as_millis(),
+            self.delay_strategy,
+            self.max_line_length,
+            self.max_clients,
+            unlimited_or(self.source_limits.per_ip),
+            unlimited_or(self.source_limits.per_subnet),
+            self.source_limits.ipv6_prefix,
+            self.source_limits.action,
+            self.admission_policy,
+            unlimited_or(
+                self.max_duration
+                    .map(|max_duration| format!("{}s", max_duration.as_secs()))
+            ),
+            unlimited_or(self.max_bytes),
+            self.capture_client_ident,
+            self.line_generator,
+            self.proxy_protocol,
+            self.trusted_proxies,
+            self.access.allow,
+            self.access.deny
+        );
     }
 }
@@ -3,17 +3,28 @@ pub mod types;
 
 use std::cmp::Ordering;
 use std::net::IpAddr;
+use std::time::Duration;
 
 use futures::stream::Stream;
 use serde::Serialize;
 use sqlx::migrate::MigrateError;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::{AssertSqlSafe, PgExecutor, PgPool, Row as _};
+use sqlx::{AssertSqlSafe, PgExecutor, PgPool, Postgres, Row as _, Transaction};
 use time::{OffsetDateTime, SignedDuration};
 use tracing::{Level, event};
 
-use crate::db::types::{AllTimeTotals, ConnectionRecord, DbDuration, DbIpAddr, DbPort, Limit};
+use crate::admission::EvictionReason;
+use crate::client::DisconnectReason;
+use crate::db::conversions::{to_integer, to_tcp_metrics};
+use crate::db::types::{
+    AllTimeTotals, ConnectionRecord, DbAuthMethod, DbDuration, DbIpAddr, DbPort, DbProtocol, Limit,
+};
+use crate::delay::DelayStrategy;
 use crate::geoip::GeoInfo;
+use crate::honeypot::{AuthAttempt, AuthMethod};
+use crate::protocol::Protocol;
+use crate::protocol::ssh::ClientKex;
+use crate::tcp_metrics::TcpMetrics;
 use crate::utils::serde::as_seconds;
 
 pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
@@ -27,15 +38,28 @@ pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
     sqlx::migrate!().run(pool).await
 }
 
-#[expect(clippy::too_many_arguments, reason = "One argument per column")]
+#[expect(
+    clippy::too_many_arguments,
+    clippy::too_many_lines,
+    reason = "One argument, and a few lines, per column"
+)]
 pub async fn insert_connection(
     pool: &PgPool,
     ip_address: IpAddr,
     port: u16,
+    listener: &str,
+    protocol: Protocol,
+    client_ident: Option<&str>,
+    client_kex: Option<&ClientKex>,
+    auth_attempts: &[AuthAttempt],
+    delay_strategy: DelayStrategy,
+    delay: Duration,
+    disconnect_reason: DisconnectReason,
     connected_at: OffsetDateTime,
     disconnected_at: OffsetDateTime,
     time_spent: time::SignedDuration,
     bytes_sent: usize,
+    tcp_metrics: Option<TcpMetrics>,
     geo: Option<&GeoInfo>,
 ) -> Result<i64, sqlx::Error> {
     let bytes_sent = i64::try_from(bytes_sent)
@@ -65,6 +89,24 @@ pub async fn insert_connection(
             , city
             , latitude
             , longitude
+            , protocol
+            , listener
+            , client_ident
+            , hassh
+            , kex_algorithms
+            , host_key_algorithms
+            , encryption_algorithms
+            , mac_algorithms
+            , compression_algorithms
+            , delay_strategy
+            , delay
+            , disconnect_reason
+            , eviction_reason
+            , rtt_us
+            , rtt_var_us
+            , mss
+            , retransmits
+            , congestion_window
         ) VALUES (
             $1
             , $2
@@ -77,6 +119,24 @@ pub async fn insert_connection(
             , $9
             , $10
             , $11
+            , $12
+            , $13
+            , $14
+            , $15
+            , $16
+            , $17
+            , $18
+            , $19
+            , $20
+            , $21
+            , $22
+            , $23
+            , $24
+            , $25
+            , $26
+            , $27
+            , $28
+            , $29
         ) RETURNING id
         "#,
         connected_at,
@@ -89,11 +149,33 @@ pub async fn insert_connection(
         geo.and_then(|g| g.country_name.clone()),
         geo.and_then(|g| g.city.clone()),
         geo.and_then(|g| g.latitude),
-        geo.and_then(|g| g.longitude)
+        geo.and_then(|g| g.longitude),
+        DbProtocol(protocol) as _,
+        listener,
+        client_ident,
+        client_kex.map(|k| k.hassh.as_str()),
+        client_kex.map(|k| k.kex_algorithms.as_str()),
+        client_kex.map(|k| k.host_key_algorithms.as_str()),
+        client_kex.map(|k| k.encryption_algorithms.as_str()),
+        client_kex.map(|k| k.mac_algorithms.as_str()),
+        client_kex.map(|k| k.compression_algorithms.as_str()),
+        delay_strategy.to_string(),
+        DbDuration(SignedDuration::try_from(delay).unwrap_or(SignedDuration::MAX)) as _,
+        disconnect_reason.as_str(),
+        disconnect_reason
+            .eviction_reason()
+            .map(EvictionReason::as_str),
+        tcp_metrics.map(|m| to_integer(m.rtt_us)),
+        tcp_metrics.map(|m| to_integer(m.rtt_var_us)),
+        tcp_metrics.map(|m| to_integer(m.mss)),
+        tcp_metrics.map(|m| to_integer(m.retransmits)),
+        tcp_metrics.map(|m| to_integer(m.congestion_window)),
     )
     .fetch_one(&mut *tx)
     .await?;
 
+    insert_auth_attempts(&mut tx, id, connected_at, ip_address, auth_attempts).await?;
+
     sqlx::query!(
         r#"
         UPDATE totals
@@ -130,6 +212,10 @@ where
             id
             , ip_address as "ip_address: DbIpAddr"
             , port as "port: DbPort"
+            , listener
+            , client_ident
+            , hassh
+            , protocol as "protocol: DbProtocol"
             , connected_at
             , disconnected_at
             , time_spent as "time_spent: DbDuration"
@@ -139,11 +225,20 @@ where
             , city
             , latitude
             , longitude
+            , rtt_us
+            , rtt_var_us
+            , mss
+            , retransmits
+            , congestion_window
         FROM (
             SELECT
                 id
                 , ip_address
                 , port
+                , listener
+                , client_ident
+                , hassh
+                , protocol
                 , connected_at
                 , disconnected_at
                 , time_spent
@@ -153,6 +248,11 @@ where
                 , city
                 , latitude
                 , longitude
+                , rtt_us
+                , rtt_var_us
+                , mss
+                , retransmits
+                , congestion_window
             FROM
                 connections
             WHERE
@@ -170,6 +270,104 @@ where
     .fetch(executor)
 }
 
+/// A single connection returned by the `/api/connections/{id}` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct ConnectionRow {
+    pub id: i64,
+    pub ip: IpAddr,
+    pub port: u16,
+    /// `None` for connections stored before listeners had names.
+    pub listener: Option<String>,
+    pub protocol: Protocol,
+    pub client_ident: Option<String>,
+    pub hassh: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    #[cfg_attr(test, ts(type = "string"))]
+    pub connected_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    #[cfg_attr(test, ts(type = "string"))]
+    pub disconnected_at: OffsetDateTime,
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub time_spent: SignedDuration,
+    pub bytes_sent: i64,
+    /// `None` for connections stored before it was.
+    pub disconnect_reason: Option<String>,
+    /// `None` when the kernel couldn't tell, and for connections stored before they were.
+    pub tcp_metrics: Option<TcpMetrics>,
+    pub country_code: Option<String>,
+    pub country_name: Option<String>,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// The connection `id`, `None` when there's no such connection, or it's past the `connections` table's retention.
+pub async fn get_connection(pool: &PgPool, id: i64) -> Result<Option<ConnectionRow>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            id
+            , ip_address AS "ip_address: DbIpAddr"
+            , port AS "port: DbPort"
+            , listener
+            , protocol AS "protocol: DbProtocol"
+            , client_ident
+            , hassh
+            , connected_at
+            , disconnected_at
+            , time_spent AS "time_spent: DbDuration"
+            , bytes_sent
+            , disconnect_reason
+            , rtt_us
+            , rtt_var_us
+            , mss
+            , retransmits
+            , congestion_window
+            , country_code
+            , country_name
+            , city
+            , latitude
+            , longitude
+        FROM
+            connections
+        WHERE
+            id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| ConnectionRow {
+        id: row.id,
+        ip: row.ip_address.into(),
+        port: row.port.into(),
+        listener: row.listener,
+        protocol: row.protocol.into(),
+        client_ident: row.client_ident,
+        hassh: row.hassh,
+        connected_at: row.connected_at,
+        disconnected_at: row.disconnected_at,
+        time_spent: row.time_spent.into(),
+        bytes_sent: row.bytes_sent,
+        disconnect_reason: row.disconnect_reason,
+        tcp_metrics: to_tcp_metrics(
+            row.rtt_us,
+            row.rtt_var_us,
+            row.mss,
+            row.retransmits,
+            row.congestion_window,
+        ),
+        country_code: row.country_code,
+        country_name: row.country_name,
+        city: row.city,
+        latitude: row.latitude,
+        longitude: row.longitude,
+    }))
+}
+
 pub async fn get_totals<'e, E>(executor: E) -> Result<AllTimeTotals, sqlx::Error>
 where
     E: PgExecutor<'e>,
@@ -408,6 +606,361 @@ pub async fn get_stats(
     })
 }
 
+/// Per-listener totals returned by the `/api/stats/listeners` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct ListenerStatsRow {
+    /// `None` for connections stored before listeners had names.
+    pub listener: Option<String>,
+    pub protocol: Protocol,
+    pub connects: i64,
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub time_spent: SignedDuration,
+    pub bytes_sent: i64,
+}
+
+/// Totals per listener for connections that ended in [from, to).
+/// These come from the raw `connections` table, so they only reach back as far as its retention.
+pub async fn get_listener_stats(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<ListenerStatsRow>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            listener
+            , protocol as "protocol: DbProtocol"
+            , COUNT(*) AS "connects!: i64"
+            , COALESCE(SUM(time_spent), '0 seconds'::interval) AS "time_spent!: DbDuration"
+            , COALESCE(SUM(bytes_sent), 0)::bigint AS "bytes_sent!: i64"
+        FROM
+            connections
+        WHERE
+            disconnected_at >= $1
+            AND disconnected_at < $2
+        GROUP BY
+            listener
+            , protocol
+        ORDER BY
+            listener
+            , protocol
+        "#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ListenerStatsRow {
+            listener: row.listener,
+            protocol: row.protocol.into(),
+            connects: row.connects,
+            time_spent: row.time_spent.into(),
+            bytes_sent: row.bytes_sent,
+        })
+        .collect())
+}
+
+/// Per-client-software totals returned by the `/api/stats/clients` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct ClientSoftwareStatsRow {
+    /// The software part of the identification line, e.g. `OpenSSH_9.6` out of `SSH-2.0-OpenSSH_9.6 Ubuntu`.
+    /// `None` for clients that didn't identify as SSH, or weren't asked to.
+    pub client_software: Option<String>,
+    pub connects: i64,
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub time_spent: SignedDuration,
+    pub bytes_sent: i64,
+}
+
+/// Totals per client software for connections that ended in [from, to).
+/// These come from the raw `connections` table, so they only reach back as far as its retention.
+pub async fn get_client_software_stats(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<ClientSoftwareStatsRow>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            substring(split_part(client_ident, ' ', 1) FROM '^SSH-[^-]*-(.+)$') AS client_software
+            , COUNT(*) AS "connects!: i64"
+            , COALESCE(SUM(time_spent), '0 seconds'::interval) AS "time_spent!: DbDuration"
+            , COALESCE(SUM(bytes_sent), 0)::bigint AS "bytes_sent!: i64"
+        FROM
+            connections
+        WHERE
+            disconnected_at >= $1
+            AND disconnected_at < $2
+        GROUP BY
+            client_software
+        ORDER BY
+            COUNT(*) DESC
+            , client_software
+        "#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ClientSoftwareStatsRow {
+            client_software: row.client_software,
+            connects: row.connects,
+            time_spent: row.time_spent.into(),
+            bytes_sent: row.bytes_sent,
+        })
+        .collect())
+}
+
+/// Per-HASSH totals returned by the `/api/stats/hassh` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct HasshStatsRow {
+    pub hassh: String,
+    /// Every identification line seen with this fingerprint.
+    pub client_idents: Vec<String>,
+    pub connects: i64,
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub time_spent: SignedDuration,
+    pub bytes_sent: i64,
+}
+
+/// Totals per HASSH for connections that ended in [from, to), only key exchange mode connections have one.
+/// These come from the raw `connections` table, so they only reach back as far as its retention.
+pub async fn get_hassh_stats(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<HasshStatsRow>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            hassh AS "hassh!"
+            , array_remove(array_agg(DISTINCT client_ident), NULL) AS "client_idents!: Vec<String>"
+            , COUNT(*) AS "connects!: i64"
+            , COALESCE(SUM(time_spent), '0 seconds'::interval) AS "time_spent!: DbDuration"
+            , COALESCE(SUM(bytes_sent), 0)::bigint AS "bytes_sent!: i64"
+        FROM
+            connections
+        WHERE
+            disconnected_at >= $1
+            AND disconnected_at < $2
+            AND hassh IS NOT NULL
+        GROUP BY
+            hassh
+        ORDER BY
+            COUNT(*) DESC
+            , hassh
+        "#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| HasshStatsRow {
+            hassh: row.hassh,
+            client_idents: row.client_idents,
+            connects: row.connects,
+            time_spent: row.time_spent.into(),
+            bytes_sent: row.bytes_sent,
+        })
+        .collect())
+}
+
+/// Per delay strategy totals returned by the `/api/stats/delays` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct DelayStatsRow {
+    /// E.g. `pareto:1.5`.
+    pub delay_strategy: String,
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub delay: SignedDuration,
+    pub connects: i64,
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub time_spent: SignedDuration,
+    /// How long the average client stayed trapped.
+    #[serde(serialize_with = "as_seconds")]
+    #[cfg_attr(test, ts(type = "number"))]
+    pub average_time_spent: SignedDuration,
+    pub bytes_sent: i64,
+}
+
+/// Totals per delay strategy and delay for connections that ended in [from, to), to compare trap durations.
+/// These come from the raw `connections` table, so they only reach back as far as its retention.
+pub async fn get_delay_stats(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<DelayStatsRow>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            delay_strategy AS "delay_strategy!"
+            , delay AS "delay!: DbDuration"
+            , COUNT(*) AS "connects!: i64"
+            , COALESCE(SUM(time_spent), '0 seconds'::interval) AS "time_spent!: DbDuration"
+            , COALESCE(AVG(time_spent), '0 seconds'::interval) AS "average_time_spent!: DbDuration"
+            , COALESCE(SUM(bytes_sent), 0)::bigint AS "bytes_sent!: i64"
+        FROM
+            connections
+        WHERE
+            disconnected_at >= $1
+            AND disconnected_at < $2
+            AND delay_strategy IS NOT NULL
+            AND delay IS NOT NULL
+        GROUP BY
+            delay_strategy
+            , delay
+        ORDER BY
+            AVG(time_spent) DESC
+            , delay_strategy
+            , delay
+        "#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| DelayStatsRow {
+            delay_strategy: row.delay_strategy,
+            delay: row.delay.into(),
+            connects: row.connects,
+            time_spent: row.time_spent.into(),
+            average_time_spent: row.average_time_spent.into(),
+            bytes_sent: row.bytes_sent,
+        })
+        .collect())
+}
+
+/// Stores the login attempts of connection `id`, as part of its insert.
+async fn insert_auth_attempts(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i64,
+    connected_at: OffsetDateTime,
+    ip_address: IpAddr,
+    auth_attempts: &[AuthAttempt],
+) -> Result<(), sqlx::Error> {
+    for attempt in auth_attempts {
+        sqlx::query!(
+            r#"
+            INSERT INTO auth_attempts (
+                connection_id
+                , connected_at
+                , attempted_at
+                , ip_address
+                , method
+                , username
+                , password
+                , key_fingerprint
+            ) VALUES (
+                $1
+                , $2
+                , $3
+                , $4
+                , $5
+                , $6
+                , $7
+                , $8
+            )
+            "#,
+            id,
+            connected_at,
+            attempt.attempted_at,
+            DbIpAddr(ip_address) as _,
+            DbAuthMethod(attempt.method) as _,
+            attempt.username,
+            attempt.password,
+            attempt.key_fingerprint,
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// A single row returned by the `/api/auth-attempts` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct AuthAttemptRow {
+    /// The `connections` row this attempt belongs to.
+    pub connection_id: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    #[cfg_attr(test, ts(type = "string"))]
+    pub attempted_at: OffsetDateTime,
+    pub ip: IpAddr,
+    pub method: AuthMethod,
+    pub username: String,
+    pub password: Option<String>,
+    pub key_fingerprint: Option<String>,
+}
+
+/// The most recent login attempts made in [from, to), newest first.
+pub async fn get_auth_attempts(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    limit: Limit,
+) -> Result<Vec<AuthAttemptRow>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            connection_id
+            , attempted_at
+            , ip_address AS "ip_address: DbIpAddr"
+            , method AS "method: DbAuthMethod"
+            , username
+            , password
+            , key_fingerprint
+        FROM
+            auth_attempts
+        WHERE
+            attempted_at >= $1
+            AND attempted_at < $2
+        ORDER BY
+            attempted_at DESC
+            , id DESC
+        LIMIT $3
+        "#,
+        from,
+        to,
+        limit as _,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| AuthAttemptRow {
+            connection_id: row.connection_id,
+            attempted_at: row.attempted_at,
+            ip: row.ip_address.into(),
+            method: row.method.into(),
+            username: row.username,
+            password: row.password,
+            key_fingerprint: row.key_fingerprint,
+        })
+        .collect())
+}
+
 #[track_caller]
 pub fn log_db_error(error: &sqlx::Error) {
     event!(Level::ERROR, ?error, "Database error");
@@ -4,6 +4,8 @@ use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use sqlx::postgres::types::PgInterval;
 use time::SignedDuration;
 
+use crate::tcp_metrics::TcpMetrics;
+
 /// Convert `IpAddr` to `IpNetwork` for `PostgreSQL` INET binding.
 /// Inserting an `IpAddr` works equally as well, but then we're not explicit.
 pub fn to_inet(ip: IpAddr) -> IpNet {
@@ -40,3 +42,27 @@ pub fn to_duration(interval: PgInterval) -> time::SignedDuration {
     time::SignedDuration::days(total_days)
         + time::SignedDuration::microseconds(interval.microseconds)
 }
+
+/// `TCP_INFO` counters are unsigned, those past `INTEGER` are capped, no round trip takes 35 minutes.
+pub fn to_integer(value: u32) -> i32 {
+    i32::try_from(value).unwrap_or(i32::MAX)
+}
+
+/// The metrics stored with a connection, which are stored together or not at all.
+pub fn to_tcp_metrics(
+    rtt_us: Option<i32>,
+    rtt_var_us: Option<i32>,
+    mss: Option<i32>,
+    retransmits: Option<i32>,
+    congestion_window: Option<i32>,
+) -> Option<TcpMetrics> {
+    let from_integer = |value: i32| u32::try_from(value).unwrap_or_default();
+
+    Some(TcpMetrics {
+        rtt_us: from_integer(rtt_us?),
+        rtt_var_us: from_integer(rtt_var_us?),
+        mss: from_integer(mss?),
+        retransmits: from_integer(retransmits?),
+        congestion_window: from_integer(congestion_window?),
+    })
+}
@@ -8,7 +8,10 @@ use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
 use sqlx::{Decode, Encode, Postgres, Type};
 use time::{OffsetDateTime, SignedDuration};
 
-use crate::db::conversions::{to_duration, to_inet, to_interval};
+use crate::db::conversions::{to_duration, to_inet, to_interval, to_tcp_metrics};
+use crate::honeypot::AuthMethod;
+use crate::protocol::Protocol;
+use crate::tcp_metrics::TcpMetrics;
 
 #[derive(Copy, Clone)]
 pub enum Limit {
@@ -122,11 +125,85 @@ impl From<DbDuration> for SignedDuration {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct DbProtocol(pub Protocol);
+
+impl Type<Postgres> for DbProtocol {
+    fn type_info() -> PgTypeInfo {
+        <&str as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <&str as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for DbProtocol {
+    fn decode(
+        value: PgValueRef<'r>,
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let protocol = <&str as Decode<'r, Postgres>>::decode(value)?;
+
+        Ok(DbProtocol(protocol.parse()?))
+    }
+}
+
+impl Encode<'_, Postgres> for DbProtocol {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode(self.0.as_str(), buf)
+    }
+}
+
+impl From<DbProtocol> for Protocol {
+    fn from(value: DbProtocol) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DbAuthMethod(pub AuthMethod);
+
+impl Type<Postgres> for DbAuthMethod {
+    fn type_info() -> PgTypeInfo {
+        <&str as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <&str as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for DbAuthMethod {
+    fn decode(
+        value: PgValueRef<'r>,
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let method = <&str as Decode<'r, Postgres>>::decode(value)?;
+
+        Ok(DbAuthMethod(method.parse()?))
+    }
+}
+
+impl Encode<'_, Postgres> for DbAuthMethod {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode(self.0.as_str(), buf)
+    }
+}
+
+impl From<DbAuthMethod> for AuthMethod {
+    fn from(value: DbAuthMethod) -> Self {
+        value.0
+    }
+}
+
 /// Raw connection record.
 pub struct ConnectionRecord {
     pub id: i64,
     pub ip_address: DbIpAddr,
     pub port: DbPort,
+    pub listener: Option<String>,
+    pub client_ident: Option<String>,
+    pub hassh: Option<String>,
+    pub protocol: DbProtocol,
     pub connected_at: OffsetDateTime,
     pub disconnected_at: OffsetDateTime,
     pub time_spent: DbDuration,
@@ -137,6 +214,23 @@ pub struct ConnectionRecord {
     pub city: Option<String>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
+    pub rtt_us: Option<i32>,
+    pub rtt_var_us: Option<i32>,
+    pub mss: Option<i32>,
+    pub retransmits: Option<i32>,
+    pub congestion_window: Option<i32>,
+}
+
+impl ConnectionRecord {
+    pub fn tcp_metrics(&self) -> Option<TcpMetrics> {
+        to_tcp_metrics(
+            self.rtt_us,
+            self.rtt_var_us,
+            self.mss,
+            self.retransmits,
+            self.congestion_window,
+        )
+    }
 }
 
 /// All-time aggregate totals for the WebSocket init payload.
@@ -0,0 +1,254 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::line::GenRange;
+use crate::line::get_random::GetRandom;
+
+/// No strategy waits longer than this between two lines, however heavy its tail.
+pub const MAX_DELAY: Duration = Duration::from_hours(1);
+
+/// How long a client waits for its next line, based on the listener's delay.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DelayStrategy {
+    /// Always the delay, what the tarpit always did.
+    #[default]
+    Fixed,
+    /// Uniformly distributed within `spread` (0 to 1) of the delay, both ways.
+    Jitter { spread: f64 },
+    /// Exponentially distributed, with the delay as mean.
+    Exponential,
+    /// Pareto distributed, with the delay as mean. The lower the `shape` (above 1), the heavier the tail.
+    Pareto { shape: f64 },
+    /// The delay, multiplied by `factor` (1 or more) for every line already sent.
+    Backoff { factor: f64 },
+}
+
+impl Display for DelayStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            DelayStrategy::Fixed => f.write_str("fixed"),
+            DelayStrategy::Jitter { spread } => write!(f, "jitter:{}", spread),
+            DelayStrategy::Exponential => f.write_str("exponential"),
+            DelayStrategy::Pareto { shape } => write!(f, "pareto:{}", shape),
+            DelayStrategy::Backoff { factor } => write!(f, "backoff:{}", factor),
+        }
+    }
+}
+
+impl FromStr for DelayStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s, None),
+        };
+
+        let parameter = |valid: fn(f64) -> bool, expected: &str| {
+            parameter
+                .and_then(|parameter| parameter.parse::<f64>().ok())
+                .filter(|&parameter| valid(parameter))
+                .ok_or_else(|| format!("`{}` expects {}", name, expected))
+        };
+
+        match name {
+            "fixed" => Ok(DelayStrategy::Fixed),
+            "jitter" => Ok(DelayStrategy::Jitter {
+                spread: parameter(
+                    |spread| (0.0..=1.0).contains(&spread),
+                    "a spread from 0 to 1",
+                )?,
+            }),
+            "exponential" => Ok(DelayStrategy::Exponential),
+            "pareto" => Ok(DelayStrategy::Pareto {
+                shape: parameter(|shape| shape > 1.0 && shape.is_finite(), "a shape above 1")?,
+            }),
+            "backoff" => Ok(DelayStrategy::Backoff {
+                factor: parameter(
+                    |factor| factor >= 1.0 && factor.is_finite(),
+                    "a factor of 1 or more",
+                )?,
+            }),
+            _ => Err(format!(
+                "Unknown delay strategy `{}`, expected `fixed`, `jitter:SPREAD`, `exponential`, `pareto:SHAPE` or `backoff:FACTOR`",
+                s
+            )),
+        }
+    }
+}
+
+/// The delays of a single client.
+pub struct Delays {
+    strategy: DelayStrategy,
+    delay: Duration,
+    /// Lines sent so far, for [`DelayStrategy::Backoff`].
+    sent: i32,
+}
+
+impl Delays {
+    pub fn new(strategy: DelayStrategy, delay: Duration) -> Self {
+        Self {
+            strategy,
+            delay,
+            sent: 0,
+        }
+    }
+
+    /// Switches to a reloaded strategy and delay, [`DelayStrategy::Backoff`] keeps counting the lines already sent.
+    pub fn reconfigure(&mut self, strategy: DelayStrategy, delay: Duration) {
+        self.strategy = strategy;
+        self.delay = delay;
+    }
+
+    /// How long to wait for the next line.
+    pub fn next(&mut self) -> Duration {
+        self.next_from(&mut GenRange::default())
+    }
+
+    fn next_from(&mut self, rng: &mut impl GetRandom) -> Duration {
+        let factor = match self.strategy {
+            DelayStrategy::Fixed => 1.0,
+            DelayStrategy::Jitter { spread } => rng.gen_range(1.0 - spread..=1.0 + spread),
+            DelayStrategy::Exponential => {
+                // inverse transform sampling, 1 - u is never 0
+                -(1.0 - rng.gen_range(0.0..1.0_f64)).ln()
+            },
+            DelayStrategy::Pareto { shape } => {
+                // scaled so the mean is the delay
+                let scale = (shape - 1.0) / shape;
+
+                scale / (1.0 - rng.gen_range(0.0..1.0_f64)).powf(shape.recip())
+            },
+            DelayStrategy::Backoff { factor } => factor.powi(self.sent),
+        };
+
+        self.sent = self.sent.saturating_add(1);
+
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor)
+            .unwrap_or(MAX_DELAY)
+            .min(MAX_DELAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Range, RangeInclusive};
+    use std::time::Duration;
+
+    use pretty_assertions::{assert_eq, assert_matches};
+
+    use crate::delay::{DelayStrategy, Delays, MAX_DELAY};
+    use crate::line::get_random::MockGetRandom;
+
+    #[test]
+    fn parses_delay_strategies() {
+        for strategy in [
+            DelayStrategy::Fixed,
+            DelayStrategy::Jitter { spread: 0.5 },
+            DelayStrategy::Exponential,
+            DelayStrategy::Pareto { shape: 1.5 },
+            DelayStrategy::Backoff { factor: 1.1 },
+        ] {
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        assert_matches!("jitter".parse::<DelayStrategy>(), Err(_));
+        assert_matches!("jitter:2".parse::<DelayStrategy>(), Err(_));
+        assert_matches!("pareto:1".parse::<DelayStrategy>(), Err(_));
+        assert_matches!("backoff:0.5".parse::<DelayStrategy>(), Err(_));
+        assert_matches!("backoff:inf".parse::<DelayStrategy>(), Err(_));
+        assert_matches!("gaussian".parse::<DelayStrategy>(), Err(_));
+    }
+
+    #[test]
+    fn jitter_stays_within_spread() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // the low end of the range
+        ctx.expect_gen_range::<f64, RangeInclusive<f64>>()
+            .returning(|x| *x.start());
+
+        let mut delays = Delays::new(
+            DelayStrategy::Jitter { spread: 0.25 },
+            Duration::from_secs(8),
+        );
+
+        // then
+        assert_eq!(delays.next_from(&mut ctx), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn pareto_is_scaled_to_the_delay() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        let mut picks = [0.0, 0.75, 1.0 - f64::EPSILON].into_iter();
+
+        ctx.expect_gen_range::<f64, Range<f64>>()
+            .returning(move |_| picks.next().unwrap());
+
+        let mut delays = Delays::new(
+            DelayStrategy::Pareto { shape: 2.0 },
+            Duration::from_secs(10),
+        );
+
+        // then
+        // the minimum is half the mean with a shape of 2
+        assert_eq!(delays.next_from(&mut ctx), Duration::from_secs(5));
+        assert_eq!(delays.next_from(&mut ctx), Duration::from_secs(10));
+        // the tail is capped
+        assert_eq!(delays.next_from(&mut ctx), MAX_DELAY);
+    }
+
+    #[test]
+    fn backoff_grows_with_every_line() {
+        // given
+        let ctx = &mut MockGetRandom::new();
+
+        let mut delays = Delays::new(
+            DelayStrategy::Backoff { factor: 2.0 },
+            Duration::from_secs(1),
+        );
+
+        // then
+        assert_eq!(
+            [
+                delays.next_from(ctx),
+                delays.next_from(ctx),
+                delays.next_from(ctx),
+            ],
+            [
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn reconfigured_backoff_keeps_counting() {
+        // given
+        let ctx = &mut MockGetRandom::new();
+
+        let mut delays = Delays::new(DelayStrategy::Fixed, Duration::from_secs(1));
+
+        delays.next_from(ctx);
+        delays.next_from(ctx);
+
+        // when
+        delays.reconfigure(
+            DelayStrategy::Backoff { factor: 2.0 },
+            Duration::from_secs(3),
+        );
+
+        // then
+        assert_eq!(delays.next_from(ctx), Duration::from_secs(12));
+    }
+}
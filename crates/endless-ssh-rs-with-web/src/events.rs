@@ -1,34 +1,66 @@
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use dashmap::DashMap;
+use ipnet::IpNet;
 use serde::Serialize;
 use time::{OffsetDateTime, SignedDuration};
 use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, event};
 
+use crate::client::DisconnectReason;
 use crate::db;
+use crate::delay::DelayStrategy;
 use crate::geoip::GeoIpReader;
-use crate::utils::serde::as_seconds;
+use crate::honeypot::{AuthAttempt, AuthMethod};
+use crate::protocol::Protocol;
+use crate::protocol::ssh::ClientKex;
+use crate::source_limits::SourceCounts;
+use crate::tcp_metrics::TcpMetrics;
+use crate::utils::serde::{as_display, as_seconds};
 
 /// Internal event bus.
 #[derive(Clone)]
 pub enum ClientEvent {
     Connected {
         addr: SocketAddr,
+        /// What the listener's per-subnet limit counts the client towards.
+        subnet: IpNet,
+        /// The listener's per source counts, the client's among them.
+        sources: Arc<SourceCounts>,
+        listener: String,
+        protocol: Protocol,
+        client_ident: Option<String>,
+        hassh: Option<String>,
         connected_at: OffsetDateTime,
     },
     BytesSent {
         addr: SocketAddr,
         bytes_sent: usize,
     },
+    AuthAttempt {
+        addr: SocketAddr,
+        listener: String,
+        attempt: AuthAttempt,
+    },
     Disconnected {
         addr: SocketAddr,
+        listener: String,
+        protocol: Protocol,
+        client_ident: Option<String>,
+        /// Boxed, it would be most of every event otherwise.
+        client_kex: Option<Box<ClientKex>>,
+        auth_attempts: Vec<AuthAttempt>,
+        delay_strategy: DelayStrategy,
+        delay: Duration,
+        disconnect_reason: DisconnectReason,
         connected_at: OffsetDateTime,
         disconnected_at: OffsetDateTime,
         time_spent: SignedDuration,
         bytes_sent: usize,
+        tcp_metrics: Option<TcpMetrics>,
     },
 }
 
@@ -52,6 +84,15 @@ pub enum WsEvent {
     Connected {
         ip: IpAddr,
         port: u16,
+        #[serde(serialize_with = "as_display")]
+        #[cfg_attr(test, ts(type = "string"))]
+        subnet: IpNet,
+        listener: String,
+        protocol: Protocol,
+        /// The identification line the client sent, when captured.
+        client_ident: Option<String>,
+        /// The fingerprint of the client's KEXINIT, in key exchange mode.
+        hassh: Option<String>,
         #[serde(with = "time::serde::rfc3339")]
         #[cfg_attr(test, ts(type = "string"))]
         connected_at: OffsetDateTime,
@@ -66,10 +107,30 @@ pub enum WsEvent {
         port: u16,
         bytes_sent: usize,
     },
+    /// A login attempt the SSH honeypot just rejected, stored when the client disconnects.
+    AuthAttempt {
+        ip: IpAddr,
+        port: u16,
+        listener: String,
+        method: AuthMethod,
+        username: String,
+        password: Option<String>,
+        key_fingerprint: Option<String>,
+        #[serde(with = "time::serde::rfc3339")]
+        #[cfg_attr(test, ts(type = "string"))]
+        attempted_at: OffsetDateTime,
+    },
     Disconnected {
         sequence: i64,
         ip: IpAddr,
         port: u16,
+        /// `None` for connections stored before listeners had names.
+        listener: Option<String>,
+        protocol: Protocol,
+        /// The identification line the client sent, when captured.
+        client_ident: Option<String>,
+        /// The fingerprint of the client's KEXINIT, in key exchange mode.
+        hassh: Option<String>,
         #[serde(with = "time::serde::rfc3339")]
         #[cfg_attr(test, ts(type = "string"))]
         connected_at: OffsetDateTime,
@@ -80,6 +141,9 @@ pub enum WsEvent {
         #[cfg_attr(test, ts(type = "number"))]
         time_spent: SignedDuration,
         bytes_sent: usize,
+        /// What the kernel measured of the connection as it ended, `None` when it couldn't tell, and for connections
+        /// stored before.
+        tcp_metrics: Option<TcpMetrics>,
         country_code: Option<String>,
         country_name: Option<String>,
         city: Option<String>,
@@ -96,6 +160,21 @@ pub enum WsEvent {
 pub struct ActiveConnectionInfo {
     pub ip: IpAddr,
     pub port: u16,
+    /// The subnet the listener's per-subnet limit counts this connection towards.
+    #[serde(serialize_with = "as_display")]
+    #[cfg_attr(test, ts(type = "string"))]
+    pub subnet: IpNet,
+    /// Connections to the same listener from this address, this one included.
+    pub connections_from_ip: u32,
+    /// Connections to the same listener from this subnet, this one included.
+    pub connections_from_subnet: u32,
+    /// Where the counts above come from, they're read again for each snapshot, see [`ActiveConnectionInfo::current`].
+    #[serde(skip)]
+    pub sources: Arc<SourceCounts>,
+    pub listener: String,
+    pub protocol: Protocol,
+    pub client_ident: Option<String>,
+    pub hassh: Option<String>,
     #[serde(with = "time::serde::rfc3339")]
     #[cfg_attr(test, ts(type = "string"))]
     pub connected_at: OffsetDateTime,
@@ -107,6 +186,20 @@ pub struct ActiveConnectionInfo {
     pub city: Option<String>,
 }
 
+impl ActiveConnectionInfo {
+    /// This connection, with the current counts of its source.
+    pub fn current(&self) -> Self {
+        let (connections_from_ip, connections_from_subnet) =
+            self.sources.current(self.ip, self.subnet);
+
+        Self {
+            connections_from_ip,
+            connections_from_subnet,
+            ..self.clone()
+        }
+    }
+}
+
 /// Main event-processing loop.
 pub async fn database_listen_forever(
     cancellation_token: CancellationToken,
@@ -144,6 +237,7 @@ pub async fn database_listen_forever(
     }
 }
 
+#[expect(clippy::too_many_lines, reason = "One arm per event")]
 async fn handle_event(
     client_event: ClientEvent,
     db_pool: &sqlx::PgPool,
@@ -152,12 +246,31 @@ async fn handle_event(
     active_connections: &Arc<DashMap<SocketAddr, ActiveConnectionInfo>>,
 ) {
     match client_event {
-        ClientEvent::Connected { addr, connected_at } => {
+        ClientEvent::Connected {
+            addr,
+            subnet,
+            sources,
+            listener,
+            protocol,
+            client_ident,
+            hassh,
+            connected_at,
+        } => {
             let mut geo = (*geo_ip_reader).lookup(addr.ip());
 
+            let (connections_from_ip, connections_from_subnet) = sources.current(addr.ip(), subnet);
+
             let info = ActiveConnectionInfo {
                 ip: addr.ip(),
                 port: addr.port(),
+                subnet,
+                connections_from_ip,
+                connections_from_subnet,
+                sources,
+                listener: listener.clone(),
+                protocol,
+                client_ident: client_ident.clone(),
+                hassh: hassh.clone(),
                 connected_at,
                 bytes_sent: 0,
                 latitude: geo.as_ref().and_then(|g| g.latitude),
@@ -174,6 +287,11 @@ async fn handle_event(
             let ws_event = WsEvent::Connected {
                 ip: info.ip,
                 port: info.port,
+                subnet,
+                listener: listener.clone(),
+                protocol,
+                client_ident,
+                hassh,
                 connected_at,
                 country_code,
                 country_name,
@@ -201,12 +319,39 @@ async fn handle_event(
             });
         },
 
+        ClientEvent::AuthAttempt {
+            addr,
+            listener,
+            attempt,
+        } => {
+            // ignore send errors, no WS clients connected is fine
+            let _r = ws_broadcast_tx.send(WsEvent::AuthAttempt {
+                ip: addr.ip(),
+                port: addr.port(),
+                listener,
+                method: attempt.method,
+                username: attempt.username,
+                password: attempt.password,
+                key_fingerprint: attempt.key_fingerprint,
+                attempted_at: attempt.attempted_at,
+            });
+        },
+
         ClientEvent::Disconnected {
             addr,
+            listener,
+            protocol,
+            client_ident,
+            client_kex,
+            auth_attempts,
+            delay_strategy,
+            delay,
+            disconnect_reason,
             connected_at,
             disconnected_at,
             time_spent,
             bytes_sent,
+            tcp_metrics,
         } => {
             active_connections.remove(&addr);
 
@@ -216,10 +361,19 @@ async fn handle_event(
                 db_pool,
                 addr.ip(),
                 addr.port(),
+                &listener,
+                protocol,
+                client_ident.as_deref(),
+                client_kex.as_deref(),
+                &auth_attempts,
+                delay_strategy,
+                delay,
+                disconnect_reason,
                 connected_at,
                 disconnected_at,
                 time_spent,
                 bytes_sent,
+                tcp_metrics,
                 geo.as_ref(),
             )
             .await
@@ -233,10 +387,15 @@ async fn handle_event(
                         sequence,
                         ip: addr.ip(),
                         port: addr.port(),
+                        listener: Some(listener),
+                        protocol,
+                        client_ident,
+                        hassh: client_kex.map(|client_kex| client_kex.hassh),
                         connected_at,
                         disconnected_at,
                         time_spent,
                         bytes_sent,
+                        tcp_metrics,
                         country_code,
                         country_name,
                         city,
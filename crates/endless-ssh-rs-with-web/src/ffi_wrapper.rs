@@ -1,23 +1,103 @@
+use std::ffi::CStr;
 use std::io::Error;
-use std::mem::size_of_val;
+use std::mem::{MaybeUninit, size_of_val, zeroed};
+use std::os::fd::BorrowedFd;
 use std::os::unix::prelude::AsRawFd as _;
+use std::ptr;
+use std::time::Duration;
 
-use libc::{SO_RCVBUF, SOL_SOCKET, c_int, c_void, setsockopt, socklen_t};
-use tokio::net::TcpStream;
+use libc::{
+    ERANGE, F_GETFD, F_SETFD, FD_CLOEXEC, IPPROTO_TCP, PR_CAP_AMBIENT, PR_CAP_AMBIENT_CLEAR_ALL,
+    RLIMIT_NOFILE, SO_LINGER, SOL_SOCKET, SYS_capget, SYS_capset, TCP_INFO, c_char, c_int, c_ulong,
+    c_void, fcntl, getgrnam_r, getpwnam_r, getrlimit, getsockopt, gid_t, group, listen, passwd,
+    prctl, rlim_t, rlimit, setgid, setgroups, setrlimit, setsockopt, setuid, socklen_t, syscall,
+    tcp_info, uid_t,
+};
 
-pub fn set_receive_buffer_size(tcp_stream: &TcpStream, size_in_bytes: usize) -> Result<(), Error> {
-    // Set the smallest possible receive buffer. This reduces local
-    // resource usage and slows down the remote end.
-    let value: i32 = i32::try_from(size_in_bytes).expect("Byte buffer didn't fit in an i32");
+/// `_LINUX_CAPABILITY_VERSION_3`, 64 bit capability sets, split in two.
+const CAPABILITY_VERSION: u32 = 0x2008_0522;
 
+/// `struct __user_cap_header_struct`, see `capget(2)`.
+#[repr(C)]
+struct CapabilityHeader {
+    version: u32,
+    pid: c_int,
+}
+
+/// `struct __user_cap_data_struct`, see `capget(2)`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapabilityData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Sets the integer socket option `name` at `level` to `value`, see `setsockopt(2)`.
+pub fn set_socket_option(
+    fd: BorrowedFd<'_>,
+    level: c_int,
+    name: c_int,
+    value: c_int,
+) -> Result<(), Error> {
     let size: socklen_t = u32::try_from(size_of_val(&value)).unwrap();
 
-    // SAFETY: external call
+    // SAFETY: external call, value is valid for reads of `size` bytes
+    let r: c_int = unsafe {
+        setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            (&raw const value).cast::<c_void>(),
+            size,
+        )
+    };
+
+    if r == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// The integer socket option `name` at `level`, see `getsockopt(2)`.
+pub fn get_socket_option(fd: BorrowedFd<'_>, level: c_int, name: c_int) -> Result<c_int, Error> {
+    let mut value: c_int = 0;
+    let mut size: socklen_t = u32::try_from(size_of_val(&value)).unwrap();
+
+    // SAFETY: external call, value is valid for writes of `size` bytes
+    let r: c_int = unsafe {
+        getsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            (&raw mut value).cast::<c_void>(),
+            &raw mut size,
+        )
+    };
+
+    if r == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(value)
+}
+
+/// Sets `SO_LINGER`, closing waits up to `linger` for unsent data, or resets the connection when it's zero.
+pub fn set_linger(fd: BorrowedFd<'_>, linger: Duration) -> Result<(), Error> {
+    let value = libc::linger {
+        l_onoff: 1,
+        l_linger: c_int::try_from(linger.as_secs()).unwrap_or(c_int::MAX),
+    };
+
+    let size: socklen_t = u32::try_from(size_of_val(&value)).unwrap();
+
+    // SAFETY: external call, value is valid for reads of `size` bytes
     let r: c_int = unsafe {
         setsockopt(
-            tcp_stream.as_raw_fd(),
+            fd.as_raw_fd(),
             SOL_SOCKET,
-            SO_RCVBUF,
+            SO_LINGER,
             (&raw const value).cast::<c_void>(),
             size,
         )
@@ -29,3 +109,223 @@ pub fn set_receive_buffer_size(tcp_stream: &TcpStream, size_in_bytes: usize) ->
 
     Ok(())
 }
+
+/// `SO_LINGER`, `None` when it's off.
+pub fn get_linger(fd: BorrowedFd<'_>) -> Result<Option<Duration>, Error> {
+    let mut value = libc::linger {
+        l_onoff: 0,
+        l_linger: 0,
+    };
+    let mut size: socklen_t = u32::try_from(size_of_val(&value)).unwrap();
+
+    // SAFETY: external call, value is valid for writes of `size` bytes
+    let r: c_int = unsafe {
+        getsockopt(
+            fd.as_raw_fd(),
+            SOL_SOCKET,
+            SO_LINGER,
+            (&raw mut value).cast::<c_void>(),
+            &raw mut size,
+        )
+    };
+
+    if r == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok((value.l_onoff != 0)
+        .then(|| Duration::from_secs(u64::try_from(value.l_linger).unwrap_or_default())))
+}
+
+/// `TCP_INFO`, what the kernel knows of the connection, see `tcp(7)`. Fields newer than the running kernel are left
+/// zeroed.
+pub fn get_tcp_info(fd: BorrowedFd<'_>) -> Result<tcp_info, Error> {
+    // SAFETY: all zeroes is a valid tcp_info
+    let mut value: tcp_info = unsafe { zeroed() };
+    let mut size: socklen_t = u32::try_from(size_of_val(&value)).unwrap();
+
+    // SAFETY: external call, value is valid for writes of `size` bytes
+    let r: c_int = unsafe {
+        getsockopt(
+            fd.as_raw_fd(),
+            IPPROTO_TCP,
+            TCP_INFO,
+            (&raw mut value).cast::<c_void>(),
+            &raw mut size,
+        )
+    };
+
+    if r == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(value)
+}
+
+/// Changes the backlog of the listening socket `fd`, calling `listen(2)` again on it does just that.
+pub fn set_backlog(fd: BorrowedFd<'_>, backlog: c_int) -> Result<(), Error> {
+    // SAFETY: external call, on a file descriptor that's open
+    if unsafe { listen(fd.as_raw_fd(), backlog) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Raises the soft limit on open files to the hard limit, every trapped client holds one. Returns the new soft limit.
+pub fn raise_open_files_limit() -> Result<rlim_t, Error> {
+    let mut limit = rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    // SAFETY: external call, limit is a valid output buffer
+    if unsafe { getrlimit(RLIMIT_NOFILE, &raw mut limit) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    if limit.rlim_cur < limit.rlim_max {
+        limit.rlim_cur = limit.rlim_max;
+
+        // SAFETY: external call, limit is fully initialized
+        if unsafe { setrlimit(RLIMIT_NOFILE, &raw const limit) } == -1 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    Ok(limit.rlim_cur)
+}
+
+/// Keeps `fd` from leaking into processes we start, like `sd_listen_fds` does for the sockets systemd passes.
+pub fn set_close_on_exec(fd: BorrowedFd<'_>) -> Result<(), Error> {
+    // SAFETY: external call, on a file descriptor that's open
+    let flags = unsafe { fcntl(fd.as_raw_fd(), F_GETFD) };
+
+    if flags == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    // SAFETY: external call, on a file descriptor that's open
+    if unsafe { fcntl(fd.as_raw_fd(), F_SETFD, flags | FD_CLOEXEC) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Calls the `getpwnam_r(3)` family member `lookup` with a buffer large enough. `None` when nothing is found.
+fn lookup<T>(
+    lookup: impl Fn(*mut T, *mut c_char, usize, *mut *mut T) -> c_int,
+) -> Result<Option<T>, Error> {
+    let mut buffer = vec![0; 1024];
+
+    loop {
+        let mut entry = MaybeUninit::<T>::uninit();
+        let mut result = ptr::null_mut();
+
+        let r = lookup(
+            entry.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &raw mut result,
+        );
+
+        match r {
+            0 if result.is_null() => return Ok(None),
+            // SAFETY: filled in, as it was found
+            0 => return Ok(Some(unsafe { entry.assume_init() })),
+            ERANGE => buffer.resize(buffer.len() * 2, 0),
+            _ => return Err(Error::from_raw_os_error(r)),
+        }
+    }
+}
+
+/// The uid and primary gid of the user `name`, see `getpwnam_r(3)`. `None` when there's no such user.
+pub fn lookup_user(name: &CStr) -> Result<Option<(uid_t, gid_t)>, Error> {
+    // SAFETY: external call, the buffers are valid for `size` bytes and outlive the entry, of which we only copy numbers
+    let user = lookup::<passwd>(|entry, buffer, size, result| unsafe {
+        getpwnam_r(name.as_ptr(), entry, buffer, size, result)
+    })?;
+
+    Ok(user.map(|user| (user.pw_uid, user.pw_gid)))
+}
+
+/// The gid of the group `name`, see `getgrnam_r(3)`. `None` when there's no such group.
+pub fn lookup_group(name: &CStr) -> Result<Option<gid_t>, Error> {
+    // SAFETY: external call, the buffers are valid for `size` bytes and outlive the entry, of which we only copy numbers
+    let group = lookup::<group>(|entry, buffer, size, result| unsafe {
+        getgrnam_r(name.as_ptr(), entry, buffer, size, result)
+    })?;
+
+    Ok(group.map(|group| group.gr_gid))
+}
+
+/// Switches every thread of the process to `uid` and `gid`, `gid` being the only group left. Switching away from root
+/// clears the capabilities, see `capabilities(7)`.
+pub fn switch_user(uid: uid_t, gid: gid_t) -> Result<(), Error> {
+    // glibc's wrappers apply these to every thread, unlike the system calls
+
+    // SAFETY: external call, gid is valid for reads of 1 gid_t
+    if unsafe { setgroups(1, &raw const gid) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    // SAFETY: external call
+    if unsafe { setgid(gid) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    // SAFETY: external call
+    if unsafe { setuid(uid) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Whether switching back to root works, it mustn't once we've switched away from it.
+pub fn can_regain_root() -> bool {
+    // SAFETY: external call
+    unsafe { setuid(0) == 0 }
+}
+
+/// Empties the calling thread's capability sets, ambient included, see `capset(2)`.
+pub fn clear_capabilities() -> Result<(), Error> {
+    let mut header = CapabilityHeader {
+        version: CAPABILITY_VERSION,
+        pid: 0,
+    };
+    let data = [CapabilityData::default(); 2];
+
+    // SAFETY: external call, header and data are valid for reads, data holds the 2 sets of version 3
+    if unsafe { syscall(SYS_capset, &raw mut header, data.as_ptr()) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    let (clear_all, unused): (c_ulong, c_ulong) = (PR_CAP_AMBIENT_CLEAR_ALL.try_into().unwrap(), 0);
+
+    // SAFETY: external call, with the 4 unused arguments set to 0, as required
+    if unsafe { prctl(PR_CAP_AMBIENT, clear_all, unused, unused, unused) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Whether the calling thread has any capability left, effective or permitted, see `capget(2)`.
+pub fn has_capabilities() -> Result<bool, Error> {
+    let mut header = CapabilityHeader {
+        version: CAPABILITY_VERSION,
+        pid: 0,
+    };
+    let mut data = [CapabilityData::default(); 2];
+
+    // SAFETY: external call, header is valid for reads, data for writes of the 2 sets of version 3
+    if unsafe { syscall(SYS_capget, &raw mut header, data.as_mut_ptr()) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(data
+        .iter()
+        .any(|data| data.effective != 0 || data.permitted != 0))
+}
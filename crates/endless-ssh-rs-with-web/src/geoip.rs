@@ -110,7 +110,7 @@ impl GeoIpReader {
     // TODO create replacer task
 }
 
-const GEO_IP_PATH: &str = "./.local/ip-database/GeoLite2-City.mmdb";
+pub const GEO_IP_PATH: &str = "./.local/ip-database/GeoLite2-City.mmdb";
 
 struct GeoIpDbWrapper {
     db: maxminddb::Reader<Mmap>,
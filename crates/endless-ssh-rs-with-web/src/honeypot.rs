@@ -0,0 +1,487 @@
+use std::borrow::Cow;
+use std::future::{Future, ready};
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::num::NonZeroU64;
+use std::os::fd::{AsFd as _, OwnedFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use color_eyre::eyre::{self, WrapErr as _};
+use russh::keys::ssh_key::{self, LineEnding};
+use russh::keys::{Algorithm, HashAlg, PrivateKey, PublicKey};
+use russh::server::{self, Auth, Handler, Response};
+use russh::{Disconnect, MethodKind, MethodSet, SshId};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::bandwidth::Bandwidth;
+use crate::client::{ClientContext, ClientSlot, DisconnectReason, Trapped};
+use crate::config::ListenerConfig;
+use crate::events::ClientEvent;
+use crate::protocol::ssh::SERVER_ID;
+use crate::scheduler::sleep_until_deadline;
+use crate::tcp_metrics::TcpMetrics;
+
+/// How a client tried to log in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    Password,
+    PublicKey,
+    KeyboardInteractive,
+}
+
+impl AuthMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuthMethod::Password => "password",
+            AuthMethod::PublicKey => "public-key",
+            AuthMethod::KeyboardInteractive => "keyboard-interactive",
+        }
+    }
+}
+
+impl FromStr for AuthMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "password" => Ok(AuthMethod::Password),
+            "public-key" => Ok(AuthMethod::PublicKey),
+            "keyboard-interactive" => Ok(AuthMethod::KeyboardInteractive),
+            _ => Err(format!("Unknown authentication method `{}`", s)),
+        }
+    }
+}
+
+/// A single (rejected) login attempt.
+#[derive(Debug, Clone)]
+pub struct AuthAttempt {
+    pub attempted_at: OffsetDateTime,
+    pub method: AuthMethod,
+    pub username: String,
+    /// Password, or keyboard-interactive response.
+    pub password: Option<String>,
+    /// SHA256 fingerprint of the offered public key, e.g. `SHA256:...`.
+    pub key_fingerprint: Option<String>,
+}
+
+/// Loads the host key, or generates (and stores) a new Ed25519 one when there is none yet,
+/// so the honeypot keeps its identity across restarts.
+pub fn load_or_generate_host_key(path: &Path) -> Result<PrivateKey, eyre::Report> {
+    match PrivateKey::read_openssh_file(path) {
+        Ok(host_key) => {
+            event!(Level::INFO, path = %path.display(), "Loaded SSH host key");
+
+            Ok(host_key)
+        },
+        Err(ssh_key::Error::Io(ErrorKind::NotFound)) => {
+            let host_key = PrivateKey::random(&mut ::rand::rng(), Algorithm::Ed25519)
+                .wrap_err("Failed to generate SSH host key")?;
+
+            host_key
+                .write_openssh_file(path, LineEnding::LF)
+                .wrap_err_with(|| format!("Failed to store SSH host key in {}", path.display()))?;
+
+            event!(Level::INFO, path = %path.display(), "Generated SSH host key");
+
+            Ok(host_key)
+        },
+        Err(error) => Err(eyre::Report::new(error).wrap_err(format!(
+            "Failed to load SSH host key from {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Every rejection takes `config.delay`, and the client gets to try as often as it likes.
+pub fn server_config(host_key: PrivateKey, config: &ListenerConfig) -> server::Config {
+    server::Config {
+        server_id: SshId::Standard(Cow::Borrowed(SERVER_ID)),
+        methods: MethodSet::from(
+            &[
+                MethodKind::Password,
+                MethodKind::PublicKey,
+                MethodKind::KeyboardInteractive,
+            ][..],
+        ),
+        auth_rejection_time: config.delay,
+        // the initial `none` probe only tells the client which methods to try
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        keys: vec![host_key],
+        max_auth_attempts: usize::MAX,
+        // the client can idle as long as it wants, as long as it's still there
+        inactivity_timeout: None,
+        keepalive_interval: Some(config.delay),
+        ..server::Config::default()
+    }
+}
+
+/// Records every attempt, and rejects it.
+struct Honeypot {
+    addr: SocketAddr,
+    listener: String,
+    attempts: Arc<Mutex<Vec<AuthAttempt>>>,
+    internal_events_tx: tokio::sync::mpsc::Sender<ClientEvent>,
+    /// Live updates only go out for recorded clients.
+    recorded: bool,
+}
+
+impl Honeypot {
+    fn record(
+        &self,
+        method: AuthMethod,
+        username: &str,
+        password: Option<&str>,
+        key_fingerprint: Option<String>,
+    ) -> Auth {
+        // Postgres text can't hold NUL
+        let attempt = AuthAttempt {
+            attempted_at: OffsetDateTime::now_utc(),
+            method,
+            username: username.replace('\0', ""),
+            password: password.map(|password| password.replace('\0', "")),
+            key_fingerprint,
+        };
+
+        event!(
+            Level::INFO,
+            addr = %self.addr,
+            listener = %self.listener,
+            method = method.as_str(),
+            username = %attempt.username,
+            "Authentication attempt"
+        );
+
+        // try_send: a full channel only costs the dashboard a live update, the attempt is stored on disconnect
+        if self.recorded {
+            let _r = self.internal_events_tx.try_send(ClientEvent::AuthAttempt {
+                addr: self.addr,
+                listener: self.listener.clone(),
+                attempt: attempt.clone(),
+            });
+        }
+
+        self.attempts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(attempt);
+
+        Auth::reject()
+    }
+}
+
+impl Handler for Honeypot {
+    type Error = russh::Error;
+
+    fn auth_password(
+        &mut self,
+        user: &str,
+        password: &str,
+    ) -> impl Future<Output = Result<Auth, Self::Error>> + Send {
+        ready(Ok(self.record(
+            AuthMethod::Password,
+            user,
+            Some(password),
+            None,
+        )))
+    }
+
+    /// The key is all we want, so it's rejected before the client gets to sign anything.
+    fn auth_publickey_offered(
+        &mut self,
+        user: &str,
+        public_key: &PublicKey,
+    ) -> impl Future<Output = Result<Auth, Self::Error>> + Send {
+        let key_fingerprint = public_key.fingerprint(HashAlg::Sha256).to_string();
+
+        ready(Ok(self.record(
+            AuthMethod::PublicKey,
+            user,
+            None,
+            Some(key_fingerprint),
+        )))
+    }
+
+    fn auth_keyboard_interactive<'a>(
+        &'a mut self,
+        user: &str,
+        _submethods: &str,
+        response: Option<Response<'a>>,
+    ) -> impl Future<Output = Result<Auth, Self::Error>> + Send {
+        let Some(mut response) = response else {
+            return ready(Ok(Auth::Partial {
+                name: Cow::Borrowed(""),
+                instructions: Cow::Borrowed(""),
+                prompts: Cow::Borrowed(&[(Cow::Borrowed("Password: "), false)]),
+            }));
+        };
+
+        let password = response
+            .next()
+            .map(|password| String::from_utf8_lossy(&password).into_owned());
+
+        ready(Ok(self.record(
+            AuthMethod::KeyboardInteractive,
+            user,
+            password.as_deref(),
+            None,
+        )))
+    }
+}
+
+/// Counts what russh sends, so honeypot connections show up in the totals and the bandwidth rate like any other, and
+/// tells once it's sent `max_bytes`.
+struct CountingStream {
+    inner: TcpStream,
+    bytes_sent: Arc<AtomicUsize>,
+    bandwidth: Arc<Bandwidth>,
+    max_bytes: Option<NonZeroU64>,
+    max_bytes_reached: CancellationToken,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = poll {
+            this.bandwidth.record(written);
+
+            let bytes_sent = this.bytes_sent.fetch_add(written, Ordering::Relaxed) + written;
+
+            if this.max_bytes.is_some_and(|max_bytes| {
+                u64::try_from(bytes_sent).unwrap_or(u64::MAX) >= max_bytes.get()
+            }) {
+                this.max_bytes_reached.cancel();
+            }
+        }
+
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Completes the handshake, and rejects every login attempt until the client gives up.
+pub async fn trap(
+    stream: TcpStream,
+    slot: &ClientSlot,
+    server_config: Arc<server::Config>,
+    context: &ClientContext,
+) -> Trapped {
+    let addr = slot.addr;
+    let connected_instant = Instant::now();
+
+    let bytes_sent = Arc::new(AtomicUsize::new(0));
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+
+    // russh closes the stream it's given, this keeps the socket around to read its metrics at the end
+    let socket: Option<OwnedFd> = stream
+        .as_fd()
+        .try_clone_to_owned()
+        .inspect_err(|error| {
+            event!(Level::DEBUG, %addr, ?error, "Failed to duplicate the client's socket");
+        })
+        .ok();
+
+    let max_bytes_reached = CancellationToken::new();
+
+    let stream = CountingStream {
+        inner: stream,
+        bytes_sent: Arc::clone(&bytes_sent),
+        bandwidth: Arc::clone(context.engine.bandwidth()),
+        max_bytes: slot.config.max_bytes,
+        max_bytes_reached: max_bytes_reached.clone(),
+    };
+
+    let honeypot = Honeypot {
+        addr,
+        listener: slot.config.name.clone(),
+        attempts: Arc::clone(&attempts),
+        internal_events_tx: context.internal_events_tx.clone(),
+        recorded: slot.recorded,
+    };
+
+    // the session ends there, as if we were shutting down
+    let deadline = slot
+        .config
+        .max_duration
+        .map(|max_duration| connected_instant + max_duration);
+    let mut limit_reached = None;
+
+    let session = tokio::select! {
+        biased;
+        () = context.cancellation_token.cancelled() => {
+            None
+        },
+        () = sleep_until_deadline(deadline) => {
+            limit_reached = Some(DisconnectReason::MaxDurationReached);
+
+            None
+        },
+        () = max_bytes_reached.cancelled() => {
+            limit_reached = Some(DisconnectReason::MaxBytesReached);
+
+            None
+        },
+        session = server::run_stream(server_config, stream, honeypot) => {
+            session.inspect_err(|error| {
+                event!(Level::TRACE, %addr, ?error, "Client gone during version exchange");
+            }).ok()
+        },
+    };
+
+    if let Some(session) = session {
+        let handle = session.handle();
+
+        let stop = tokio::select! {
+            biased;
+            () = context.cancellation_token.cancelled() => {
+                true
+            },
+            () = sleep_until_deadline(deadline) => {
+                limit_reached = Some(DisconnectReason::MaxDurationReached);
+
+                true
+            },
+            () = max_bytes_reached.cancelled() => {
+                limit_reached = Some(DisconnectReason::MaxBytesReached);
+
+                true
+            },
+            result = session => {
+                if let Err(error) = result {
+                    event!(Level::TRACE, %addr, ?error, "Session ended");
+                }
+
+                false
+            },
+        };
+
+        if stop {
+            // the session runs on its own task, tell it to stop
+            let _r = handle
+                .disconnect(Disconnect::ByApplication, String::new(), String::new())
+                .await;
+        }
+    }
+
+    let auth_attempts =
+        std::mem::take(&mut *attempts.lock().unwrap_or_else(PoisonError::into_inner));
+
+    Trapped {
+        time_spent: connected_instant.elapsed().try_into().unwrap_or_default(),
+        bytes_sent: bytes_sent.load(Ordering::Relaxed),
+        auth_attempts,
+        disconnect_reason: limit_reached.unwrap_or(DisconnectReason::ClientGone),
+        tcp_metrics: socket
+            .as_ref()
+            .and_then(|socket| TcpMetrics::read(socket.as_fd())),
+        ..Trapped::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use std::num::NonZeroU64;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::io::AsyncWriteExt as _;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::bandwidth::Bandwidth;
+    use crate::honeypot::{AuthMethod, CountingStream, load_or_generate_host_key};
+
+    #[test]
+    fn auth_method_round_trips() {
+        for method in [
+            AuthMethod::Password,
+            AuthMethod::PublicKey,
+            AuthMethod::KeyboardInteractive,
+        ] {
+            assert_eq!(method.as_str().parse(), Ok(method));
+        }
+    }
+
+    #[test]
+    fn keeps_generated_host_key() {
+        let path = std::env::temp_dir().join(format!(
+            "endless-ssh-rs-host-key-{:016x}",
+            ::rand::random::<u64>()
+        ));
+
+        let generated = load_or_generate_host_key(&path).unwrap();
+        let loaded = load_or_generate_host_key(&path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(generated.public_key(), loaded.public_key());
+    }
+
+    #[tokio::test]
+    async fn tells_once_max_bytes_are_sent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let inner = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let _client = listener.accept().await.unwrap();
+
+        let bytes_sent = Arc::new(AtomicUsize::new(0));
+        let max_bytes_reached = CancellationToken::new();
+
+        let mut stream = CountingStream {
+            inner,
+            bytes_sent: Arc::clone(&bytes_sent),
+            bandwidth: Arc::new(Bandwidth::new(None)),
+            max_bytes: NonZeroU64::new(8),
+            max_bytes_reached: max_bytes_reached.clone(),
+        };
+
+        stream.write_all(b"SSH-").await.unwrap();
+
+        assert!(!max_bytes_reached.is_cancelled());
+
+        stream.write_all(b"2.0-").await.unwrap();
+
+        assert!(max_bytes_reached.is_cancelled());
+        assert_eq!(bytes_sent.load(Ordering::Relaxed), 8);
+    }
+}
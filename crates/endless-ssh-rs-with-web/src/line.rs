@@ -1,9 +1,13 @@
+pub mod generator;
+
 use ::rand::distr::uniform::{SampleRange, SampleUniform};
 use rand::RngExt as _;
 use rand::rngs::ThreadRng;
 
+use crate::protocol::ssh::binary_packet;
+
 #[cfg_attr(test, expect(clippy::disallowed_types, reason = "Macro"))]
-mod get_random {
+pub mod get_random {
     use ::rand::distr::uniform::{SampleRange, SampleUniform};
 
     #[cfg_attr(test, mockall::automock)]
@@ -17,10 +21,16 @@ mod get_random {
 
 use get_random::GetRandom;
 
-struct GenRange<R> {
+pub struct GenRange<R> {
     rng: R,
 }
 
+impl Default for GenRange<ThreadRng> {
+    fn default() -> Self {
+        Self { rng: ::rand::rng() }
+    }
+}
+
 impl GetRandom for GenRange<ThreadRng> {
     fn gen_range<T, R>(&mut self, range: R) -> T
     where
@@ -31,100 +41,206 @@ impl GetRandom for GenRange<ThreadRng> {
     }
 }
 
-pub fn randline(maxlen: usize) -> Vec<u8> {
-    randline_from(GenRange { rng: ::rand::rng() }, maxlen)
+pub fn randgreeting(maxlen: usize) -> Vec<u8> {
+    randgreeting_from(GenRange { rng: ::rand::rng() }, maxlen)
 }
 
-fn randline_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
-    // original did 3 + rand(s) % (maxlen - 2)
-    // so if rand(2) was 47, maxlen 50, the outcome is 3 + (47 % 48)
-    // we have a length of 50
-    // with a range we don't need to do - 2
-    let len = rng.gen_range(3..=maxlen);
+fn randgreeting_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
+    // the `-` marks a continuation line, the final line would be `220 `, which we never send
+    const PREFIX: &[u8] = b"220-";
+    // prefix, CRLF, and at least one character of text
+    const MIN_LENGTH: usize = PREFIX.len() + 3;
+
+    let len = rng.gen_range(MIN_LENGTH..=maxlen.max(MIN_LENGTH));
 
-    let mut buffer = vec![0_u8; len];
+    let mut buffer = Vec::with_capacity(len);
 
-    for l in buffer.iter_mut().take(len - 2) {
+    buffer.extend_from_slice(PREFIX);
+
+    for _ in 0..len - PREFIX.len() - 2 {
         // ASCII 32 .. (including) ASCII 126
-        *l = rng.gen_range(32..=126);
+        buffer.push(rng.gen_range(32..=126));
     }
 
+    buffer.extend_from_slice(b"\r\n");
+
     buffer
-        .get_mut(len - 2..len)
-        .expect("minimum length is 3")
-        .copy_from_slice(&[13_u8, 10]);
+}
+
+/// Header name characters. Letters and digits only, which makes every name a valid RFC 9110 token.
+const HEADER_NAME_CHARACTERS: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+pub fn randheader(maxlen: usize) -> Vec<u8> {
+    randheader_from(GenRange { rng: ::rand::rng() }, maxlen)
+}
+
+fn randheader_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
+    // `X-`, `: ` and CRLF, plus at least one character of name and one of value
+    const OVERHEAD: usize = b"X-: \r\n".len();
+    const MIN_LENGTH: usize = OVERHEAD + 2;
+
+    let len = rng.gen_range(MIN_LENGTH..=maxlen.max(MIN_LENGTH));
+    let name_len = rng.gen_range(1..=len - MIN_LENGTH + 1);
+    let value_len = len - OVERHEAD - name_len;
+
+    let mut buffer = Vec::with_capacity(len);
+
+    buffer.extend_from_slice(b"X-");
 
-    // ensure start doesn't begin with "SSH-"
-    if buffer.starts_with(b"SSH-") {
-        buffer[0] = b'X';
+    for _ in 0..name_len {
+        let index = rng.gen_range(0..HEADER_NAME_CHARACTERS.len());
+
+        buffer.push(HEADER_NAME_CHARACTERS[index]);
+    }
+
+    buffer.extend_from_slice(b": ");
+
+    for _ in 0..value_len {
+        // ASCII 33 .. (including) ASCII 126, no spaces, as leading ones would get trimmed
+        buffer.push(rng.gen_range(33..=126));
     }
 
+    buffer.extend_from_slice(b"\r\n");
+
     buffer
 }
 
+/// See RFC 4253, section 11.2.
+const SSH_MSG_IGNORE: u8 = 2;
+
+pub fn randignore(maxlen: usize) -> Vec<u8> {
+    randignore_from(GenRange { rng: ::rand::rng() }, maxlen)
+}
+
+/// An `SSH_MSG_IGNORE` packet with random data, valid at any point of the connection.
+#[expect(clippy::big_endian_bytes, reason = "SSH is big-endian on the wire")]
+fn randignore_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
+    let len = rng.gen_range(1..=maxlen.max(1));
+
+    let mut payload = Vec::with_capacity(1 + 4 + len);
+
+    payload.push(SSH_MSG_IGNORE);
+    payload.extend_from_slice(
+        &u32::try_from(len)
+            .expect("line lengths are small")
+            .to_be_bytes(),
+    );
+
+    for _ in 0..len {
+        payload.push(rng.gen_range(0..=255));
+    }
+
+    binary_packet(&payload)
+}
+
+/// Telnet commands, see RFC 854.
+const IAC: u8 = 255;
+const DO: u8 = 253;
+const WILL: u8 = 251;
+
+/// Options bots are happy to keep negotiating: echo, suppress go ahead, terminal type,
+/// window size, terminal speed, remote flow control, linemode and (new) environment.
+const TELNET_OPTIONS: &[u8] = &[1, 3, 24, 31, 32, 33, 34, 36, 39];
+
+/// Pieces of the login prompts bots look for before sending their credentials.
+const LOGIN_PROMPT_FRAGMENTS: &[&[u8]] = &[
+    b"\r\n",
+    b"login: ",
+    b"Login: ",
+    b"Username: ",
+    b"Password: ",
+    b"\r\nLogin incorrect\r\n",
+    b"\r\nUser Access Verification\r\n\r\n",
+    b"BusyBox v1.19.4 (2014-03-10 12:39:26 CST) built-in shell (ash)\r\n",
+];
+
+pub fn randnegotiation(maxlen: usize) -> Vec<u8> {
+    randnegotiation_from(GenRange { rng: ::rand::rng() }, maxlen)
+}
+
+fn randnegotiation_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
+    // one in four drips is a prompt fragment, the rest keeps the bot negotiating
+    if rng.gen_range(0..4_u8) == 0 {
+        let fragment = LOGIN_PROMPT_FRAGMENTS[rng.gen_range(0..LOGIN_PROMPT_FRAGMENTS.len())];
+
+        return fragment[..fragment.len().min(maxlen)].to_vec();
+    }
+
+    // one negotiation per drip, whole even when `maxlen` is under its 3 bytes
+    let command = if rng.gen_range(0..2_u8) == 0 {
+        DO
+    } else {
+        WILL
+    };
+    let option = TELNET_OPTIONS[rng.gen_range(0..TELNET_OPTIONS.len())];
+
+    vec![IAC, command, option]
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ops::RangeInclusive;
+    use std::ops::{Range, RangeInclusive};
 
     use pretty_assertions::assert_eq;
 
     use crate::line::get_random::MockGetRandom;
-    use crate::line::randline_from;
+    use crate::line::{
+        DO, IAC, SSH_MSG_IGNORE, WILL, randgreeting_from, randheader_from, randignore_from,
+        randnegotiation_from,
+    };
 
     #[test]
-    fn randline() {
+    fn randheader() {
         // given
         // mock rng
-        let mut mock_rng = MockGetRandom::new();
+        let mut ctx = MockGetRandom::new();
 
-        // set random length to requested maximum length
-        mock_rng
-            .expect_gen_range::<usize, RangeInclusive<usize>>()
+        // set random length to requested maximum length, and the name takes all but one byte of the payload
+        ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
             .returning(|x| *x.end());
 
-        // every byte will be 97, or 'a'
-        mock_rng
-            .expect_gen_range::<u8, RangeInclusive<u8>>()
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .return_const(0_usize);
+
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
             .return_const(b'a');
 
-        let max_len = 50;
-        let randline = randline_from(mock_rng, max_len);
+        // when
+        let max_len = 20;
+        let randheader = randheader_from(ctx, max_len);
 
         // then
-        // did we get a line our length?
-        assert_eq!(randline.len(), max_len);
+        assert_eq!(randheader.len(), max_len);
 
-        // since we mocked the Rng, we can make sure that the line is as we expect it is
-        assert_eq!(randline[..(max_len - 2)], vec![b'a'; max_len - 2]);
+        assert_eq!(randheader, b"X-AAAAAAAAAAAAA: a\r\n");
     }
 
     #[test]
-    fn randline_carriage_return_line_feed() {
+    fn randheader_below_minimum_length() {
         // given
         // mock rng
         let mut ctx = MockGetRandom::new();
 
-        // set random length to requested maximum length
         ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
             .returning(|x| *x.end());
 
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .return_const(1_usize);
+
         ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
-            .return_const(b'a');
+            .return_const(b'b');
 
         // when
-        let max_len = 50;
-        let randline = randline_from(ctx, max_len);
+        let randheader = randheader_from(ctx, 3);
 
         // then
-        // did we get a line our length?
-        assert_eq!(randline.len(), max_len);
-
-        // do we end in a CRLF linebreak?
-        assert_eq!(randline[(max_len - 2)..], [b'\r', b'\n']);
+        // name and value need at least a character each
+        assert_eq!(randheader, b"X-B: b\r\n");
     }
 
     #[test]
-    fn randline_no_ssh_prefix() {
+    fn randgreeting() {
         // given
         // mock rng
         let mut ctx = MockGetRandom::new();
@@ -133,27 +249,139 @@ mod tests {
         ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
             .returning(|x| *x.end());
 
-        let fake_randoms = *b"SSH-";
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(b'a');
+
+        // when
+        let max_len = 12;
+        let randgreeting = randgreeting_from(ctx, max_len);
+
+        // then
+        // a continuation line, never the final `220 ` line
+        assert_eq!(randgreeting, b"220-aaaaaa\r\n");
+    }
+
+    #[test]
+    fn randgreeting_below_minimum_length() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
+            .returning(|x| *x.end());
 
         ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(b'a');
+
+        // when
+        let randgreeting = randgreeting_from(ctx, 3);
+
+        // then
+        assert_eq!(randgreeting, b"220-a\r\n");
+    }
+
+    #[test]
+    fn randnegotiation() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // negotiation rather than prompt, then `WILL`
+        let mut picks = [1_u8, 1].into_iter();
+
+        ctx.expect_gen_range::<u8, Range<u8>>()
+            .times(2)
+            .returning(move |_| picks.next().unwrap());
+
+        // the second option, suppress go ahead
+        ctx.expect_gen_range::<usize, Range<usize>>()
             .times(1)
-            .return_const(fake_randoms[0]);
-        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(1_usize);
+
+        // when
+        let randnegotiation = randnegotiation_from(ctx, 80);
+
+        // then
+        // a single one, however much would fit
+        assert_eq!(randnegotiation, [IAC, WILL, 3]);
+    }
+
+    #[test]
+    fn randnegotiation_whole() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // negotiation rather than prompt, then `DO`
+        let mut picks = [1_u8, 0].into_iter();
+
+        ctx.expect_gen_range::<u8, Range<u8>>()
+            .times(2)
+            .returning(move |_| picks.next().unwrap());
+
+        // the first option, echo
+        ctx.expect_gen_range::<usize, Range<usize>>()
             .times(1)
-            .return_const(fake_randoms[1]);
-        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(0_usize);
+
+        // when
+        let randnegotiation = randnegotiation_from(ctx, 2);
+
+        // then
+        // not cut to the maximum length
+        assert_eq!(randnegotiation, [IAC, DO, 1]);
+    }
+
+    #[test]
+    fn randnegotiation_prompt_fragment() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // prompt rather than negotiation
+        ctx.expect_gen_range::<u8, Range<u8>>()
             .times(1)
-            .return_const(fake_randoms[2]);
-        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(0_u8);
+
+        // `Password: `
+        ctx.expect_gen_range::<usize, Range<usize>>()
             .times(1)
-            .return_const(fake_randoms[3]);
+            .return_const(4_usize);
 
-        let max_len = 6;
-        let randline = randline_from(ctx, max_len);
+        // when
+        let randnegotiation = randnegotiation_from(ctx, 5);
 
-        assert_eq!(randline.len(), max_len);
+        // then
+        // capped to the maximum length
+        assert_eq!(randnegotiation, b"Passw");
+    }
 
-        let xsh = *b"XSH-";
-        assert_eq!(randline[..xsh.len()], xsh);
+    #[test]
+    fn randignore() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // set random length to requested maximum length
+        ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
+            .returning(|x| *x.end());
+
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(b'a');
+
+        // when
+        let randignore = randignore_from(ctx, 3);
+
+        // then
+        // packet length, padding length, the message, the data as a string, and zeroed padding
+        assert_eq!(
+            randignore,
+            [
+                &[0, 0, 0, 20, 11, SSH_MSG_IGNORE, 0, 0, 0, 3][..],
+                b"aaa",
+                &[0; 11]
+            ]
+            .concat()
+        );
     }
 }
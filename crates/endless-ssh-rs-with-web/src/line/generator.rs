@@ -0,0 +1,440 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use color_eyre::eyre::{self, WrapErr as _};
+
+use crate::line::GenRange;
+use crate::line::get_random::GetRandom;
+
+/// Where the banner lines of a listener come from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LineSource {
+    /// Random printable ASCII.
+    #[default]
+    Random,
+    /// Lines picked from a file, e.g. fake MOTDs or legal notices.
+    Corpus(PathBuf),
+    /// Lines made up by a Markov chain trained on a file.
+    Markov(PathBuf),
+}
+
+impl Display for LineSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            LineSource::Random => f.write_str("random"),
+            LineSource::Corpus(ref path) => write!(f, "corpus:{}", path.display()),
+            LineSource::Markov(ref path) => write!(f, "markov:{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for LineSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "random" => Ok(LineSource::Random),
+            Some(("corpus", path)) if !path.is_empty() => Ok(LineSource::Corpus(path.into())),
+            Some(("markov", path)) if !path.is_empty() => Ok(LineSource::Markov(path.into())),
+            _ => Err(format!(
+                "Unknown line generator `{}`, expected `random`, `corpus:PATH` or `markov:PATH`",
+                s
+            )),
+        }
+    }
+}
+
+/// Makes up banner lines.
+pub trait GenerateLine {
+    /// The text of the next line. Doesn't need to fit, or be safe to send, [`GenerateLine::line`] takes care of that.
+    fn text(&self, rng: &mut impl GetRandom, max_length: usize) -> Vec<u8>;
+
+    /// At most `max_length` bytes, ending in CRLF, and never starting with `SSH-`, see RFC 4253, section 4.2.
+    fn line(&self, rng: &mut impl GetRandom, max_length: usize) -> Vec<u8> {
+        let mut buffer = self.text(rng, max_length);
+
+        // a CR or LF would end the line early, the other control characters have no business in a banner
+        buffer.retain(|b| !b.is_ascii_control());
+
+        buffer.truncate(floor_char_boundary(&buffer, max_length.saturating_sub(2)));
+
+        // ensure start doesn't begin with "SSH-"
+        if buffer.starts_with(b"SSH-") {
+            buffer[0] = b'X';
+        }
+
+        buffer.extend_from_slice(b"\r\n");
+
+        buffer
+    }
+}
+
+/// The largest index at or below `index` that doesn't split a UTF-8 sequence.
+fn floor_char_boundary(buffer: &[u8], index: usize) -> usize {
+    if index >= buffer.len() {
+        return buffer.len();
+    }
+
+    // continuation bytes look like 0b10xx_xxxx
+    (0..=index)
+        .rev()
+        .find(|&i| buffer[i] & 0b1100_0000 != 0b1000_0000)
+        .unwrap_or(0)
+}
+
+/// Random printable ASCII, what the tarpit always sent.
+pub struct RandomLines;
+
+impl GenerateLine for RandomLines {
+    fn text(&self, rng: &mut impl GetRandom, max_length: usize) -> Vec<u8> {
+        // original did 3 + rand(s) % (maxlen - 2)
+        // so if rand(2) was 47, maxlen 50, the outcome is 3 + (47 % 48)
+        // we have a length of 50
+        // with a range we don't need to do - 2
+        let len = rng.gen_range(3..=max_length);
+
+        // ASCII 32 .. (including) ASCII 126
+        std::iter::repeat_with(|| rng.gen_range(32..=126))
+            .take(len - 2)
+            .collect()
+    }
+}
+
+/// Reads `path` as text, every line trimmed of trailing whitespace.
+fn read_lines(path: &Path) -> Result<Vec<String>, eyre::Report> {
+    let text = std::fs::read(path)
+        .wrap_err_with(|| format!("Failed to read line generator file {}", path.display()))?;
+
+    let lines: Vec<String> = String::from_utf8_lossy(&text)
+        .lines()
+        .map(|line| line.trim_end().to_owned())
+        .collect();
+
+    if lines.iter().all(String::is_empty) {
+        return Err(eyre::eyre!(
+            "Line generator file {} has no text",
+            path.display()
+        ));
+    }
+
+    Ok(lines)
+}
+
+/// Picks random lines from a file, blank ones included, they are part of a MOTD too.
+pub struct CorpusLines {
+    lines: Vec<String>,
+}
+
+impl CorpusLines {
+    pub fn load(path: &Path) -> Result<Self, eyre::Report> {
+        Ok(Self {
+            lines: read_lines(path)?,
+        })
+    }
+}
+
+impl GenerateLine for CorpusLines {
+    fn text(&self, rng: &mut impl GetRandom, _max_length: usize) -> Vec<u8> {
+        self.lines[rng.gen_range(0..self.lines.len())]
+            .as_bytes()
+            .to_vec()
+    }
+}
+
+/// Marks both the start and the end of a line in [`MarkovLines`].
+const BOUNDARY: usize = 0;
+
+/// A word level Markov chain, each word picked based on the two before it.
+pub struct MarkovLines {
+    /// Every word seen while training, [`BOUNDARY`] at index 0.
+    words: Vec<String>,
+    /// The words seen after a pair of words, repeated as often as they were seen.
+    transitions: BTreeMap<(usize, usize), Vec<usize>>,
+}
+
+impl MarkovLines {
+    pub fn load(path: &Path) -> Result<Self, eyre::Report> {
+        Ok(Self::train(&read_lines(path)?))
+    }
+
+    /// Every line of `text` is trained as a sentence of its own.
+    fn train(text: &[String]) -> Self {
+        let mut words = vec![String::new()];
+        let mut indices = BTreeMap::new();
+        let mut transitions: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+
+        for line in text {
+            let mut previous = (BOUNDARY, BOUNDARY);
+
+            for word in line.split_whitespace() {
+                let index = *indices.entry(word).or_insert_with(|| {
+                    words.push(word.to_owned());
+
+                    words.len() - 1
+                });
+
+                transitions.entry(previous).or_default().push(index);
+
+                previous = (previous.1, index);
+            }
+
+            if previous != (BOUNDARY, BOUNDARY) {
+                transitions.entry(previous).or_default().push(BOUNDARY);
+            }
+        }
+
+        Self { words, transitions }
+    }
+}
+
+impl GenerateLine for MarkovLines {
+    fn text(&self, rng: &mut impl GetRandom, max_length: usize) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut previous = (BOUNDARY, BOUNDARY);
+
+        // every line has at least one word, `line` cuts it short when it doesn't fit
+        while let Some(next) = self.transitions.get(&previous) {
+            let index = next[rng.gen_range(0..next.len())];
+
+            if index == BOUNDARY {
+                break;
+            }
+
+            let word = self.words[index].as_bytes();
+
+            if !buffer.is_empty() {
+                if buffer.len() + 1 + word.len() + 2 > max_length {
+                    break;
+                }
+
+                buffer.push(b' ');
+            }
+
+            buffer.extend_from_slice(word);
+
+            previous = (previous.1, index);
+        }
+
+        buffer
+    }
+}
+
+/// A listener's [`LineSource`], loaded.
+pub enum LineGenerator {
+    Random(RandomLines),
+    Corpus(CorpusLines),
+    Markov(MarkovLines),
+}
+
+impl Default for LineGenerator {
+    fn default() -> Self {
+        LineGenerator::Random(RandomLines)
+    }
+}
+
+impl LineGenerator {
+    pub fn load(source: &LineSource) -> Result<Self, eyre::Report> {
+        match *source {
+            LineSource::Random => Ok(LineGenerator::Random(RandomLines)),
+            LineSource::Corpus(ref path) => Ok(LineGenerator::Corpus(CorpusLines::load(path)?)),
+            LineSource::Markov(ref path) => Ok(LineGenerator::Markov(MarkovLines::load(path)?)),
+        }
+    }
+
+    pub fn line(&self, max_length: usize) -> Vec<u8> {
+        let mut rng = GenRange { rng: ::rand::rng() };
+
+        match *self {
+            LineGenerator::Random(ref generator) => generator.line(&mut rng, max_length),
+            LineGenerator::Corpus(ref generator) => generator.line(&mut rng, max_length),
+            LineGenerator::Markov(ref generator) => generator.line(&mut rng, max_length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Range, RangeInclusive};
+    use std::path::PathBuf;
+
+    use pretty_assertions::{assert_eq, assert_matches};
+
+    use crate::line::generator::{
+        CorpusLines, GenerateLine as _, LineSource, MarkovLines, RandomLines,
+    };
+    use crate::line::get_random::MockGetRandom;
+
+    #[test]
+    fn randline() {
+        // given
+        // mock rng
+        let mut mock_rng = MockGetRandom::new();
+
+        // set random length to requested maximum length
+        mock_rng
+            .expect_gen_range::<usize, RangeInclusive<usize>>()
+            .returning(|x| *x.end());
+
+        // every byte will be 97, or 'a'
+        mock_rng
+            .expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(b'a');
+
+        let max_len = 50;
+        let randline = RandomLines.line(&mut mock_rng, max_len);
+
+        // then
+        // did we get a line our length?
+        assert_eq!(randline.len(), max_len);
+
+        // since we mocked the Rng, we can make sure that the line is as we expect it is
+        assert_eq!(randline[..(max_len - 2)], vec![b'a'; max_len - 2]);
+    }
+
+    #[test]
+    fn randline_carriage_return_line_feed() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // set random length to requested maximum length
+        ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
+            .returning(|x| *x.end());
+
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .return_const(b'a');
+
+        // when
+        let max_len = 50;
+        let randline = RandomLines.line(&mut ctx, max_len);
+
+        // then
+        // did we get a line our length?
+        assert_eq!(randline.len(), max_len);
+
+        // do we end in a CRLF linebreak?
+        assert_eq!(randline[(max_len - 2)..], [b'\r', b'\n']);
+    }
+
+    #[test]
+    fn randline_no_ssh_prefix() {
+        // given
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // set random length to requested maximum length
+        ctx.expect_gen_range::<usize, RangeInclusive<usize>>()
+            .returning(|x| *x.end());
+
+        let fake_randoms = *b"SSH-";
+
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .times(1)
+            .return_const(fake_randoms[0]);
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .times(1)
+            .return_const(fake_randoms[1]);
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .times(1)
+            .return_const(fake_randoms[2]);
+        ctx.expect_gen_range::<u8, RangeInclusive<u8>>()
+            .times(1)
+            .return_const(fake_randoms[3]);
+
+        let max_len = 6;
+        let randline = RandomLines.line(&mut ctx, max_len);
+
+        assert_eq!(randline.len(), max_len);
+
+        let xsh = *b"XSH-";
+        assert_eq!(randline[..xsh.len()], xsh);
+    }
+
+    #[test]
+    fn corpus_lines_are_safe_to_send() {
+        // given
+        let corpus = CorpusLines {
+            lines: vec![
+                "SSH-2.0-OpenSSH_9.6".to_owned(),
+                "Authorized\r\nuse only".to_owned(),
+                "\u{dc}berwachung aktiv".to_owned(),
+            ],
+        };
+
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        let mut picks = [0_usize, 1, 2].into_iter();
+
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .returning(move |_| picks.next().unwrap());
+
+        // then
+        // never a version line
+        assert_eq!(corpus.line(&mut ctx, 64), b"XSH-2.0-OpenSSH_9.6\r\n");
+
+        // no line breaks in the middle
+        assert_eq!(corpus.line(&mut ctx, 64), b"Authorizeduse only\r\n");
+
+        // cut to the maximum length, on a character boundary
+        assert_eq!(corpus.line(&mut ctx, 3), b"\r\n");
+    }
+
+    #[test]
+    fn markov_lines_follow_the_training_text() {
+        // given
+        let markov = MarkovLines::train(&[
+            "all access is logged".to_owned(),
+            String::new(),
+            "all access is monitored".to_owned(),
+        ]);
+
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        // always the last word seen
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .returning(|x| x.end - 1);
+
+        // when
+        let line = markov.line(&mut ctx, 64);
+
+        // then
+        assert_eq!(line, b"all access is monitored\r\n");
+    }
+
+    #[test]
+    fn markov_lines_stop_at_a_word() {
+        // given
+        let markov = MarkovLines::train(&["all access is logged".to_owned()]);
+
+        // mock rng
+        let mut ctx = MockGetRandom::new();
+
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .return_const(0_usize);
+
+        // when
+        let line = markov.line(&mut ctx, 14);
+
+        // then
+        assert_eq!(line, b"all access\r\n");
+    }
+
+    #[test]
+    fn parses_line_source() {
+        assert_eq!("random".parse(), Ok(LineSource::Random));
+        assert_eq!(
+            "corpus:/etc/motd".parse(),
+            Ok(LineSource::Corpus(PathBuf::from("/etc/motd")))
+        );
+        assert_eq!(
+            "markov:notices.txt".parse(),
+            Ok(LineSource::Markov(PathBuf::from("notices.txt")))
+        );
+        assert_matches!("corpus:".parse::<LineSource>(), Err(_));
+        assert_matches!("lorem".parse::<LineSource>(), Err(_));
+    }
+}
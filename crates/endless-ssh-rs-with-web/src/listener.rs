@@ -1,62 +1,499 @@
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::os::fd::AsFd as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 use color_eyre::eyre;
+use russh::keys::PrivateKey;
+use russh::server;
 use time::OffsetDateTime;
-use tokio::net::TcpListener;
-use tokio::sync::{Semaphore, TryAcquireError};
+use tokio::io::AsyncWriteExt as _;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
 use tracing::{Level, event};
 
-use crate::SIZE_IN_BYTES;
-use crate::client::{ClientContext, handle_client};
-use crate::config::Config;
+use crate::access::Access;
+use crate::admission::{AdmissionPolicy, Evict, EvictionReason, SessionSlot, Sessions};
+use crate::client::{ClientContext, ClientSlot, handle_client};
+use crate::config::ListenerConfig;
 use crate::events::ClientEvent;
-use crate::ffi_wrapper::set_receive_buffer_size;
+use crate::honeypot::{self, load_or_generate_host_key};
+use crate::line::generator::LineGenerator;
+use crate::protocol::Protocol;
+use crate::proxy_protocol;
+use crate::scheduler::Engine;
+use crate::socket_options::SocketOptions;
+use crate::source_limits::{Excess, ExcessAction, SourceCounts, SourceSlot};
+use crate::task_tracker_ext::TaskTrackerExt as _;
+
+/// How long a new client waits for the slot of the client it evicted.
+const EVICTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Lines a connection over its source's limits gets with [`ExcessAction::Tarpit`], one per delay.
+const TURNED_AWAY_LINES: usize = 3;
+/// Connections over their source's limits a listener tarpits at once, those past it are closed.
+const TURNED_AWAY_MAX: usize = 256;
+/// Connections a listener waits for the PROXY header of at once, those past it are closed.
+const PENDING_HEADERS_MAX: usize = 256;
+
+/// Slots given up in a reload while clients held them, taken back as those leave, see [`resize`].
+#[derive(Debug, Default)]
+struct OwedSlots {
+    count: usize,
+    /// Stops the task taking them back, when a later reload gives them back first.
+    reclaim: Option<CancellationToken>,
+}
+
+/// Grows or shrinks `semaphore` from `previous` to `slots` permits. Held permits can't be taken back, those are owed,
+/// and a task forgets them as they're released. Growing pays the owed slots back first.
+fn resize(
+    semaphore: &Arc<Semaphore>,
+    owed: &Arc<Mutex<OwedSlots>>,
+    previous: usize,
+    slots: usize,
+    client_task_tracker: &TaskTracker,
+    cancellation_token: &CancellationToken,
+) {
+    match slots.cmp(&previous) {
+        Ordering::Greater => {
+            let mut owed = owed.lock().unwrap_or_else(PoisonError::into_inner);
+
+            let grown = slots - previous;
+            let paid = grown.min(owed.count);
+
+            owed.count -= paid;
+
+            if owed.count == 0
+                && let Some(reclaim) = owed.reclaim.take()
+            {
+                reclaim.cancel();
+            }
+
+            semaphore.add_permits(grown - paid);
+        },
+        Ordering::Less => {
+            let shrunk = previous - slots;
+            let held = shrunk - semaphore.forget_permits(shrunk);
+
+            if held == 0 {
+                return;
+            }
+
+            let mut owed_slots = owed.lock().unwrap_or_else(PoisonError::into_inner);
+
+            owed_slots.count += held;
+
+            if owed_slots.reclaim.is_some() {
+                return;
+            }
+
+            let reclaim = cancellation_token.child_token();
+
+            owed_slots.reclaim = Some(reclaim.clone());
+
+            let semaphore = Arc::clone(semaphore);
+            let owed = Arc::clone(owed);
+
+            client_task_tracker.spawn(async move {
+                loop {
+                    let permit = tokio::select! {
+                        biased;
+                        () = reclaim.cancelled() => {
+                            return;
+                        },
+                        result = Arc::clone(&semaphore).acquire_owned() => {
+                            match result {
+                                Ok(permit) => permit,
+                                Err(_closed) => return,
+                            }
+                        },
+                    };
+
+                    let mut owed = owed.lock().unwrap_or_else(PoisonError::into_inner);
+
+                    // paid back by a reload in the meantime, the permit goes back with the drop
+                    if owed.count == 0 {
+                        return;
+                    }
+
+                    permit.forget();
+
+                    owed.count -= 1;
+
+                    if owed.count == 0 {
+                        owed.reclaim = None;
+
+                        return;
+                    }
+                }
+            });
+        },
+        Ordering::Equal => {},
+    }
+}
+
+/// Allowlisted clients are trapped without the dashboard and the statistics hearing of them.
+fn recorded(config: &ListenerConfig, addr: SocketAddr) -> bool {
+    config.access.check(addr.ip()) != Access::Unrecorded
+}
+
+/// What a listener's clients get, replaced when the configuration is reloaded.
+#[derive(Clone)]
+pub struct ListenerSettings {
+    pub config: Arc<ListenerConfig>,
+    /// The SSH server configuration of `ssh-honeypot` listeners, it depends on the delay.
+    pub honeypot: Option<Arc<server::Config>>,
+}
+
+struct Running {
+    config: Arc<ListenerConfig>,
+    settings: watch::Sender<ListenerSettings>,
+    stop: CancellationToken,
+    handle: JoinHandle<Option<std::net::TcpListener>>,
+}
+
+impl Running {
+    /// Stops the listener, its clients stay. Returns its socket.
+    async fn stop(self) -> Option<std::net::TcpListener> {
+        self.stop.cancel();
+
+        self.handle.await.ok().flatten()
+    }
+}
+
+/// The running listeners by name, started, updated and stopped as the configuration is reloaded.
+pub struct Listeners {
+    running: BTreeMap<String, Running>,
+    /// Loaded once a listener is an `ssh-honeypot` one.
+    host_key: Option<PrivateKey>,
+    engine: Arc<Engine>,
+    tasks: TaskTracker,
+    cancellation_token: CancellationToken,
+    client_tasks: TaskTracker,
+    client_cancellation_token: CancellationToken,
+    internal_events_tx: Sender<ClientEvent>,
+}
+
+impl Listeners {
+    /// Listeners run on `tasks`, their clients on `client_tasks`.
+    pub fn new(
+        host_key: Option<PrivateKey>,
+        engine: Arc<Engine>,
+        tasks: TaskTracker,
+        cancellation_token: CancellationToken,
+        client_tasks: TaskTracker,
+        client_cancellation_token: CancellationToken,
+        internal_events_tx: Sender<ClientEvent>,
+    ) -> Self {
+        Self {
+            running: BTreeMap::new(),
+            host_key,
+            engine,
+            tasks,
+            cancellation_token,
+            client_tasks,
+            client_cancellation_token,
+            internal_events_tx,
+        }
+    }
+
+    /// What `config`'s clients get, loading the SSH host key from `ssh_host_key` if it's the first `ssh-honeypot` listener.
+    pub fn settings(
+        &mut self,
+        config: Arc<ListenerConfig>,
+        ssh_host_key: &Path,
+    ) -> Result<ListenerSettings, eyre::Report> {
+        let honeypot = if config.protocol == Protocol::SshHoneypot {
+            if self.host_key.is_none() {
+                self.host_key = Some(load_or_generate_host_key(ssh_host_key)?);
+            }
+
+            self.host_key
+                .clone()
+                .map(|host_key| Arc::new(honeypot::server_config(host_key, &config)))
+        } else {
+            None
+        };
+
+        Ok(ListenerSettings { config, honeypot })
+    }
+
+    /// Starts listening with `settings`, on `socket` if given. A listener that fails stops everything else.
+    pub fn start(
+        &mut self,
+        settings: ListenerSettings,
+        socket: Option<std::net::TcpListener>,
+        lines: Arc<LineGenerator>,
+    ) {
+        let config = Arc::clone(&settings.config);
+        let (settings, updates) = watch::channel(settings);
+        let stop = self.client_cancellation_token.child_token();
+
+        let cancellation_token = self.cancellation_token.clone();
+        let client_cancellation_token = self.client_cancellation_token.clone();
+        let client_tasks = self.client_tasks.clone();
+        let internal_events_tx = self.internal_events_tx.clone();
+        let engine = Arc::clone(&self.engine);
+
+        let handle = self
+            .tasks
+            .spawn_with_name(&format!("connection listener {}", config.name), {
+                let stop = stop.clone();
+
+                async move {
+                    let guard = cancellation_token.drop_guard_ref();
+                    let client_guard = client_cancellation_token.clone().drop_guard();
+
+                    let socket = listen_for_new_connections(
+                        updates,
+                        socket,
+                        lines,
+                        engine,
+                        stop.clone(),
+                        client_cancellation_token,
+                        client_tasks,
+                        internal_events_tx,
+                    )
+                    .await;
+
+                    // stopped on purpose, not a failure
+                    if stop.is_cancelled() {
+                        guard.disarm();
+                        client_guard.disarm();
+                    }
+
+                    socket
+                }
+            });
+
+        self.running.insert(
+            config.name.clone(),
+            Running {
+                config,
+                settings,
+                stop,
+                handle,
+            },
+        );
+    }
+
+    /// The configuration of the running listener `name`.
+    pub fn config(&self, name: &str) -> Option<&ListenerConfig> {
+        self.running.get(name).map(|running| &*running.config)
+    }
+
+    /// Whether a running listener listens on `address`, its socket goes to the listener that takes it over.
+    pub fn is_bound(&self, address: SocketAddr) -> bool {
+        self.running
+            .values()
+            .any(|running| running.config.address == address)
+    }
+
+    /// Applies the reloaded `configs`. Listeners that are gone stop, new ones start. Changes to a listener's address,
+    /// protocol or line generator restart it, the others reach it and its clients live. Trapped clients stay either way.
+    pub async fn reload(&mut self, configs: Vec<ListenerConfig>, ssh_host_key: &Path) {
+        // sockets of stopped listeners, for those that listen on the same address
+        let mut freed = Vec::new();
+
+        let removed = self
+            .running
+            .keys()
+            .filter(|name| !configs.iter().any(|config| config.name == **name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for name in removed {
+            if let Some(running) = self.running.remove(&name) {
+                event!(Level::INFO, listener = %name, "Listener removed, stopping it");
+
+                freed.extend(running.stop().await);
+            }
+        }
+
+        for config in configs {
+            let config = Arc::new(config);
+
+            if let Some(running) = self.running.get(&config.name) {
+                if running.config == config {
+                    continue;
+                }
+
+                if running.config.address == config.address
+                    && running.config.protocol == config.protocol
+                    && running.config.line_generator == config.line_generator
+                {
+                    match self.settings(Arc::clone(&config), ssh_host_key) {
+                        Ok(settings) => {
+                            if let Some(running) = self.running.get_mut(&config.name) {
+                                running.settings.send_replace(settings);
+                                running.config = config;
+                            }
+                        },
+                        Err(error) => {
+                            event!(Level::ERROR, listener = %config.name, ?error, "Failed to reload listener, keeping it as it was");
+                        },
+                    }
+
+                    continue;
+                }
+            }
+
+            self.restart(config, ssh_host_key, &mut freed).await;
+        }
+    }
+
+    /// Starts the listener for `config`, in place of the running one of the same name.
+    async fn restart(
+        &mut self,
+        config: Arc<ListenerConfig>,
+        ssh_host_key: &Path,
+        freed: &mut Vec<std::net::TcpListener>,
+    ) {
+        let lines = match LineGenerator::load(&config.line_generator) {
+            Ok(lines) => Arc::new(lines),
+            Err(error) => {
+                event!(Level::ERROR, listener = %config.name, ?error, "Failed to load line generator, keeping listener as it was");
+
+                return;
+            },
+        };
+
+        let settings = match self.settings(Arc::clone(&config), ssh_host_key) {
+            Ok(settings) => settings,
+            Err(error) => {
+                event!(Level::ERROR, listener = %config.name, ?error, "Failed to set up SSH host key, keeping listener as it was");
+
+                return;
+            },
+        };
+
+        // the new listener takes over the running one's socket
+        if self
+            .running
+            .get(&config.name)
+            .is_some_and(|running| running.config.address == config.address)
+            && let Some(running) = self.running.remove(&config.name)
+        {
+            freed.extend(running.stop().await);
+        }
+
+        let socket = if let Some(index) = freed.iter().position(|socket| {
+            socket
+                .local_addr()
+                .is_ok_and(|address| address == config.address)
+        }) {
+            freed.swap_remove(index)
+        } else {
+            match TcpListener::bind(config.address)
+                .await
+                .and_then(TcpListener::into_std)
+            {
+                Ok(socket) => socket,
+                Err(error) => {
+                    event!(Level::ERROR, listener = %config.name, ?error, "Failed to bind, keeping listener as it was");
+
+                    return;
+                },
+            }
+        };
+
+        if let Some(running) = self.running.remove(&config.name) {
+            freed.extend(running.stop().await);
+        }
+
+        event!(Level::INFO, listener = %config.name, "Starting listener");
+
+        config.log();
+
+        self.start(settings, Some(socket), lines);
+    }
+}
 
 struct Listener {
-    config: Arc<Config>,
+    settings: watch::Receiver<ListenerSettings>,
     #[expect(clippy::struct_field_names, reason = "Clarity")]
     tcp_listener: TcpListener,
     client_task_tracker: TaskTracker,
     cancellation_token: CancellationToken,
-    internal_events_tx: tokio::sync::mpsc::Sender<ClientEvent>,
+    internal_events_tx: Sender<ClientEvent>,
     semaphore: Arc<Semaphore>,
+    owed_slots: Arc<Mutex<OwedSlots>>,
+    sources: Arc<SourceCounts>,
+    /// Slots for connections over their source's limits, see [`TURNED_AWAY_MAX`].
+    turned_away: Arc<Semaphore>,
+    /// Slots for connections waiting for their PROXY header, see [`PENDING_HEADERS_MAX`].
+    pending_headers: Arc<Semaphore>,
+    sessions: Arc<Sessions>,
+    lines: Arc<LineGenerator>,
+    engine: Arc<Engine>,
 }
 
+/// Accepts clients until `stop` is cancelled, `settings` brings the listener's new settings when the configuration
+/// is reloaded. `socket` is the one to listen on, passed by systemd or left by the listener this one replaces,
+/// `lines` makes up the banner lines, `engine` drips to the clients once their handshake is done.
+/// Returns the socket once stopped, for the listener that replaces it.
+#[expect(clippy::too_many_arguments, reason = "Entrypoint")]
 pub async fn listen_for_new_connections(
-    config: Arc<Config>,
+    mut settings: watch::Receiver<ListenerSettings>,
+    socket: Option<std::net::TcpListener>,
+    lines: Arc<LineGenerator>,
+    engine: Arc<Engine>,
+    stop: CancellationToken,
     cancellation_token: CancellationToken,
     client_task_tracker: TaskTracker,
-    internal_events_tx: tokio::sync::mpsc::Sender<ClientEvent>,
-    semaphore: Arc<Semaphore>,
-) {
+    internal_events_tx: Sender<ClientEvent>,
+) -> Option<std::net::TcpListener> {
+    let mut config = Arc::clone(&settings.borrow_and_update().config);
+
     // listen forever, accept new clients
     let listener = match Listener::bind(
-        Arc::clone(&config),
+        settings.clone(),
+        socket,
+        lines,
+        engine,
         client_task_tracker,
-        cancellation_token.clone(),
+        cancellation_token,
         internal_events_tx,
-        semaphore,
     )
     .await
     {
-        Ok(l) => l,
+        Ok(l) => Arc::new(l),
         Err(error) => {
-            event!(Level::ERROR, ?error);
+            event!(Level::ERROR, listener = %config.name, ?error);
 
-            return;
+            return None;
         },
     };
 
-    event!(Level::INFO, listener = ?listener.tcp_listener, "Bound and listening!");
+    event!(Level::INFO, listener = %config.name, tcp_listener = ?listener.tcp_listener, "Bound and listening!");
 
     loop {
         let result = tokio::select! {
             biased;
-            () = cancellation_token.cancelled() => {
+            () = stop.cancelled() => {
                 break;
             },
+            result = settings.changed() => {
+                // the sender only goes away once we're stopped
+                if result.is_err() {
+                    break;
+                }
+
+                let reloaded = Arc::clone(&settings.borrow_and_update().config);
+
+                listener.reload(&config, &reloaded);
+                config = reloaded;
+
+                continue;
+            },
             result = listener.accept() => {
                 result
             },
@@ -69,88 +506,131 @@ pub async fn listen_for_new_connections(
                 "Failed to accept new connection, aborting."
             );
 
-            break;
+            return None;
         }
     }
+
+    // the copy outlives the clients still reading their PROXY header, which hold on to this one
+    match listener.tcp_listener.as_fd().try_clone_to_owned() {
+        Ok(socket) => Some(std::net::TcpListener::from(socket)),
+        Err(error) => {
+            event!(Level::WARN, listener = %config.name, ?error, "Failed to keep the socket");
+
+            None
+        },
+    }
 }
 
 impl Listener {
+    /// Binds `address`, unless given the `socket` to listen on.
     pub async fn bind(
-        config: Arc<Config>,
+        settings: watch::Receiver<ListenerSettings>,
+        socket: Option<std::net::TcpListener>,
+        lines: Arc<LineGenerator>,
+        engine: Arc<Engine>,
         client_task_tracker: TaskTracker,
         cancellation_token: CancellationToken,
-        internal_events_tx: tokio::sync::mpsc::Sender<ClientEvent>,
-        semaphore: Arc<Semaphore>,
+        internal_events_tx: Sender<ClientEvent>,
     ) -> Result<Self, eyre::Report> {
-        let listener = TcpListener::bind(config.ssh_listen_address).await?;
+        let config = Arc::clone(&settings.borrow().config);
+
+        let listener = if let Some(socket) = socket {
+            socket.set_nonblocking(true)?;
+
+            TcpListener::from_std(socket)?
+        } else {
+            TcpListener::bind(config.address).await?
+        };
+
+        let ipv6 = config.address.is_ipv6();
+
+        config
+            .socket_options
+            .apply(&config.name, listener.as_fd(), ipv6);
+        SocketOptions::log_effective(&config.name, listener.as_fd(), ipv6);
+
+        // available slots semaphore, every listener has its own quota
+        let semaphore = Arc::new(Semaphore::new(config.slots()));
+
+        let sources = Arc::new(SourceCounts::new(config.source_limits));
+        let sessions = Arc::new(Sessions::default());
 
         Ok(Self {
-            config,
+            settings,
             tcp_listener: listener,
             client_task_tracker,
             cancellation_token,
             internal_events_tx,
             semaphore,
+            owed_slots: Arc::new(Mutex::new(OwedSlots::default())),
+            sources,
+            turned_away: Arc::new(Semaphore::new(TURNED_AWAY_MAX)),
+            pending_headers: Arc::new(Semaphore::new(PENDING_HEADERS_MAX)),
+            sessions,
+            lines,
+            engine,
         })
     }
 
-    pub async fn accept(&self) -> Result<(), eyre::Report> {
+    /// Applies what changed from `previous` to `config` to the listener itself, the clients pick up the rest on their own.
+    fn reload(&self, previous: &ListenerConfig, config: &ListenerConfig) {
+        self.sources.set_limits(config.source_limits);
+
+        // an option that's no longer configured keeps its value until the listener restarts
+        if config.socket_options != previous.socket_options {
+            let ipv6 = config.address.is_ipv6();
+
+            config
+                .socket_options
+                .apply(&config.name, self.tcp_listener.as_fd(), ipv6);
+            SocketOptions::log_effective(&config.name, self.tcp_listener.as_fd(), ipv6);
+        }
+
+        // slots given up may still be held, they're taken back as their clients leave
+        resize(
+            &self.semaphore,
+            &self.owed_slots,
+            previous.slots(),
+            config.slots(),
+            &self.client_task_tracker,
+            &self.cancellation_token,
+        );
+
+        event!(Level::INFO, listener = %config.name, "Reloaded");
+
+        config.log();
+    }
+
+    pub async fn accept(self: &Arc<Self>) -> Result<(), eyre::Report> {
         let accept = self.tcp_listener.accept().await;
 
         match accept {
             Ok((socket, addr)) => {
-                // Set the smallest possible receive buffer. This reduces local
-                // resource usage and slows down the remote end.
-                if let Err(error) = set_receive_buffer_size(&socket, SIZE_IN_BYTES) {
-                    event!(
-                        Level::ERROR,
-                        ?error,
-                        "Failed to set the tcp stream's receive buffer",
-                    );
+                // the socket options were inherited from the listening socket
+                let settings = self.settings.borrow().clone();
+
+                if settings.config.proxy_protocol {
+                    let Ok(permit) = Arc::clone(&self.pending_headers).try_acquire_owned() else {
+                        event!(
+                            Level::WARN,
+                            listener = %settings.config.name,
+                            ?addr,
+                            "Too many connections waiting for their PROXY header, closing connection"
+                        );
+
+                        return Ok(());
+                    };
+
+                    // the header can take a while, the next connection shouldn't wait for it
+                    let listener = Arc::clone(self);
+
+                    self.client_task_tracker.spawn(async move {
+                        listener.admit_proxied(settings, socket, addr).await;
+
+                        drop(permit);
+                    });
                 } else {
-                    // we do try_acquire because either we can add the client or we cannot
-                    // no in-between, no sense in waiting
-                    match Arc::clone(&self.semaphore).try_acquire_owned() {
-                        Ok(permit) => {
-                            let connected_at = OffsetDateTime::now_utc();
-
-                            self.client_task_tracker.spawn(handle_client(
-                                socket,
-                                addr,
-                                connected_at,
-                                permit,
-                                Arc::clone(&self.config),
-                                ClientContext {
-                                    cancellation_token: self.cancellation_token.clone(),
-                                    internal_events_tx: self.internal_events_tx.clone(),
-                                },
-                            ));
-
-                            // now that the client is registered, broadcast for the dashboard
-                            let _r = self
-                                .internal_events_tx
-                                .send(ClientEvent::Connected { addr, connected_at })
-                                .await;
-
-                            let current_clients = usize::from(self.config.max_clients.get())
-                                - self.semaphore.available_permits();
-
-                            event!(
-                                Level::INFO,
-                                addr = ?addr,
-                                current_clients,
-                                max_clients = self.config.max_clients,
-                                "Accepted new client",
-                            );
-                        },
-                        Err(TryAcquireError::NoPermits) => {
-                            event!(Level::WARN, ?addr, "Queue full, not accepting new client");
-                        },
-                        Err(error @ TryAcquireError::Closed) => {
-                            return Err(eyre::Report::new(error)
-                                .wrap_err("Queue gone, not accepting new client"));
-                        },
-                    }
+                    self.admit(&settings, socket, addr)?;
                 }
             },
             Err(error) => match error.raw_os_error() {
@@ -187,4 +667,461 @@ impl Listener {
 
         Ok(())
     }
+
+    /// Reads the PROXY header `peer` sends, and takes the client behind it in, see [`proxy_protocol::read_header`].
+    async fn admit_proxied(
+        &self,
+        settings: ListenerSettings,
+        mut socket: TcpStream,
+        peer: SocketAddr,
+    ) {
+        let config = Arc::clone(&settings.config);
+
+        let header = tokio::select! {
+            biased;
+            () = self.cancellation_token.cancelled() => {
+                return;
+            },
+            result = proxy_protocol::read_header(&mut socket, peer, &config.trusted_proxies) => {
+                result
+            },
+        };
+
+        let addr = match header {
+            Ok(addr) => addr,
+            Err(error) => {
+                event!(
+                    Level::WARN,
+                    listener = %config.name,
+                    ?peer,
+                    %error,
+                    "Rejected PROXY header, not accepting new client"
+                );
+
+                return;
+            },
+        };
+
+        if addr != peer {
+            event!(Level::DEBUG, listener = %config.name, ?peer, ?addr, "Client behind proxy");
+        }
+
+        if let Err(error) = self.admit(&settings, socket, addr) {
+            event!(Level::ERROR, listener = %config.name, ?error);
+        }
+    }
+
+    /// Takes the new client in, if the source limits and the admission policy let it.
+    fn admit(
+        &self,
+        settings: &ListenerSettings,
+        socket: TcpStream,
+        addr: SocketAddr,
+    ) -> Result<(), eyre::Report> {
+        let config = Arc::clone(&settings.config);
+
+        if config.access.check(addr.ip()) == Access::Close {
+            event!(
+                Level::INFO,
+                listener = %config.name,
+                ?addr,
+                "Denylisted, closing connection"
+            );
+
+            return Ok(());
+        }
+
+        let source = match self.sources.admit(addr.ip()) {
+            Ok(source) => source,
+            Err(excess) => {
+                self.turn_away(&config, socket, addr, excess);
+
+                return Ok(());
+            },
+        };
+
+        let (session, first_seen) = self.sessions.open(addr.ip());
+        let policy = config.admission_policy;
+
+        if !first_seen && self.semaphore.available_permits() <= policy.reserved(config.slots()) {
+            event!(
+                Level::WARN,
+                listener = %config.name,
+                ?addr,
+                "Remaining slots reserved for new sources, not accepting new client"
+            );
+
+            return Ok(());
+        }
+
+        // we do try_acquire because either we can add the client or we cannot
+        // no in-between, no sense in waiting
+        match Arc::clone(&self.semaphore).try_acquire_owned() {
+            Ok(permit) => {
+                self.start(settings, socket, addr, permit, source, session);
+
+                Ok(())
+            },
+            Err(TryAcquireError::NoPermits) => {
+                let reason = match policy {
+                    AdmissionPolicy::EvictOldest => EvictionReason::Oldest,
+                    AdmissionPolicy::EvictLowestValue => EvictionReason::LowestValue,
+                    AdmissionPolicy::Refuse | AdmissionPolicy::ReserveNew { .. } => {
+                        event!(
+                            Level::WARN,
+                            listener = %config.name,
+                            ?addr,
+                            "Queue full, not accepting new client"
+                        );
+
+                        return Ok(());
+                    },
+                };
+
+                self.evict_for(settings, socket, addr, source, session, reason);
+
+                Ok(())
+            },
+            Err(error @ TryAcquireError::Closed) => {
+                Err(eyre::Report::new(error).wrap_err("Queue gone, not accepting new client"))
+            },
+        }
+    }
+
+    fn context(&self, settings: &ListenerSettings) -> ClientContext {
+        ClientContext {
+            cancellation_token: self.cancellation_token.clone(),
+            internal_events_tx: self.internal_events_tx.clone(),
+            honeypot: settings.honeypot.clone(),
+            lines: Arc::clone(&self.lines),
+            engine: Arc::clone(&self.engine),
+        }
+    }
+
+    fn start(
+        &self,
+        settings: &ListenerSettings,
+        socket: TcpStream,
+        addr: SocketAddr,
+        permit: OwnedSemaphorePermit,
+        source: SourceSlot,
+        session: SessionSlot,
+    ) {
+        let connected_at = OffsetDateTime::now_utc();
+        let config = Arc::clone(&settings.config);
+
+        self.client_task_tracker.spawn(handle_client(
+            socket,
+            ClientSlot {
+                addr,
+                connected_at,
+                config: Arc::clone(&config),
+                updates: self.settings.clone(),
+                permit,
+                source,
+                session,
+                recorded: recorded(&config, addr),
+            },
+            self.context(settings),
+        ));
+
+        // slots given up in a reload may still be held
+        let current_clients = config
+            .slots()
+            .saturating_sub(self.semaphore.available_permits());
+
+        event!(
+            Level::INFO,
+            listener = %config.name,
+            addr = ?addr,
+            current_clients,
+            max_clients = config.max_clients,
+            protocol = %config.protocol,
+            "Accepted new client",
+        );
+    }
+
+    /// Makes room for the new client by evicting one of the trapped ones, see [`Sessions::evict`].
+    fn evict_for(
+        &self,
+        settings: &ListenerSettings,
+        socket: TcpStream,
+        addr: SocketAddr,
+        source: SourceSlot,
+        session: SessionSlot,
+        reason: EvictionReason,
+    ) {
+        let Some(evict) = self.sessions.evict(reason) else {
+            event!(
+                Level::WARN,
+                listener = %settings.config.name,
+                ?addr,
+                "Queue full and nobody to evict, not accepting new client"
+            );
+
+            return;
+        };
+
+        event!(
+            Level::INFO,
+            listener = %settings.config.name,
+            ?addr,
+            ?evict,
+            %reason,
+            "Queue full, evicting a client"
+        );
+
+        match evict {
+            Evict::Task(cancellation_token) => cancellation_token.cancel(),
+            Evict::Scheduled(token) => self.engine.evict(token),
+        }
+
+        // the evicted client gives its slot back as soon as its task or scheduler gets to it
+        let connected_at = OffsetDateTime::now_utc();
+        let semaphore = Arc::clone(&self.semaphore);
+        let config = Arc::clone(&settings.config);
+        let updates = self.settings.clone();
+        let context = self.context(settings);
+        let recorded = recorded(&config, addr);
+
+        self.client_task_tracker.spawn(async move {
+            let permit = tokio::select! {
+                biased;
+                () = context.cancellation_token.cancelled() => {
+                    return;
+                },
+                result = timeout(EVICTION_TIMEOUT, semaphore.acquire_owned()) => {
+                    result
+                },
+            };
+
+            let Ok(Ok(permit)) = permit else {
+                event!(
+                    Level::WARN,
+                    listener = %config.name,
+                    ?addr,
+                    "Evicted client's slot wasn't freed in time, dropping new client"
+                );
+
+                return;
+            };
+
+            handle_client(
+                socket,
+                ClientSlot {
+                    addr,
+                    connected_at,
+                    config,
+                    updates,
+                    permit,
+                    source,
+                    session,
+                    recorded,
+                },
+                context,
+            )
+            .await;
+        });
+    }
+
+    /// Handles a connection over its source's limits, it never takes a slot, and isn't stored. A tarpitted one gets a
+    /// few lines, or is closed when too many are already.
+    fn turn_away(
+        &self,
+        config: &ListenerConfig,
+        socket: TcpStream,
+        addr: SocketAddr,
+        excess: Excess,
+    ) {
+        let mut action = config.source_limits.action;
+
+        let permit = match action {
+            ExcessAction::Close | ExcessAction::Reset => None,
+            ExcessAction::Tarpit => {
+                let permit = Arc::clone(&self.turned_away).try_acquire_owned().ok();
+
+                if permit.is_none() {
+                    action = ExcessAction::Close;
+                }
+
+                permit
+            },
+        };
+
+        event!(
+            Level::WARN,
+            listener = %config.name,
+            ?addr,
+            ?excess,
+            %action,
+            "Too many connections from source"
+        );
+
+        match action {
+            ExcessAction::Close => drop(socket),
+            ExcessAction::Reset => {
+                // closing with a zero linger sends a RST instead of a FIN
+                if let Err(error) = socket.set_zero_linger() {
+                    event!(Level::DEBUG, ?addr, ?error, "Failed to set zero linger");
+                }
+
+                drop(socket);
+            },
+            ExcessAction::Tarpit => {
+                let cancellation_token = self.cancellation_token.clone();
+                let bandwidth = Arc::clone(self.engine.bandwidth());
+                let lines = Arc::clone(&self.lines);
+                let delay = config.delay;
+                let max_length = config.max_line_length.get().into();
+
+                // banner lines, there's no handshake
+                let protocol = match config.protocol {
+                    Protocol::Ssh | Protocol::SshKex | Protocol::SshHoneypot => Protocol::Ssh,
+                    protocol @ (Protocol::Http | Protocol::Smtp | Protocol::Telnet) => protocol,
+                };
+
+                self.client_task_tracker.spawn(async move {
+                    let mut socket = socket;
+
+                    for _ in 0..TURNED_AWAY_LINES {
+                        let line = protocol.line(&lines, max_length);
+
+                        let sent = tokio::select! {
+                            biased;
+                            () = cancellation_token.cancelled() => false,
+                            () = sleep(delay) => {
+                                // a full window doesn't hold the slot any longer than a delay
+                                matches!(timeout(delay, socket.write_all(&line)).await, Ok(Ok(())))
+                            },
+                        };
+
+                        if !sent {
+                            break;
+                        }
+
+                        bandwidth.record(line.len());
+                    }
+
+                    drop(socket);
+                    drop(permit);
+                });
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use russh::keys::{Algorithm, PrivateKey};
+    use tokio::sync::Semaphore;
+    use tokio::sync::mpsc::channel;
+    use tokio::time::timeout;
+    use tokio_util::sync::CancellationToken;
+    use tokio_util::task::TaskTracker;
+
+    use crate::config::ListenerConfig;
+    use crate::listener::{Listeners, OwedSlots, resize};
+    use crate::protocol::Protocol;
+    use crate::scheduler::Engine;
+
+    #[tokio::test]
+    async fn first_honeypot_on_reload_takes_the_key_loaded_at_start() {
+        let cancellation_token = CancellationToken::new();
+        let tasks = TaskTracker::new();
+        let (internal_events_tx, _internal_events_rx) = channel(1);
+
+        let engine = Engine::start(
+            NonZeroU16::MIN,
+            None,
+            &cancellation_token,
+            &tasks,
+            &internal_events_tx,
+        )
+        .unwrap();
+
+        let host_key = PrivateKey::random(&mut ::rand::rng(), Algorithm::Ed25519).unwrap();
+
+        let mut listeners = Listeners::new(
+            Some(host_key),
+            Arc::new(engine),
+            tasks.clone(),
+            cancellation_token.clone(),
+            tasks.clone(),
+            cancellation_token.clone(),
+            internal_events_tx,
+        );
+
+        // sandboxed, the key can't be created anymore
+        let path = Path::new("/nonexistent/ssh_host_ed25519_key");
+
+        let settings = listeners
+            .settings(
+                Arc::new(ListenerConfig {
+                    protocol: Protocol::SshHoneypot,
+                    ..ListenerConfig::default()
+                }),
+                path,
+            )
+            .unwrap();
+
+        assert!(settings.honeypot.is_some());
+        assert!(!path.exists());
+
+        cancellation_token.cancel();
+        tasks.close();
+        tasks.wait().await;
+    }
+
+    #[tokio::test]
+    async fn growing_pays_back_slots_still_held() {
+        let cancellation_token = CancellationToken::new();
+        let tasks = TaskTracker::new();
+        let semaphore = Arc::new(Semaphore::new(4));
+        let owed = Arc::new(Mutex::new(OwedSlots::default()));
+
+        let held = Arc::clone(&semaphore).acquire_many_owned(4).await.unwrap();
+
+        resize(&semaphore, &owed, 4, 2, &tasks, &cancellation_token);
+
+        assert_eq!(owed.lock().unwrap().count, 2);
+
+        resize(&semaphore, &owed, 2, 5, &tasks, &cancellation_token);
+
+        assert_eq!(owed.lock().unwrap().count, 0);
+        assert_eq!(semaphore.available_permits(), 1);
+
+        // nothing's left to take back
+        tasks.close();
+        timeout(Duration::from_secs(1), tasks.wait()).await.unwrap();
+
+        drop(held);
+
+        assert_eq!(semaphore.available_permits(), 5);
+    }
+
+    #[tokio::test]
+    async fn takes_back_slots_as_clients_leave() {
+        let cancellation_token = CancellationToken::new();
+        let tasks = TaskTracker::new();
+        let semaphore = Arc::new(Semaphore::new(4));
+        let owed = Arc::new(Mutex::new(OwedSlots::default()));
+
+        let held = Arc::clone(&semaphore).acquire_many_owned(4).await.unwrap();
+
+        resize(&semaphore, &owed, 4, 2, &tasks, &cancellation_token);
+
+        drop(held);
+
+        tasks.close();
+        timeout(Duration::from_secs(1), tasks.wait()).await.unwrap();
+
+        assert_eq!(semaphore.available_permits(), 2);
+        assert_eq!(owed.lock().unwrap().count, 0);
+    }
 }
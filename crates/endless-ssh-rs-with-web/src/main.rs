@@ -1,39 +1,59 @@
+mod access;
+mod admission;
+mod bandwidth;
 mod build_env;
 mod cli;
 mod client;
 mod config;
+mod config_file;
 mod db;
+mod delay;
 mod events;
 mod ffi_wrapper;
 mod geoip;
 mod helpers;
+mod honeypot;
 mod line;
 mod listener;
+mod privileges;
+mod protocol;
+mod proxy_protocol;
 mod router;
+mod sandbox;
+mod scheduler;
 mod sender;
 mod server;
 mod shutdown;
 mod signal_handlers;
+mod socket_options;
+mod source_limits;
 mod span;
 mod state;
 mod states;
+mod systemd;
 mod task_tracker_ext;
+mod tcp_metrics;
 mod test_utils;
 mod timeout;
 mod utils;
+mod watcher;
+mod wheel;
 
 use std::convert::Infallible;
 use std::env::{self, VarError};
+use std::mem;
 use std::net::SocketAddr;
+use std::num::{NonZeroU16, NonZeroU64};
+use std::pin::pin;
 use std::process::{ExitCode, Termination as _};
 use std::sync::Arc;
 use std::time::Duration;
 
 use color_eyre::config::HookBuilder;
-use color_eyre::eyre;
+use color_eyre::eyre::{self, WrapErr as _};
 use dashmap::DashMap;
-use dotenvy::dotenv;
-use tokio::sync::{Semaphore, broadcast};
+use dotenvy::{dotenv, dotenv_override};
+use tokio::sync::broadcast;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
@@ -46,12 +66,21 @@ use crate::build_env::get_build_env;
 use crate::cli::parse_cli;
 use crate::config::Config;
 use crate::events::{ActiveConnectionInfo, ClientEvent, WsEvent, database_listen_forever};
+use crate::ffi_wrapper::raise_open_files_limit;
 use crate::geoip::GeoIpReader;
-use crate::listener::listen_for_new_connections;
+use crate::honeypot::load_or_generate_host_key;
+use crate::line::generator::LineGenerator;
+use crate::listener::Listeners;
+use crate::privileges::drop_privileges;
+use crate::protocol::Protocol;
 use crate::router::build_router;
+use crate::sandbox::Sandbox;
+use crate::scheduler::Engine;
 use crate::server::setup_server;
 use crate::shutdown::Shutdown;
+use crate::signal_handlers::Sighups;
 use crate::state::ApplicationState;
+use crate::systemd::{ActivatedSockets, HTTP_SOCKET_NAME, notify_or_log, watchdog_interval};
 use crate::task_tracker_ext::TaskTrackerExt as _;
 use crate::utils::flatten_shutdown_handle;
 use crate::utils::task::spawn_with_name;
@@ -60,8 +89,6 @@ use crate::utils::task::spawn_with_name;
 #[cfg_attr(miri, expect(unused, reason = "Not supported in Miri"))]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-const SIZE_IN_BYTES: usize = 1;
-
 fn build_filter() -> (EnvFilter, Option<eyre::Report>) {
     fn build_default_filter() -> EnvFilter {
         EnvFilter::builder()
@@ -97,7 +124,7 @@ fn init_tracing(filter: EnvFilter) -> Result<(), eyre::Report> {
 
 fn main() -> ExitCode {
     // set up .env, if it fails, user didn't provide any
-    let _r = dotenv();
+    let env_file = dotenv().is_ok();
 
     HookBuilder::default()
         .capture_span_trace_by_default(true)
@@ -123,7 +150,7 @@ fn main() -> ExitCode {
         .block_on(async {
             // explicitly launch everything in a spawned task
             // see https://docs.rs/tokio/latest/tokio/attr.main.html#non-worker-async-function
-            let handle = spawn_with_name("main task runner", start_tasks());
+            let handle = spawn_with_name("main task runner", start_tasks(env_file));
 
             flatten_shutdown_handle(handle).await
         });
@@ -163,24 +190,92 @@ fn get_config() -> Result<Arc<Config>, eyre::Report> {
 
 /// starts all the tasks, such as the web server, the key refresh, ...
 /// ensures all tasks are gracefully shutdown in case of error, `CTRL+c` or `SIGTERM`.
+/// `env_file` tells whether a `.env` was read at start.
 #[expect(clippy::too_many_lines, reason = "Entrypoint")]
-async fn start_tasks() -> Shutdown {
-    print_header();
-
+async fn start_tasks(env_file: bool) -> Shutdown {
     let config = match get_config() {
         Ok(config) => config,
         Err(error) => return Shutdown::from(error),
     };
 
+    // only the configuration goes to stdout, so it can be redirected to a file
+    if config.print_and_exit {
+        print!("{}", config_file::to_toml(&config));
+
+        return Shutdown::Success;
+    }
+
+    print_header();
+
     config.log();
 
-    let Ok(database_url) = env::var("DATABASE_URL") else {
-        event!(Level::ERROR, "DATABASE_URL environment variable is not set");
+    // passed by systemd socket activation, to listen on instead of binding
+    let activated_sockets = ActivatedSockets::from_env();
+
+    // every trapped client holds a file descriptor
+    match raise_open_files_limit() {
+        Ok(open_files) => {
+            let max_clients = config
+                .listeners
+                .iter()
+                .map(|listener| NonZeroU64::from(listener.max_clients).get())
+                .sum::<u64>();
+
+            if max_clients > open_files {
+                event!(
+                    Level::WARN,
+                    open_files,
+                    max_clients,
+                    "Open files limit is below the listeners' combined max clients"
+                );
+            } else {
+                event!(Level::INFO, "OpenFilesLimit: {}", open_files);
+            }
+        },
+        Err(error) => {
+            event!(Level::WARN, ?error, "Failed to raise the open files limit");
+        },
+    }
+
+    // the sandbox won't let a reload that adds the first `ssh-honeypot` listener create the key
+    let host_key = if config.sandbox != Sandbox::Off
+        || config
+            .listeners
+            .iter()
+            .any(|listener| listener.protocol == Protocol::SshHoneypot)
+    {
+        match load_or_generate_host_key(&config.ssh_host_key) {
+            Ok(host_key) => Some(host_key),
+            Err(error) => {
+                event!(Level::ERROR, ?error, "Failed to set up SSH host key");
+
+                return Shutdown::from(error);
+            },
+        }
+    } else {
+        None
+    };
+
+    let mut line_generators = Vec::with_capacity(config.listeners.len());
+
+    for listener in &config.listeners {
+        match LineGenerator::load(&listener.line_generator) {
+            Ok(lines) => line_generators.push(Arc::new(lines)),
+            Err(error) => {
+                event!(Level::ERROR, listener = %listener.name, ?error, "Failed to load line generator");
+
+                return Shutdown::from(error);
+            },
+        }
+    }
+
+    let Some(database_url) = config.database_url.as_deref() else {
+        event!(Level::ERROR, "`--database-url` (`DATABASE_URL`) is not set");
 
         return Shutdown::from(eyre::eyre!("DATABASE_URL not set"));
     };
 
-    let db_pool = match db::create_pool(&database_url).await {
+    let db_pool = match db::create_pool(database_url).await {
         Ok(pool) => pool,
         Err(error) => {
             event!(Level::ERROR, ?error, "Failed to connect to database");
@@ -196,8 +291,8 @@ async fn start_tasks() -> Shutdown {
 
     event!(Level::INFO, "Database ready");
 
-    let geo_ip = match std::env::var("MAXMIND_LICENSE_KEY") {
-        Ok(key) if !key.is_empty() => Arc::new(GeoIpReader::try_init(&key).await),
+    let geo_ip = match config.maxmind_license_key.as_deref() {
+        Some(key) if !key.is_empty() => Arc::new(GeoIpReader::try_init(key).await),
         _ => {
             event!(
                 Level::INFO,
@@ -208,6 +303,60 @@ async fn start_tasks() -> Shutdown {
         },
     };
 
+    let mut http_socket = activated_sockets.take(HTTP_SOCKET_NAME, config.http_listen_address);
+    let mut listener_sockets = config
+        .listeners
+        .iter()
+        .map(|listener| activated_sockets.take(&listener.name, listener.address))
+        .collect::<Vec<_>>();
+
+    activated_sockets.close_unclaimed();
+
+    // the sandbox finds the threads in `/proc`, which the chroot hides
+    let task_dir = match sandbox::open_task_dir(&config) {
+        Ok(task_dir) => task_dir,
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Failed to install the sandbox");
+
+            return Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Failed to install the sandbox",
+            };
+        },
+    };
+
+    if let Some(privileges) = config.privileges.as_ref() {
+        let dropped = async {
+            // ports below 1024 need root, so everything is bound before dropping it
+            bind_now(&mut http_socket, config.http_listen_address).await?;
+
+            for (socket, listener) in listener_sockets.iter_mut().zip(&config.listeners) {
+                bind_now(socket, listener.address).await?;
+            }
+
+            drop_privileges(privileges)
+        }
+        .await;
+
+        if let Err(error) = dropped {
+            event!(Level::ERROR, ?error, "Failed to drop privileges");
+
+            return Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Failed to drop privileges",
+            };
+        }
+    }
+
+    if let Err(error) = sandbox::install(&config, task_dir.as_ref()) {
+        event!(Level::ERROR, ?error, "Failed to install the sandbox");
+
+        return Shutdown::OperationalFailure {
+            code: ExitCode::FAILURE,
+            message: "Failed to install the sandbox",
+        };
+    }
+
     let (internal_events_tx, internal_events_rx) = tokio::sync::mpsc::channel::<ClientEvent>(1000);
     let (ws_broadcast_tx, _ws_broadcast_rx) = broadcast::channel::<WsEvent>(1000);
     let active_connections: Arc<DashMap<SocketAddr, ActiveConnectionInfo>> =
@@ -219,17 +368,39 @@ async fn start_tasks() -> Shutdown {
     let cancellation_token = CancellationToken::new();
     let client_cancellation_token = cancellation_token.child_token();
 
-    // available slots semaphore
-    let semaphore = Arc::new(Semaphore::new(config.max_clients.get().into()));
-
     let client_tasks = TaskTracker::new();
 
+    let schedulers = config
+        .schedulers
+        .or_else(|| {
+            std::thread::available_parallelism()
+                .ok()
+                .map(|cpus| NonZeroU16::try_from(cpus).unwrap_or(NonZeroU16::MAX))
+        })
+        .unwrap_or(NonZeroU16::MIN);
+
+    let engine = match Engine::start(
+        schedulers,
+        config.max_bandwidth,
+        &client_cancellation_token,
+        &client_tasks,
+        &internal_events_tx,
+    ) {
+        Ok(engine) => Arc::new(engine),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Failed to start the schedulers");
+
+            return Shutdown::from(eyre::Report::new(error));
+        },
+    };
+
     let application_state = ApplicationState::new(
         states::config::Config {},
         db_pool.clone(),
         Arc::clone(&geo_ip),
         ws_broadcast_tx.clone(),
         Arc::clone(&active_connections),
+        Arc::clone(&engine),
     );
 
     let tasks = TaskTracker::new();
@@ -238,31 +409,41 @@ async fn start_tasks() -> Shutdown {
         "server",
         set_up_server(
             config.http_listen_address,
+            http_socket,
+            config.front_end_proxy.clone(),
             application_state,
             cancellation_token.clone(),
         ),
     );
 
+    let mut listeners = Listeners::new(
+        host_key,
+        Arc::clone(&engine),
+        tasks.clone(),
+        cancellation_token.clone(),
+        client_tasks.clone(),
+        client_cancellation_token.clone(),
+        internal_events_tx.clone(),
+    );
+
+    for ((listener_config, lines), socket) in config
+        .listeners
+        .iter()
+        .zip(line_generators)
+        .zip(listener_sockets)
     {
-        let cancellation_token = cancellation_token.clone();
-        let client_tasks = client_tasks.clone();
-        let client_cancellation_token = client_cancellation_token.clone();
-
-        tasks.spawn_with_name("connection listener", async move {
-            let _guard = cancellation_token.drop_guard_ref();
-            let _client_guard = client_cancellation_token.clone().drop_guard();
-
-            listen_for_new_connections(
-                Arc::clone(&config),
-                client_cancellation_token,
-                client_tasks,
-                internal_events_tx,
-                semaphore,
-            )
-            .await;
-        });
+        // the host key is already loaded if needed
+        match listeners.settings(Arc::new(listener_config.clone()), &config.ssh_host_key) {
+            Ok(settings) => listeners.start(settings, socket, lines),
+            Err(error) => {
+                event!(Level::ERROR, listener = %listener_config.name, ?error, "Failed to set up listener");
+            },
+        }
     }
 
+    // the listeners, their registry and the engine hold the remaining senders
+    drop(internal_events_tx);
+
     {
         let cancellation_token = cancellation_token.clone();
         let db_pool = db_pool.clone();
@@ -285,36 +466,71 @@ async fn start_tasks() -> Shutdown {
         });
     }
 
+    if let Some(interval) = watchdog_interval() {
+        let cancellation_token = cancellation_token.clone();
+
+        tasks.spawn_with_name("systemd watchdog", async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    () = cancellation_token.cancelled() => {
+                        break;
+                    },
+                    () = tokio::time::sleep(interval) => {
+                        notify_or_log("WATCHDOG=1");
+                    },
+                }
+            }
+        });
+    }
+
     // done enrolling tasks in this tracker
     tasks.close();
 
+    notify_or_log("READY=1");
+
+    let mut sighups = Sighups::new();
+    let mut sigterm = pin!(signal_handlers::wait_for_sigterm());
+    let mut sigint = pin!(signal_handlers::wait_for_sigint());
+
     // now we wait forever for either
-    // * the cancellation token. we only cancel it ourselves after this select, so
+    // * the cancellation token. we only cancel it ourselves after this loop, so
     //   here it means a task stopped on its own, which tasks only do on failure
     // * SIGTERM
     // * CTRL+c (SIGINT)
+    // reloading the configuration on every SIGHUP in the meantime
     // biased so that when multiple are ready at once, task failure wins over signals
-    let shutdown_reason = tokio::select! {
-        biased;
-        () = cancellation_token.cancelled() => {
-            event!(Level::WARN, "Underlying task stopped, stopping all other tasks");
-
-            Shutdown::OperationalFailure {
-                code: ExitCode::FAILURE,
-                message: "A task failed, triggering a shutdown"
-            }
-        },
-        result = signal_handlers::wait_for_sigterm() => {
-            result
-        },
-        result = signal_handlers::wait_for_sigint() => {
-            result
-        },
+    let shutdown_reason = loop {
+        tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => {
+                event!(Level::WARN, "Underlying task stopped, stopping all other tasks");
+
+                break Shutdown::OperationalFailure {
+                    code: ExitCode::FAILURE,
+                    message: "A task failed, triggering a shutdown"
+                };
+            },
+            result = &mut sigterm => {
+                break result;
+            },
+            result = &mut sigint => {
+                break result;
+            },
+            () = sighups.recv() => {
+                reload(&config, env_file, &mut listeners).await;
+            },
+        }
     };
 
+    notify_or_log("STOPPING=1");
+
     client_cancellation_token.cancel();
     client_tasks.close();
 
+    // holds a sender of the internal events, which the database task waits on
+    drop(listeners);
+
     // drain clients
     if timeout(Duration::from_secs(10), client_tasks.wait())
         .await
@@ -342,16 +558,131 @@ async fn start_tasks() -> Shutdown {
     shutdown_reason
 }
 
+/// Re-reads `.env`, the configuration file and the command line, and applies the listeners' changes to `listeners`.
+/// The rest of `config` only changes on restart, and so do the listeners that can't be bound once the privileges are
+/// dropped.
+async fn reload(config: &Config, env_file: bool, listeners: &mut Listeners) {
+    let chroot = config
+        .privileges
+        .as_ref()
+        .and_then(|privileges| privileges.chroot.as_deref());
+
+    // variables set by `.env` are overridden, the others stay as they were
+    let env_reloaded = dotenv_override().is_ok();
+
+    // in the chroot, paths resolve inside it, the files read at start may no longer be there
+    if let Some(root) = chroot {
+        if env_file && !env_reloaded {
+            event!(
+                Level::WARN,
+                chroot = %root.display(),
+                "No `.env` in the chroot, changes to the one read at start need a restart"
+            );
+        }
+
+        if let Some(file) = config.file.as_deref()
+            && !file.exists()
+        {
+            event!(
+                Level::ERROR,
+                chroot = %root.display(),
+                file = %file.display(),
+                "Configuration file not in the chroot, reloading it needs a restart, keeping the current configuration"
+            );
+
+            return;
+        }
+    }
+
+    // unlike at start, a bad configuration keeps the current one instead of exiting
+    let mut reloaded = match parse_cli() {
+        Ok(reloaded) => reloaded,
+        Err(error) => {
+            event!(
+                Level::ERROR,
+                ?error,
+                "Invalid configuration, keeping the current one"
+            );
+
+            return;
+        },
+    };
+
+    if reloaded.http_listen_address != config.http_listen_address
+        || reloaded.ssh_host_key != config.ssh_host_key
+        || reloaded.schedulers != config.schedulers
+        || reloaded.max_bandwidth != config.max_bandwidth
+        || reloaded.front_end_proxy != config.front_end_proxy
+        || reloaded.database_url != config.database_url
+        || reloaded.maxmind_license_key != config.maxmind_license_key
+        || reloaded.privileges != config.privileges
+        || reloaded.sandbox != config.sandbox
+    {
+        event!(
+            Level::WARN,
+            "Only the listeners change on reload, the rest of the configuration on restart"
+        );
+    }
+
+    if config.privileges.is_some() {
+        reloaded.listeners = mem::take(&mut reloaded.listeners)
+            .into_iter()
+            .filter_map(|listener| {
+                let port = listener.address.port();
+
+                // ports below 1024 need root, only the sockets bound at start are still there
+                if port == 0 || port >= 1024 || listeners.is_bound(listener.address) {
+                    return Some(listener);
+                }
+
+                event!(
+                    Level::ERROR,
+                    listener = %listener.name,
+                    address = %listener.address,
+                    "Ports below 1024 can't be bound once the privileges are dropped, this needs a restart, keeping listener as it was"
+                );
+
+                listeners.config(&listener.name).cloned()
+            })
+            .collect();
+    }
+
+    listeners
+        .reload(reloaded.listeners, &config.ssh_host_key)
+        .await;
+
+    event!(Level::INFO, "Configuration reloaded");
+}
+
+/// Binds `address` unless `socket` is already there, for when it can't be bound later.
+async fn bind_now(
+    socket: &mut Option<std::net::TcpListener>,
+    address: SocketAddr,
+) -> Result<(), eyre::Report> {
+    if socket.is_none() {
+        let listener = tokio::net::TcpListener::bind(address)
+            .await
+            .and_then(tokio::net::TcpListener::into_std)
+            .wrap_err_with(|| format!("Failed to bind {}", address))?;
+
+        *socket = Some(listener);
+    }
+
+    Ok(())
+}
+
 async fn set_up_server(
     bind_to: SocketAddr,
+    socket: Option<std::net::TcpListener>,
+    front_end_proxy: Option<String>,
     application_state: ApplicationState,
     cancellation_token: CancellationToken,
 ) {
-    let router = build_router(application_state);
+    let router = build_router(application_state, front_end_proxy.as_deref());
 
     let _guard = cancellation_token.clone().drop_guard();
 
-    match setup_server(bind_to, router, cancellation_token).await {
+    match setup_server(bind_to, socket, router, cancellation_token).await {
         Err(error) => {
             event!(Level::ERROR, ?error, "Webserver died");
         },
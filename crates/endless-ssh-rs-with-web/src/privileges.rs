@@ -0,0 +1,92 @@
+use std::env;
+use std::ffi::CString;
+use std::os::unix::fs::chroot;
+use std::path::PathBuf;
+
+use color_eyre::eyre::{self, WrapErr as _};
+use tracing::{Level, event};
+
+use crate::ffi_wrapper::{
+    can_regain_root, clear_capabilities, has_capabilities, lookup_group, lookup_user, switch_user,
+};
+
+/// Who we become once the sockets are bound, see [`drop_privileges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Privileges {
+    pub user: String,
+    /// The user's primary group when `None`.
+    pub group: Option<String>,
+    /// Where we're locked in, relative paths such as `dist` and that of the IP database resolve in it from then on.
+    pub chroot: Option<PathBuf>,
+}
+
+/// Changes root if asked to, then switches to `privileges`' user and group, with no other group and no capability
+/// left. Any step that fails is an error, and so is being able to switch back to root.
+pub fn drop_privileges(privileges: &Privileges) -> Result<(), eyre::Report> {
+    let Privileges {
+        ref user,
+        ref group,
+        chroot: ref root,
+    } = *privileges;
+
+    // the user and group databases are out of reach once in the chroot
+    let (uid, primary_gid) = lookup_user(&CString::new(user.as_str())?)
+        .wrap_err_with(|| format!("Failed to look up user `{}`", user))?
+        .ok_or_else(|| eyre::eyre!("No such user `{}`", user))?;
+
+    if uid == 0 {
+        return Err(eyre::eyre!("User `{}` is root", user));
+    }
+
+    let gid = match group.as_deref() {
+        Some(group) => lookup_group(&CString::new(group)?)
+            .wrap_err_with(|| format!("Failed to look up group `{}`", group))?
+            .ok_or_else(|| eyre::eyre!("No such group `{}`", group))?,
+        None => primary_gid,
+    };
+
+    if let Some(root) = root.as_deref() {
+        chroot(root).wrap_err_with(|| format!("Failed to chroot to `{}`", root.display()))?;
+
+        env::set_current_dir("/").wrap_err("Failed to enter the chroot")?;
+    }
+
+    switch_user(uid, gid).wrap_err_with(|| format!("Failed to switch to user `{}`", user))?;
+
+    clear_capabilities().wrap_err("Failed to drop capabilities")?;
+
+    if has_capabilities().wrap_err("Failed to read capabilities")? {
+        return Err(eyre::eyre!("Capabilities left after dropping them"));
+    }
+
+    if can_regain_root() {
+        return Err(eyre::eyre!("Switching back to root still works"));
+    }
+
+    event!(
+        Level::INFO,
+        user,
+        uid,
+        gid,
+        chroot = ?root,
+        "Dropped privileges"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::ffi_wrapper::{lookup_group, lookup_user};
+
+    #[test]
+    fn looks_up_users_and_groups() {
+        assert_eq!(lookup_user(c"root").unwrap(), Some((0, 0)));
+        assert_eq!(lookup_group(c"root").unwrap(), Some(0));
+
+        assert_eq!(lookup_user(c"no-such-user").unwrap(), None);
+        assert_eq!(lookup_group(c"no-such-group").unwrap(), None);
+    }
+}
@@ -0,0 +1,185 @@
+mod http;
+pub mod ssh;
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::line::generator::LineGenerator;
+use crate::line::{randgreeting, randheader, randignore, randnegotiation};
+use crate::protocol::ssh::ClientKex;
+
+/// What a tarpit pretends to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    /// Endless lines before the version exchange, see RFC 4253, section 4.2.
+    Ssh,
+    /// A proper version exchange, and then our KEXINIT, a byte at a time, followed by endless `SSH_MSG_IGNORE` packets.
+    #[serde(rename = "ssh-kex")]
+    SshKex,
+    /// A real SSH server up to authentication, which records and rejects every login attempt, see [`crate::honeypot`].
+    #[serde(rename = "ssh-honeypot")]
+    SshHoneypot,
+    /// A `200` status line, followed by an endless header block.
+    Http,
+    /// An endless multi-line `220` greeting, see RFC 5321, section 4.2.1.
+    Smtp,
+    /// Endless option negotiation, interleaved with login prompt fragments, see RFC 854.
+    Telnet,
+}
+
+impl Protocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Ssh => "ssh",
+            Protocol::SshKex => "ssh-kex",
+            Protocol::SshHoneypot => "ssh-honeypot",
+            Protocol::Http => "http",
+            Protocol::Smtp => "smtp",
+            Protocol::Telnet => "telnet",
+        }
+    }
+
+    /// Whatever the protocol needs to do before we start dripping lines.
+    /// Returns what we learned about the client, or `Err` when the client is gone.
+    pub async fn handshake<S>(
+        self,
+        stream: &mut S,
+        wait: Duration,
+        capture_client_ident: bool,
+    ) -> Result<Handshake, ()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + std::fmt::Debug,
+    {
+        match self {
+            Protocol::Ssh if capture_client_ident => Ok(Handshake {
+                client_ident: ssh::read_identification(stream, wait).await,
+                ..Handshake::default()
+            }),
+            // the honeypot's handshake is russh's business
+            Protocol::Ssh | Protocol::SshHoneypot | Protocol::Smtp | Protocol::Telnet => {
+                Ok(Handshake::default())
+            },
+            Protocol::SshKex => ssh::kex_handshake(stream, wait).await,
+            Protocol::Http => Ok(Handshake {
+                sent: http::handshake(stream, wait).await?,
+                ..Handshake::default()
+            }),
+        }
+    }
+
+    /// The next line to drip to the client, `lines` makes up the banner lines in `ssh` mode.
+    pub fn line(self, lines: &LineGenerator, max_length: usize) -> Vec<u8> {
+        match self {
+            Protocol::Ssh | Protocol::SshHoneypot => lines.line(max_length),
+            Protocol::SshKex => randignore(max_length),
+            Protocol::Http => randheader(max_length),
+            Protocol::Smtp => randgreeting(max_length),
+            Protocol::Telnet => randnegotiation(max_length),
+        }
+    }
+}
+
+/// What a protocol's handshake learned about the client.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Handshake {
+    /// Bytes sent during the handshake.
+    pub sent: usize,
+    pub client_ident: Option<String>,
+    pub client_kex: Option<ClientKex>,
+}
+
+/// The bytes we drip to a single client.
+pub struct Drip {
+    protocol: Protocol,
+    lines: Arc<LineGenerator>,
+    /// The rest of the packet in key exchange mode, and what didn't fit in the client's window, which goes first.
+    pending: VecDeque<u8>,
+}
+
+impl Drip {
+    pub fn new(protocol: Protocol, lines: Arc<LineGenerator>) -> Self {
+        let pending = match protocol {
+            Protocol::SshKex => ssh::kexinit().into(),
+            Protocol::Ssh
+            | Protocol::SshHoneypot
+            | Protocol::Http
+            | Protocol::Smtp
+            | Protocol::Telnet => VecDeque::new(),
+        };
+
+        Self {
+            protocol,
+            lines,
+            pending,
+        }
+    }
+
+    /// A line at a time, except in key exchange mode, where the client gets a single byte of a packet. What
+    /// [`Drip::unsent`] gave back comes before a new line.
+    pub fn next(&mut self, max_length: usize) -> Vec<u8> {
+        match self.protocol {
+            Protocol::SshKex => {
+                if self.pending.is_empty() {
+                    self.pending
+                        .extend(self.protocol.line(&self.lines, max_length));
+                }
+
+                self.pending.pop_front().into_iter().collect()
+            },
+            Protocol::Ssh
+            | Protocol::SshHoneypot
+            | Protocol::Http
+            | Protocol::Smtp
+            | Protocol::Telnet => {
+                if self.pending.is_empty() {
+                    self.protocol.line(&self.lines, max_length)
+                } else {
+                    self.pending.drain(..).collect()
+                }
+            },
+        }
+    }
+
+    /// Gives back the end of what [`Drip::next`] returned that couldn't be sent, so a line or packet is never cut.
+    pub fn unsent(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().rev() {
+            self.pending.push_front(byte);
+        }
+    }
+
+    /// What the packet still being dripped holds on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.pending.capacity()
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ssh" => Ok(Protocol::Ssh),
+            "ssh-kex" => Ok(Protocol::SshKex),
+            "ssh-honeypot" => Ok(Protocol::SshHoneypot),
+            "http" => Ok(Protocol::Http),
+            "smtp" => Ok(Protocol::Smtp),
+            "telnet" => Ok(Protocol::Telnet),
+            _ => Err(format!("Unknown protocol `{}`", s)),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::time::timeout;
+use tracing::{Level, event};
+
+/// Everything after this is an endless header block, so the client never gets to the body.
+const STATUS_LINE: &[u8] = b"HTTP/1.1 200 OK\r\n";
+
+/// We only care about the request line, cap what we read while looking for it.
+const MAX_REQUEST_LINE_LENGTH: usize = 8192;
+
+/// Reads (and discards) the request line, then sends the status line.
+/// A client that doesn't send its request line within `wait` gets the status line anyway.
+pub async fn handshake<S>(stream: &mut S, wait: Duration) -> Result<usize, ()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + std::fmt::Debug,
+{
+    let mut request_line = Vec::new();
+
+    let read = timeout(wait, async {
+        let mut buffer = [0_u8; 256];
+
+        loop {
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => {
+                    return Err(());
+                },
+                Ok(read) => {
+                    let chunk = &buffer[..read];
+
+                    if let Some(end) = chunk.iter().position(|&b| b == b'\n') {
+                        request_line.extend_from_slice(&chunk[..end]);
+
+                        return Ok(());
+                    }
+
+                    request_line.extend_from_slice(chunk);
+
+                    if request_line.len() >= MAX_REQUEST_LINE_LENGTH {
+                        return Ok(());
+                    }
+                },
+            }
+        }
+    })
+    .await;
+
+    match read {
+        Ok(Ok(())) => {
+            event!(
+                Level::DEBUG,
+                request_line = %String::from_utf8_lossy(request_line.trim_ascii_end()),
+                "Received request line"
+            );
+        },
+        Ok(Err(())) => {
+            event!(
+                Level::TRACE,
+                ?stream,
+                "Client gone before sending a request line"
+            );
+
+            return Err(());
+        },
+        Err(_elapsed) => {
+            event!(Level::TRACE, ?stream, "No request line received in time");
+        },
+    }
+
+    match stream.write_all(STATUS_LINE).await {
+        Ok(()) => Ok(STATUS_LINE.len()),
+        Err(error) => {
+            event!(Level::DEBUG, ?error, "Failed to send status line");
+
+            Err(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, duplex};
+
+    use crate::protocol::http::{STATUS_LINE, handshake};
+
+    #[tokio::test]
+    async fn answers_after_request_line() {
+        let (mut client, mut server) = duplex(1024);
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n")
+            .await
+            .unwrap();
+
+        let result = handshake(&mut server, Duration::from_secs(1)).await;
+
+        assert_eq!(result, Ok(STATUS_LINE.len()));
+
+        let mut received = vec![0_u8; STATUS_LINE.len()];
+        client.read_exact(&mut received).await.unwrap();
+
+        assert_eq!(received, STATUS_LINE);
+    }
+
+    #[tokio::test]
+    async fn answers_silent_client_after_wait() {
+        let (_client, mut server) = duplex(1024);
+
+        let result = handshake(&mut server, Duration::from_millis(10)).await;
+
+        assert_eq!(result, Ok(STATUS_LINE.len()));
+    }
+
+    #[tokio::test]
+    async fn client_gone() {
+        let (client, mut server) = duplex(1024);
+
+        drop(client);
+
+        let result = handshake(&mut server, Duration::from_secs(1)).await;
+
+        assert_eq!(result, Err(()));
+    }
+}
@@ -0,0 +1,455 @@
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use md5::{Digest as _, Md5};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::time::timeout;
+use tracing::{Level, event};
+
+use crate::protocol::Handshake;
+
+/// The identification string, CR and LF included, is at most 255 bytes, see RFC 4253, section 4.2.
+pub const MAX_IDENTIFICATION_LENGTH: usize = 255;
+
+/// What we claim to be in key exchange and honeypot mode.
+pub const SERVER_ID: &str = "SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13.5";
+
+/// [`SERVER_ID`], as it goes on the wire.
+const SERVER_IDENTIFICATION: &[u8] = b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13.5\r\n";
+
+/// Anything larger than this isn't a KEXINIT we're interested in, see RFC 4253, section 6.1.
+const MAX_PACKET_LENGTH: u32 = 35000;
+
+const SSH_MSG_KEXINIT: u8 = 20;
+
+/// The name-lists of our KEXINIT, what a recent OpenSSH offers. Never negotiated, the client
+/// doesn't get to see the end of them for a long time.
+const KEXINIT_NAME_LISTS: [&str; 10] = [
+    "curve25519-sha256,curve25519-sha256@libssh.org,ecdh-sha2-nistp256,ecdh-sha2-nistp384,ecdh-sha2-nistp521,diffie-hellman-group-exchange-sha256,diffie-hellman-group16-sha512,diffie-hellman-group18-sha512,diffie-hellman-group14-sha256,ext-info-s,kex-strict-s-v00@openssh.com",
+    "rsa-sha2-512,rsa-sha2-256,ecdsa-sha2-nistp256,ssh-ed25519",
+    "chacha20-poly1305@openssh.com,aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com",
+    "chacha20-poly1305@openssh.com,aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com",
+    "umac-64-etm@openssh.com,umac-128-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha1-etm@openssh.com,umac-64@openssh.com,umac-128@openssh.com,hmac-sha2-256,hmac-sha2-512,hmac-sha1",
+    "umac-64-etm@openssh.com,umac-128-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha1-etm@openssh.com,umac-64@openssh.com,umac-128@openssh.com,hmac-sha2-256,hmac-sha2-512,hmac-sha1",
+    "none,zlib@openssh.com",
+    "none,zlib@openssh.com",
+    "",
+    "",
+];
+
+/// The algorithms a client offered in its KEXINIT, see RFC 4253, section 7.1.
+/// The directional lists are the client to server ones, the ones HASSH uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientKex {
+    /// MD5 of `kex;encryption;mac;compression`, see <https://github.com/salesforce/hassh>.
+    pub hassh: String,
+    pub kex_algorithms: String,
+    pub host_key_algorithms: String,
+    pub encryption_algorithms: String,
+    pub mac_algorithms: String,
+    pub compression_algorithms: String,
+}
+
+impl ClientKex {
+    /// Parses the payload of a KEXINIT packet.
+    #[expect(clippy::big_endian_bytes, reason = "SSH is big-endian on the wire")]
+    fn parse(payload: &[u8]) -> Option<Self> {
+        let (&message, rest) = payload.split_first()?;
+
+        if message != SSH_MSG_KEXINIT {
+            return None;
+        }
+
+        // skip the cookie
+        let mut rest = rest.get(16..)?;
+
+        let mut next_name_list = || {
+            let (length, tail) = rest.split_first_chunk::<4>()?;
+            let length = usize::try_from(u32::from_be_bytes(*length)).ok()?;
+
+            let name_list = tail.get(..length)?;
+
+            rest = tail.get(length..)?;
+
+            Some(name_list)
+        };
+
+        let kex_algorithms = next_name_list()?;
+        let host_key_algorithms = next_name_list()?;
+        let encryption_algorithms = next_name_list()?;
+        let _encryption_algorithms_server_to_client = next_name_list()?;
+        let mac_algorithms = next_name_list()?;
+        let _mac_algorithms_server_to_client = next_name_list()?;
+        let compression_algorithms = next_name_list()?;
+
+        let digest = Md5::digest(
+            [
+                kex_algorithms,
+                encryption_algorithms,
+                mac_algorithms,
+                compression_algorithms,
+            ]
+            .join(&b';'),
+        );
+
+        let hassh = digest
+            .iter()
+            .fold(String::with_capacity(32), |mut hassh, byte| {
+                let _r = write!(hassh, "{:02x}", byte);
+
+                hassh
+            });
+
+        Some(Self {
+            hassh,
+            kex_algorithms: sanitize(kex_algorithms),
+            host_key_algorithms: sanitize(host_key_algorithms),
+            encryption_algorithms: sanitize(encryption_algorithms),
+            mac_algorithms: sanitize(mac_algorithms),
+            compression_algorithms: sanitize(compression_algorithms),
+        })
+    }
+}
+
+/// Whatever the client sent, as text we can store.
+fn sanitize(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect()
+}
+
+/// Wraps `payload` in an unencrypted binary packet, see RFC 4253, section 6.
+/// Before the key exchange there is no MAC, and the padding may as well be zeroes.
+#[expect(clippy::big_endian_bytes, reason = "SSH is big-endian on the wire")]
+pub fn binary_packet(payload: &[u8]) -> Vec<u8> {
+    // packet length, padding length, payload and padding need to be a multiple of 8,
+    // with at least 4 bytes of padding
+    let unpadded_length = 4 + 1 + payload.len();
+    let padding_length = match 8 - unpadded_length % 8 {
+        short @ 0..4 => short + 8,
+        padding_length => padding_length,
+    };
+
+    let packet_length =
+        u32::try_from(1 + payload.len() + padding_length).expect("packets we build are small");
+
+    let mut packet = Vec::with_capacity(unpadded_length + padding_length);
+
+    packet.extend_from_slice(&packet_length.to_be_bytes());
+    packet.push(u8::try_from(padding_length).expect("padding is at most 11 bytes"));
+    packet.extend_from_slice(payload);
+    packet.resize(unpadded_length + padding_length, 0);
+
+    packet
+}
+
+/// Our own KEXINIT, to be dripped a byte at a time.
+#[expect(clippy::big_endian_bytes, reason = "SSH is big-endian on the wire")]
+pub fn kexinit() -> Vec<u8> {
+    let mut payload = vec![SSH_MSG_KEXINIT];
+
+    payload.extend_from_slice(&::rand::random::<[u8; 16]>());
+
+    for name_list in KEXINIT_NAME_LISTS {
+        let length = u32::try_from(name_list.len()).expect("our name-lists are short");
+
+        payload.extend_from_slice(&length.to_be_bytes());
+        payload.extend_from_slice(name_list.as_bytes());
+    }
+
+    // first_kex_packet_follows, reserved
+    payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+
+    binary_packet(&payload)
+}
+
+/// Reads the client's first binary packet, and parses it as a KEXINIT.
+async fn read_kexinit<S>(stream: &mut S) -> Option<ClientKex>
+where
+    S: AsyncRead + Unpin,
+{
+    let packet_length = stream.read_u32().await.ok()?;
+
+    if !(1..=MAX_PACKET_LENGTH).contains(&packet_length) {
+        return None;
+    }
+
+    let mut packet = vec![0_u8; usize::try_from(packet_length).ok()?];
+
+    stream.read_exact(&mut packet).await.ok()?;
+
+    let (&padding_length, rest) = packet.split_first()?;
+
+    let payload = rest.get(..rest.len().checked_sub(usize::from(padding_length))?)?;
+
+    ClientKex::parse(payload)
+}
+
+/// Sends our identification, then reads the client's identification and KEXINIT, waiting at most `wait` for each.
+/// Only failing to send is an `Err`, a client that doesn't play along still gets our KEXINIT.
+pub async fn kex_handshake<S>(stream: &mut S, wait: Duration) -> Result<Handshake, ()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + std::fmt::Debug,
+{
+    if let Err(error) = stream.write_all(SERVER_IDENTIFICATION).await {
+        event!(Level::DEBUG, ?error, "Failed to send identification");
+
+        return Err(());
+    }
+
+    let client_ident = read_identification(stream, wait).await;
+
+    let client_kex = if client_ident.is_some() {
+        timeout(wait, read_kexinit(stream)).await.ok().flatten()
+    } else {
+        None
+    };
+
+    if let Some(client_kex) = client_kex.as_ref() {
+        event!(Level::DEBUG, hassh = %client_kex.hassh, "Received KEXINIT");
+    } else {
+        event!(Level::TRACE, ?stream, "No KEXINIT received");
+    }
+
+    Ok(Handshake {
+        sent: SERVER_IDENTIFICATION.len(),
+        client_ident,
+        client_kex,
+    })
+}
+
+/// Reads the client's identification line, e.g. `SSH-2.0-OpenSSH_9.6`.
+/// Returns `None` when the client doesn't send one within `wait`, or is gone.
+///
+/// We read byte by byte so nothing sent after the line is consumed.
+/// What we capture is stored as-is, minus the line ending and control characters,
+/// a client that sends something other than `SSH-...` is interesting too.
+pub async fn read_identification<S>(stream: &mut S, wait: Duration) -> Option<String>
+where
+    S: AsyncRead + Unpin + std::fmt::Debug,
+{
+    let mut line = Vec::with_capacity(MAX_IDENTIFICATION_LENGTH);
+
+    let read = timeout(wait, async {
+        while line.len() < MAX_IDENTIFICATION_LENGTH {
+            match stream.read_u8().await {
+                Ok(b'\n') => return Ok(()),
+                Ok(byte) => line.push(byte),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    })
+    .await;
+
+    match read {
+        Ok(Ok(())) => {},
+        Ok(Err(error)) => {
+            event!(
+                Level::TRACE,
+                ?stream,
+                ?error,
+                "Client gone before identifying"
+            );
+
+            return None;
+        },
+        Err(_elapsed) => {
+            event!(Level::TRACE, ?stream, "No identification received in time");
+
+            if line.is_empty() {
+                return None;
+            }
+        },
+    }
+
+    let identification = String::from_utf8_lossy(&line)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+
+    event!(Level::DEBUG, %identification, "Received identification");
+
+    Some(identification)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, duplex};
+
+    use crate::protocol::ssh::{
+        ClientKex, MAX_IDENTIFICATION_LENGTH, SERVER_ID, SERVER_IDENTIFICATION, SSH_MSG_KEXINIT,
+        binary_packet, kex_handshake, kexinit, read_identification,
+    };
+
+    #[expect(clippy::big_endian_bytes, reason = "SSH is big-endian on the wire")]
+    fn client_kexinit() -> Vec<u8> {
+        let name_lists: [&[u8]; 10] = [
+            b"curve25519-sha256",
+            b"ssh-ed25519",
+            b"aes128-ctr",
+            b"aes256-ctr",
+            b"hmac-sha2-256",
+            b"hmac-sha2-512",
+            b"none",
+            b"zlib",
+            b"",
+            b"",
+        ];
+
+        let mut payload = vec![SSH_MSG_KEXINIT];
+
+        payload.extend_from_slice(&[0xaa; 16]);
+
+        for name_list in name_lists {
+            payload.extend_from_slice(&u32::try_from(name_list.len()).unwrap().to_be_bytes());
+            payload.extend_from_slice(name_list);
+        }
+
+        payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+
+        binary_packet(&payload)
+    }
+
+    fn expected_client_kex() -> ClientKex {
+        ClientKex {
+            hassh: "e97d07603350d1111ec2b64bf25413c9".to_owned(),
+            kex_algorithms: "curve25519-sha256".to_owned(),
+            host_key_algorithms: "ssh-ed25519".to_owned(),
+            encryption_algorithms: "aes128-ctr".to_owned(),
+            mac_algorithms: "hmac-sha2-256".to_owned(),
+            compression_algorithms: "none".to_owned(),
+        }
+    }
+
+    #[test]
+    fn server_identification_is_server_id() {
+        assert_eq!(
+            SERVER_IDENTIFICATION,
+            format!("{}\r\n", SERVER_ID).as_bytes()
+        );
+    }
+
+    #[test]
+    fn pads_binary_packets() {
+        for length in 0..32 {
+            let packet = binary_packet(&vec![1; length]);
+
+            assert_eq!(packet.len() % 8, 0, "{} byte payload", length);
+            assert!(packet[4] >= 4, "{} byte payload", length);
+        }
+    }
+
+    #[test]
+    fn kexinit_is_a_kexinit() {
+        let packet = kexinit();
+
+        assert_eq!(packet[5], SSH_MSG_KEXINIT);
+        assert_eq!(packet.len() % 8, 0);
+    }
+
+    #[tokio::test]
+    async fn fingerprints_client_kexinit() {
+        let (mut client, mut server) = duplex(4096);
+
+        client.write_all(b"SSH-2.0-Go\r\n").await.unwrap();
+        client.write_all(&client_kexinit()).await.unwrap();
+
+        let handshake = kex_handshake(&mut server, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        assert_eq!(handshake.sent, SERVER_IDENTIFICATION.len());
+        assert_eq!(handshake.client_ident.as_deref(), Some("SSH-2.0-Go"));
+        assert_eq!(handshake.client_kex, Some(expected_client_kex()));
+
+        let mut received = vec![0_u8; SERVER_IDENTIFICATION.len()];
+        client.read_exact(&mut received).await.unwrap();
+
+        assert_eq!(received, SERVER_IDENTIFICATION);
+    }
+
+    #[tokio::test]
+    async fn ignores_garbage_instead_of_kexinit() {
+        let (mut client, mut server) = duplex(4096);
+
+        client
+            .write_all(b"SSH-2.0-Go\r\n\xff\xff\xff\xff")
+            .await
+            .unwrap();
+
+        let handshake = kex_handshake(&mut server, Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        assert_eq!(handshake.client_ident.as_deref(), Some("SSH-2.0-Go"));
+        assert_eq!(handshake.client_kex, None);
+    }
+
+    #[tokio::test]
+    async fn reads_identification_line() {
+        let (mut client, mut server) = duplex(1024);
+
+        client
+            .write_all(b"SSH-2.0-OpenSSH_9.6\r\n\x00\x00\x05\xdc")
+            .await
+            .unwrap();
+
+        let result = read_identification(&mut server, Duration::from_secs(1)).await;
+
+        assert_eq!(result.as_deref(), Some("SSH-2.0-OpenSSH_9.6"));
+
+        // whatever came after the line is still there
+        let mut rest = [0_u8; 4];
+        server.read_exact(&mut rest).await.unwrap();
+
+        assert_eq!(rest, [0x00, 0x00, 0x05, 0xdc]);
+    }
+
+    #[tokio::test]
+    async fn caps_identification_length() {
+        let (mut client, mut server) = duplex(1024);
+
+        client.write_all(&[b'a'; 1000]).await.unwrap();
+
+        let result = read_identification(&mut server, Duration::from_secs(1)).await;
+
+        assert_eq!(result.map(|i| i.len()), Some(MAX_IDENTIFICATION_LENGTH));
+    }
+
+    #[tokio::test]
+    async fn strips_control_characters() {
+        let (mut client, mut server) = duplex(1024);
+
+        client.write_all(b"SSH-2.0-Go\x00\x1b\r\n").await.unwrap();
+
+        let result = read_identification(&mut server, Duration::from_secs(1)).await;
+
+        assert_eq!(result.as_deref(), Some("SSH-2.0-Go"));
+    }
+
+    #[tokio::test]
+    async fn silent_client() {
+        let (_client, mut server) = duplex(1024);
+
+        let result = read_identification(&mut server, Duration::from_millis(10)).await;
+
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    async fn client_gone() {
+        let (client, mut server) = duplex(1024);
+
+        drop(client);
+
+        let result = read_identification(&mut server, Duration::from_secs(1)).await;
+
+        assert_eq!(result, None);
+    }
+}
@@ -0,0 +1,454 @@
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use ipnet::IpNet;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt as _};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+/// Starts every v1 header, see <https://www.haproxy.org/download/3.1/doc/proxy-protocol.txt>.
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+/// Starts every v2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest v1 header, CRLF included.
+const V1_MAX_LENGTH: usize = 107;
+/// Signature, version and command, family and protocol, and the length of the addresses.
+const V2_HEADER_LENGTH: usize = 16;
+
+/// How long a connection gets to send its first bytes, after that it's taken to send no header.
+/// Clients of protocols where the server speaks first, like SMTP, wait this long for their greeting.
+const FIRST_BYTES_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long a proxy gets for the rest of its header.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the rest of a signature that came in part.
+const PEEK_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Error, Debug)]
+pub enum HeaderError {
+    #[error("PROXY header from an untrusted source")]
+    Untrusted,
+    #[error("Malformed PROXY header")]
+    Malformed,
+    #[error("PROXY header not received in time")]
+    TimedOut,
+    #[error("I/O error: {0}")]
+    Io(
+        #[from]
+        #[source]
+        io::Error,
+    ),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    V1,
+    V2,
+}
+
+/// What the first bytes of a connection tell us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Signature {
+    Found(Version),
+    Absent,
+    /// Too few bytes to tell.
+    Incomplete,
+}
+
+fn detect(bytes: &[u8]) -> Signature {
+    for (version, signature) in [(Version::V1, V1_SIGNATURE), (Version::V2, V2_SIGNATURE)] {
+        let length = bytes.len().min(signature.len());
+
+        if bytes[..length] == signature[..length] {
+            return if length == signature.len() {
+                Signature::Found(version)
+            } else {
+                Signature::Incomplete
+            };
+        }
+    }
+
+    Signature::Absent
+}
+
+/// Whether `peer` may tell us who the client is.
+pub fn is_trusted(trusted_proxies: &[IpNet], peer: IpAddr) -> bool {
+    let peer = peer.to_canonical();
+
+    trusted_proxies.iter().any(|net| net.contains(&peer))
+}
+
+/// The address of the client behind `peer`, taken from the PROXY header `stream` starts with.
+/// Without a header, the client is `peer` itself. A header from a source not in `trusted_proxies` is an error.
+pub async fn read_header(
+    stream: &mut TcpStream,
+    peer: SocketAddr,
+    trusted_proxies: &[IpNet],
+) -> Result<SocketAddr, HeaderError> {
+    let version = match timeout(FIRST_BYTES_TIMEOUT, peek_signature(stream)).await {
+        Ok(version) => version?,
+        // a client waiting for us to speak first
+        Err(_elapsed) => None,
+    };
+
+    let Some(version) = version else {
+        return Ok(peer);
+    };
+
+    if !is_trusted(trusted_proxies, peer.ip()) {
+        return Err(HeaderError::Untrusted);
+    }
+
+    let addr = timeout(HEADER_TIMEOUT, read(stream, version))
+        .await
+        .map_err(|_elapsed| HeaderError::TimedOut)??;
+
+    // health checks and the like, from the proxy itself
+    Ok(addr.unwrap_or(peer))
+}
+
+/// Looks at the first bytes without taking them, a connection without a header keeps them.
+async fn peek_signature(stream: &TcpStream) -> Result<Option<Version>, HeaderError> {
+    let mut buffer = [0; V2_SIGNATURE.len()];
+
+    loop {
+        let peeked = stream.peek(&mut buffer).await?;
+
+        if peeked == 0 {
+            return Err(HeaderError::Io(io::Error::from(ErrorKind::UnexpectedEof)));
+        }
+
+        match detect(&buffer[..peeked]) {
+            Signature::Found(version) => return Ok(Some(version)),
+            Signature::Absent => return Ok(None),
+            // proxies send their header in one go, the rest is on its way
+            Signature::Incomplete => sleep(PEEK_INTERVAL).await,
+        }
+    }
+}
+
+/// Reads the whole header, `Ok(None)` when it carries no address.
+#[expect(
+    clippy::big_endian_bytes,
+    reason = "PROXY v2 is big-endian on the wire"
+)]
+async fn read<S>(stream: &mut S, version: Version) -> Result<Option<SocketAddr>, HeaderError>
+where
+    S: AsyncRead + Unpin,
+{
+    match version {
+        Version::V1 => {
+            let mut line = Vec::with_capacity(V1_MAX_LENGTH);
+
+            while !line.ends_with(b"\r\n") {
+                if line.len() == V1_MAX_LENGTH {
+                    return Err(HeaderError::Malformed);
+                }
+
+                line.push(stream.read_u8().await?);
+            }
+
+            parse_v1(&line[..line.len() - 2])
+        },
+        Version::V2 => {
+            let mut header = [0; V2_HEADER_LENGTH];
+
+            stream.read_exact(&mut header).await?;
+
+            let mut addresses = vec![0; usize::from(u16::from_be_bytes([header[14], header[15]]))];
+
+            stream.read_exact(&mut addresses).await?;
+
+            parse_v2(&header, &addresses)
+        },
+    }
+}
+
+/// `PROXY TCP4 SOURCE DESTINATION SOURCE_PORT DESTINATION_PORT`, without its CRLF.
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>, HeaderError> {
+    let line = str::from_utf8(line).map_err(|_| HeaderError::Malformed)?;
+
+    let mut parts = line.split(' ');
+
+    if parts.next() != Some("PROXY") {
+        return Err(HeaderError::Malformed);
+    }
+
+    let source = match parts.next() {
+        Some("TCP4") => parts
+            .next()
+            .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+            .map(IpAddr::V4),
+        Some("TCP6") => parts
+            .next()
+            .and_then(|ip| ip.parse::<Ipv6Addr>().ok())
+            .map(IpAddr::V6),
+        // whatever follows is to be ignored
+        Some("UNKNOWN") => return Ok(None),
+        _ => None,
+    };
+
+    let Some(source) = source else {
+        return Err(HeaderError::Malformed);
+    };
+
+    // the destination is ours, we only check it's there
+    let (Some(_destination), Some(port), Some(_destination_port), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(HeaderError::Malformed);
+    };
+
+    let port = port.parse::<u16>().map_err(|_| HeaderError::Malformed)?;
+
+    Ok(Some(SocketAddr::new(source, port)))
+}
+
+/// The binary header, followed by the addresses it announces.
+#[expect(
+    clippy::big_endian_bytes,
+    reason = "PROXY v2 is big-endian on the wire"
+)]
+fn parse_v2(
+    header: &[u8; V2_HEADER_LENGTH],
+    addresses: &[u8],
+) -> Result<Option<SocketAddr>, HeaderError> {
+    let version_command = header[12];
+    let family = header[13] >> 4;
+
+    if version_command >> 4 != 2 {
+        return Err(HeaderError::Malformed);
+    }
+
+    match version_command & 0x0F {
+        // LOCAL, the proxy speaks for itself
+        0x0 => return Ok(None),
+        // PROXY
+        0x1 => {},
+        _ => return Err(HeaderError::Malformed),
+    }
+
+    match family {
+        // AF_INET, 2 addresses of 4 bytes and 2 ports
+        0x1 => {
+            let Some(&[a, b, c, d]) = addresses.get(..4) else {
+                return Err(HeaderError::Malformed);
+            };
+            let Some(&[high, low]) = addresses.get(8..10) else {
+                return Err(HeaderError::Malformed);
+            };
+
+            Ok(Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+                u16::from_be_bytes([high, low]),
+            )))
+        },
+        // AF_INET6, 2 addresses of 16 bytes and 2 ports
+        0x2 => {
+            let Some(source) = addresses
+                .get(..16)
+                .and_then(|source| <[u8; 16]>::try_from(source).ok())
+            else {
+                return Err(HeaderError::Malformed);
+            };
+            let Some(&[high, low]) = addresses.get(32..34) else {
+                return Err(HeaderError::Malformed);
+            };
+
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(source)),
+                u16::from_be_bytes([high, low]),
+            )))
+        },
+        // AF_UNSPEC and AF_UNIX, nothing we can store
+        0x0 | 0x3 => Ok(None),
+        _ => Err(HeaderError::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use ipnet::IpNet;
+    use pretty_assertions::{assert_eq, assert_matches};
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, duplex};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::proxy_protocol::{
+        HeaderError, Signature, V2_SIGNATURE, Version, detect, is_trusted, parse_v1, parse_v2,
+        read, read_header,
+    };
+
+    #[expect(
+        clippy::big_endian_bytes,
+        reason = "PROXY v2 is big-endian on the wire"
+    )]
+    fn v2_header(version_command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+
+        header.push(version_command);
+        header.push(family);
+        header.extend_from_slice(&u16::try_from(addresses.len()).unwrap().to_be_bytes());
+        header.extend_from_slice(addresses);
+
+        header
+    }
+
+    #[test]
+    fn detects_signatures() {
+        assert_eq!(detect(b"PROXY TCP4"), Signature::Found(Version::V1));
+        assert_eq!(detect(V2_SIGNATURE), Signature::Found(Version::V2));
+        assert_eq!(detect(b"PRO"), Signature::Incomplete);
+        assert_eq!(detect(b"\r\n\r\n"), Signature::Incomplete);
+        assert_eq!(detect(b"POST / HTTP/1.1"), Signature::Absent);
+        assert_eq!(detect(b"SSH-2.0-OpenSSH_9.6\r\n"), Signature::Absent);
+    }
+
+    #[test]
+    fn parses_v1() {
+        assert_matches!(
+            parse_v1(b"PROXY TCP4 198.51.100.7 192.0.2.1 51234 22"),
+            Ok(Some(addr)) if addr == SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)), 51234)
+        );
+        assert_matches!(
+            parse_v1(b"PROXY TCP6 2001:db8::7 2001:db8::1 51234 22"),
+            Ok(Some(addr)) if addr == SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7)), 51234)
+        );
+        assert_matches!(parse_v1(b"PROXY UNKNOWN"), Ok(None));
+
+        assert_matches!(
+            parse_v1(b"PROXY TCP4 2001:db8::7 192.0.2.1 51234 22"),
+            Err(HeaderError::Malformed)
+        );
+        assert_matches!(
+            parse_v1(b"PROXY TCP4 198.51.100.7 192.0.2.1 51234"),
+            Err(HeaderError::Malformed)
+        );
+        assert_matches!(
+            parse_v1(b"PROXY TCP4 198.51.100.7 192.0.2.1 65536 22"),
+            Err(HeaderError::Malformed)
+        );
+    }
+
+    #[test]
+    fn parses_v2() {
+        let header = v2_header(
+            0x21,
+            0x11,
+            &[198, 51, 100, 7, 192, 0, 2, 1, 0xC8, 0x22, 0, 22],
+        );
+        let (header, addresses) = header.split_at(16);
+
+        assert_matches!(
+            parse_v2(header.try_into().unwrap(), addresses),
+            Ok(Some(addr)) if addr == SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)), 51234)
+        );
+
+        // LOCAL
+        let header = v2_header(0x20, 0x00, &[]);
+
+        assert_matches!(parse_v2(header[..16].try_into().unwrap(), &[]), Ok(None));
+
+        // addresses cut short
+        let header = v2_header(0x21, 0x21, &[0; 20]);
+        let (header, addresses) = header.split_at(16);
+
+        assert_matches!(
+            parse_v2(header.try_into().unwrap(), addresses),
+            Err(HeaderError::Malformed)
+        );
+
+        // version 1 in a binary header
+        let header = v2_header(0x11, 0x11, &[0; 12]);
+        let (header, addresses) = header.split_at(16);
+
+        assert_matches!(
+            parse_v2(header.try_into().unwrap(), addresses),
+            Err(HeaderError::Malformed)
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_only_the_header() {
+        let mut addresses = Vec::new();
+
+        addresses.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7).octets());
+        addresses.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).octets());
+        addresses.extend_from_slice(&[0xC8, 0x22, 0, 22]);
+        // TLVs, ignored
+        addresses.extend_from_slice(&[0x04, 0, 1, 0]);
+
+        let (mut client, mut server) = duplex(1024);
+
+        client
+            .write_all(&v2_header(0x21, 0x21, &addresses))
+            .await
+            .unwrap();
+        client.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.unwrap();
+
+        assert_matches!(
+            read(&mut server, Version::V2).await,
+            Ok(Some(addr)) if addr == SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7)), 51234)
+        );
+
+        let mut rest = [0; 4];
+
+        server.read_exact(&mut rest).await.unwrap();
+
+        assert_eq!(&rest, b"SSH-");
+    }
+
+    #[tokio::test]
+    async fn refuses_endless_v1_headers() {
+        let (mut client, mut server) = duplex(1024);
+
+        client.write_all(&[b'A'; 200]).await.unwrap();
+
+        assert_matches!(
+            read(&mut server, Version::V1).await,
+            Err(HeaderError::Malformed)
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_headers_from_untrusted_sources() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut stream, peer) = listener.accept().await.unwrap();
+
+        client
+            .write_all(b"PROXY TCP4 198.51.100.7 192.0.2.1 51234 22\r\n")
+            .await
+            .unwrap();
+
+        let trusted_proxies = ["192.0.2.0/24".parse::<IpNet>().unwrap()];
+
+        assert_matches!(
+            read_header(&mut stream, peer, &trusted_proxies).await,
+            Err(HeaderError::Untrusted)
+        );
+    }
+
+    #[test]
+    fn trusts_configured_sources() {
+        let trusted_proxies = ["10.0.0.0/8".parse::<IpNet>().unwrap()];
+
+        assert!(is_trusted(
+            &trusted_proxies,
+            IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))
+        ));
+        // as seen on dual-stack sockets
+        assert!(is_trusted(
+            &trusted_proxies,
+            IpAddr::V6(Ipv4Addr::new(10, 1, 2, 3).to_ipv6_mapped())
+        ));
+        assert!(!is_trusted(
+            &trusted_proxies,
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))
+        ));
+    }
+}
@@ -1,5 +1,5 @@
 mod api_router;
-mod html_router;
+pub mod html_router;
 mod ws_router;
 
 use axum::Router;
@@ -25,9 +25,10 @@ async fn healthz() -> impl IntoResponse {
     (StatusCode::OK, "Hello, world!")
 }
 
-pub fn build_router(state: ApplicationState) -> Router {
+/// `front_end_proxy` is where the dashboard comes from, `dist` when `None`.
+pub fn build_router(state: ApplicationState, front_end_proxy: Option<&str>) -> Router {
     let api_router = build_api_router(state);
-    let html_router = build_html_router();
+    let html_router = build_html_router(front_end_proxy);
 
     // we can move the `/healthz` layer beneath the `TraceLayer` to prevent it from being logged
 
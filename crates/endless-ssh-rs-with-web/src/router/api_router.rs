@@ -1,4 +1,4 @@
-use axum::extract::{Query, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::get;
@@ -9,6 +9,7 @@ use time::format_description::well_known::Rfc3339;
 use tracing::{Level, event};
 
 use crate::db;
+use crate::db::types::Limit;
 use crate::router::ws_router::ws_handler;
 use crate::state::ApplicationState;
 
@@ -16,6 +17,13 @@ pub fn build_api_router(state: ApplicationState) -> Router {
     Router::new()
         .route("/ws", get(ws_handler))
         .route("/stats", get(stats_handler))
+        .route("/stats/listeners", get(listener_stats_handler))
+        .route("/stats/clients", get(client_software_stats_handler))
+        .route("/stats/hassh", get(hassh_stats_handler))
+        .route("/stats/delays", get(delay_stats_handler))
+        .route("/auth-attempts", get(auth_attempts_handler))
+        .route("/connections/{id}", get(connection_handler))
+        .route("/engine", get(engine_handler))
         .with_state(state)
 }
 
@@ -25,6 +33,29 @@ pub struct StatsQueryParams {
     to: Option<String>,
 }
 
+/// Resolves the requested range. `to` falls back to now, `from` to 24 hours before `to`.
+fn resolve_range(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(OffsetDateTime, OffsetDateTime), (StatusCode, &'static str)> {
+    let now = OffsetDateTime::now_utc();
+
+    let to = to
+        .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
+        .unwrap_or(now);
+
+    // if no from provided, or invalid, fall back to 24 hours before `to`
+    let from = from
+        .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
+        .unwrap_or_else(|| to - time::SignedDuration::hours(24));
+
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "`from` needs to be before `to`"));
+    }
+
+    Ok((from, to))
+}
+
 // GET /api/stats?from=<rfc3339>&to=<rfc3339>
 async fn stats_handler(
     Query(StatsQueryParams { from, to }): Query<StatsQueryParams>,
@@ -33,24 +64,10 @@ async fn stats_handler(
     let from_to = if from.is_none() && to.is_none() {
         None
     } else {
-        let now = OffsetDateTime::now_utc();
-
-        let to = to
-            .as_deref()
-            .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
-            .unwrap_or(now);
-
-        // if no from provided, or invalid, fall back to 24 hours before `to`
-        let from = from
-            .as_deref()
-            .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
-            .unwrap_or_else(|| to - time::SignedDuration::hours(24));
-
-        if from > to {
-            return (StatusCode::BAD_REQUEST, "`from` needs to be before `to`").into_response();
+        match resolve_range(from.as_deref(), to.as_deref()) {
+            Ok(from_to) => Some(from_to),
+            Err(rejection) => return rejection.into_response(),
         }
-
-        Some((from, to))
     };
 
     match db::get_stats(&state.db_pool, from_to).await {
@@ -62,3 +79,144 @@ async fn stats_handler(
         },
     }
 }
+
+// GET /api/stats/listeners?from=<rfc3339>&to=<rfc3339>
+async fn listener_stats_handler(
+    Query(StatsQueryParams { from, to }): Query<StatsQueryParams>,
+    State(state): State<ApplicationState>,
+) -> impl IntoResponse {
+    let (from, to) = match resolve_range(from.as_deref(), to.as_deref()) {
+        Ok(from_to) => from_to,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match db::get_listener_stats(&state.db_pool, from, to).await {
+        Ok(rows) => Json(rows).into_response(),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Listener stats query failed");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "listener stats query failed",
+            )
+                .into_response()
+        },
+    }
+}
+
+// GET /api/stats/clients?from=<rfc3339>&to=<rfc3339>
+async fn client_software_stats_handler(
+    Query(StatsQueryParams { from, to }): Query<StatsQueryParams>,
+    State(state): State<ApplicationState>,
+) -> impl IntoResponse {
+    let (from, to) = match resolve_range(from.as_deref(), to.as_deref()) {
+        Ok(from_to) => from_to,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match db::get_client_software_stats(&state.db_pool, from, to).await {
+        Ok(rows) => Json(rows).into_response(),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Client software stats query failed");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "client software stats query failed",
+            )
+                .into_response()
+        },
+    }
+}
+
+// GET /api/stats/hassh?from=<rfc3339>&to=<rfc3339>
+async fn hassh_stats_handler(
+    Query(StatsQueryParams { from, to }): Query<StatsQueryParams>,
+    State(state): State<ApplicationState>,
+) -> impl IntoResponse {
+    let (from, to) = match resolve_range(from.as_deref(), to.as_deref()) {
+        Ok(from_to) => from_to,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match db::get_hassh_stats(&state.db_pool, from, to).await {
+        Ok(rows) => Json(rows).into_response(),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "HASSH stats query failed");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "HASSH stats query failed",
+            )
+                .into_response()
+        },
+    }
+}
+
+// GET /api/stats/delays?from=<rfc3339>&to=<rfc3339>
+async fn delay_stats_handler(
+    Query(StatsQueryParams { from, to }): Query<StatsQueryParams>,
+    State(state): State<ApplicationState>,
+) -> impl IntoResponse {
+    let (from, to) = match resolve_range(from.as_deref(), to.as_deref()) {
+        Ok(from_to) => from_to,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match db::get_delay_stats(&state.db_pool, from, to).await {
+        Ok(rows) => Json(rows).into_response(),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Delay stats query failed");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "delay stats query failed",
+            )
+                .into_response()
+        },
+    }
+}
+
+// GET /api/auth-attempts?from=<rfc3339>&to=<rfc3339>
+async fn auth_attempts_handler(
+    Query(StatsQueryParams { from, to }): Query<StatsQueryParams>,
+    State(state): State<ApplicationState>,
+) -> impl IntoResponse {
+    let (from, to) = match resolve_range(from.as_deref(), to.as_deref()) {
+        Ok(from_to) => from_to,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match db::get_auth_attempts(&state.db_pool, from, to, Limit::Limit(1000)).await {
+        Ok(rows) => Json(rows).into_response(),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Auth attempts query failed");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "auth attempts query failed",
+            )
+                .into_response()
+        },
+    }
+}
+
+// GET /api/connections/<id>
+async fn connection_handler(
+    Path(id): Path<i64>,
+    State(state): State<ApplicationState>,
+) -> impl IntoResponse {
+    match db::get_connection(&state.db_pool, id).await {
+        Ok(Some(row)) => Json(row).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "no such connection").into_response(),
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Connection query failed");
+
+            (StatusCode::INTERNAL_SERVER_ERROR, "connection query failed").into_response()
+        },
+    }
+}
+
+// GET /api/engine
+async fn engine_handler(State(state): State<ApplicationState>) -> impl IntoResponse {
+    Json(state.engine.stats())
+}
@@ -1,9 +1,14 @@
+use std::path::Path;
+
 use axum::Router;
 use tower_http::services::{ServeDir, ServeFile};
 use tracing::{Level, event};
 
-pub fn build_html_router() -> Router {
-    if let Ok(front_end_proxy) = std::env::var("FRONT_END_PROXY").as_deref() {
+/// The built dashboard, served unless it's proxied.
+pub const DIST: &str = "dist";
+
+pub fn build_html_router(front_end_proxy: Option<&str>) -> Router {
+    if let Some(front_end_proxy) = front_end_proxy {
         event!(Level::INFO, "Serving website via proxy");
 
         let vite_proxy_service_builder = tower_proxy::builder_http(front_end_proxy).unwrap();
@@ -17,7 +22,8 @@ pub fn build_html_router() -> Router {
         Router::new().fallback_service(svc)
     } else {
         event!(Level::INFO, "Serving website from dist");
-        Router::new()
-            .fallback_service(ServeDir::new("dist").fallback(ServeFile::new("dist/index.html")))
+        Router::new().fallback_service(
+            ServeDir::new(DIST).fallback(ServeFile::new(Path::new(DIST).join("index.html"))),
+        )
     }
 }
@@ -68,14 +68,21 @@ async fn send_connection_record(
     socket: &mut WebSocket,
     record: ConnectionRecord,
 ) -> Result<(), ()> {
+    let tcp_metrics = record.tcp_metrics();
+
     let ws_event = WsEvent::Disconnected {
         sequence: record.id,
         ip: record.ip_address.into(),
         port: record.port.into(),
+        listener: record.listener,
+        protocol: record.protocol.into(),
+        client_ident: record.client_ident,
+        hassh: record.hassh,
         connected_at: record.connected_at,
         disconnected_at: record.disconnected_at,
         time_spent: record.time_spent.into(),
         bytes_sent: usize::try_from(record.bytes_sent).unwrap_or(0),
+        tcp_metrics,
         country_code: record.country_code,
         country_name: record.country_name,
         city: record.city,
@@ -123,7 +130,7 @@ async fn handle_socket(
     let active: Vec<_> = state
         .active_connections
         .iter()
-        .map(|v| v.value().clone())
+        .map(|v| v.value().current())
         .collect::<Vec<ActiveConnectionInfo>>();
 
     let since_id = params.since.unwrap_or(0);
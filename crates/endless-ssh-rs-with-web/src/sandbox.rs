@@ -0,0 +1,141 @@
+mod landlock;
+mod seccomp;
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use color_eyre::eyre;
+use libc::{SECCOMP_RET_KILL_PROCESS, SECCOMP_RET_LOG, gettid, pid_t};
+use tracing::{Level, event};
+
+use crate::config::Config;
+use crate::geoip::GEO_IP_PATH;
+use crate::line::generator::LineSource;
+use crate::router::html_router::DIST;
+use crate::sandbox::landlock::TaskDir;
+
+/// What DNS lookups of the database's host read.
+const RESOLVER_FILES: &[&str] = &[
+    "/etc/hosts",
+    "/etc/resolv.conf",
+    "/etc/nsswitch.conf",
+    "/etc/host.conf",
+    "/etc/gai.conf",
+];
+
+/// How the daemon is locked down once it's started, see [`install`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sandbox {
+    #[default]
+    Off,
+    /// Landlock restricts the filesystem, and a syscall outside the seccomp filter kills the process.
+    Enforce,
+    /// Syscalls outside the seccomp filter are only logged, by the kernel, and the filesystem is left alone.
+    Audit,
+}
+
+impl Sandbox {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Sandbox::Off => "off",
+            Sandbox::Enforce => "enforce",
+            Sandbox::Audit => "audit",
+        }
+    }
+}
+
+impl Display for Sandbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Sandbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Sandbox::Off),
+            "enforce" => Ok(Sandbox::Enforce),
+            "audit" => Ok(Sandbox::Audit),
+            _ => Err(format!(
+                "Unknown sandbox mode `{}`, expected `off`, `enforce` or `audit`",
+                s
+            )),
+        }
+    }
+}
+
+fn current_thread() -> pid_t {
+    // SAFETY: external call, it can't fail
+    unsafe { gettid() }
+}
+
+/// Opens what [`install`] finds the threads in, before the privileges are dropped and the chroot hides it. `None`
+/// unless the sandbox enforces.
+pub fn open_task_dir(config: &Config) -> Result<Option<TaskDir>, eyre::Report> {
+    match config.sandbox {
+        Sandbox::Enforce => TaskDir::open().map(Some),
+        Sandbox::Off | Sandbox::Audit => Ok(None),
+    }
+}
+
+/// What the daemon still reads once it's started, in the chroot if there is one: the dashboard, the IP database, and
+/// what a reload reads.
+fn readable(config: &Config) -> Vec<PathBuf> {
+    let mut readable = vec![
+        PathBuf::from(DIST),
+        Path::new(GEO_IP_PATH)
+            .parent()
+            .map_or_else(|| PathBuf::from(GEO_IP_PATH), Path::to_path_buf),
+        PathBuf::from(".env"),
+        config.ssh_host_key.clone(),
+    ];
+
+    readable.extend(config.file.clone());
+    readable.extend(RESOLVER_FILES.iter().map(PathBuf::from));
+
+    for listener in &config.listeners {
+        match listener.line_generator {
+            LineSource::Random => {},
+            LineSource::Corpus(ref path) | LineSource::Markov(ref path) => {
+                readable.push(path.clone());
+            },
+        }
+    }
+
+    readable
+}
+
+/// With `Enforce`, restricts every thread, listed by `task_dir`, to reading what [`readable`] lists, and installs the
+/// seccomp filter, which kills the process on a syscall outside it. With `Audit`, the filter only has the kernel log
+/// those, and the filesystem is left alone, Landlock can't only log. Threads started later inherit both.
+pub fn install(config: &Config, task_dir: Option<&TaskDir>) -> Result<(), eyre::Report> {
+    let default_action = match (config.sandbox, task_dir) {
+        (Sandbox::Off, _) => return Ok(()),
+        (Sandbox::Enforce, Some(task_dir)) => {
+            landlock::restrict_threads(&readable(config), task_dir)?;
+
+            SECCOMP_RET_KILL_PROCESS
+        },
+        (Sandbox::Enforce, None) => {
+            return Err(eyre::eyre!("Can't list the threads to restrict"));
+        },
+        (Sandbox::Audit, _) => {
+            event!(
+                Level::WARN,
+                readable = ?readable(config),
+                "Filesystem not restricted in audit mode, reading anything but these would fail when enforced"
+            );
+
+            SECCOMP_RET_LOG
+        },
+    };
+
+    seccomp::install(default_action)?;
+
+    event!(Level::INFO, sandbox = %config.sandbox, "Sandbox installed");
+
+    Ok(())
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeSet;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind};
+use std::mem::{size_of_val, zeroed};
+use std::os::fd::{AsRawFd as _, FromRawFd as _, IntoRawFd as _, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
+
+use color_eyre::eyre::{self, WrapErr as _};
+use libc::{
+    __errno_location, ESRCH, O_CLOEXEC, O_DIRECTORY, O_PATH, O_RDONLY, PR_SET_NO_NEW_PRIVS,
+    SA_RESTART, SIGRTMIN, SYS_landlock_add_rule, SYS_landlock_create_ruleset,
+    SYS_landlock_restrict_self, c_int, c_ulong, closedir, fdopendir, getpid, openat, pid_t, prctl,
+    readdir, sigaction, sigemptyset, sighandler_t, syscall, tgkill,
+};
+use tracing::{Level, event};
+
+use crate::sandbox::current_thread;
+
+/// `LANDLOCK_CREATE_RULESET_VERSION`, asks for the highest ABI the kernel supports instead of creating a ruleset.
+const CREATE_RULESET_VERSION: u32 = 1;
+
+/// `LANDLOCK_RULE_PATH_BENEATH`.
+const RULE_PATH_BENEATH: c_int = 1;
+
+/// `LANDLOCK_ACCESS_FS_READ_FILE`.
+const READ_FILE: u64 = 1 << 2;
+/// `LANDLOCK_ACCESS_FS_READ_DIR`.
+const READ_DIR: u64 = 1 << 3;
+/// Every filesystem access of the first ABI, from `LANDLOCK_ACCESS_FS_EXECUTE` to `LANDLOCK_ACCESS_FS_MAKE_SYM`.
+const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+/// `LANDLOCK_ACCESS_FS_REFER`, from ABI 2 on.
+const REFER: u64 = 1 << 13;
+/// `LANDLOCK_ACCESS_FS_TRUNCATE`, from ABI 3 on.
+const TRUNCATE: u64 = 1 << 14;
+/// `LANDLOCK_ACCESS_FS_IOCTL_DEV`, from ABI 5 on.
+const IOCTL_DEV: u64 = 1 << 15;
+
+/// How long the other threads get to restrict themselves.
+const SIGNALED_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `struct landlock_ruleset_attr`, as of the first ABI, the kernel takes the size we pass.
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+/// `struct landlock_path_beneath_attr`.
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: c_int,
+}
+
+/// The ruleset every thread restricts itself with, -1 until it's installed.
+static RULESET: AtomicI32 = AtomicI32::new(-1);
+/// Signaled threads done restricting themselves.
+static SIGNALED_DONE: AtomicUsize = AtomicUsize::new(0);
+/// Signaled threads that failed to.
+static SIGNALED_FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// `/proc/self/task`, opened before the chroot hides `/proc`, it lists every thread of the process, whoever started
+/// them.
+pub struct TaskDir(File);
+
+impl TaskDir {
+    pub fn open() -> Result<Self, eyre::Report> {
+        File::open("/proc/self/task")
+            .map(Self)
+            .wrap_err("Failed to open `/proc/self/task`")
+    }
+
+    /// The threads running now.
+    fn threads(&self) -> Result<BTreeSet<pid_t>, Error> {
+        // SAFETY: external call, on the directory's file descriptor, with a nul terminated path
+        let fd = unsafe {
+            openat(
+                self.0.as_raw_fd(),
+                c".".as_ptr(),
+                O_RDONLY | O_DIRECTORY | O_CLOEXEC,
+            )
+        };
+
+        if fd == -1 {
+            return Err(Error::last_os_error());
+        }
+
+        // SAFETY: external call, the stream owns the file descriptor from then on
+        let dir = unsafe { fdopendir(fd) };
+
+        if dir.is_null() {
+            let error = Error::last_os_error();
+
+            // SAFETY: just opened, nothing else owns it
+            drop(unsafe { OwnedFd::from_raw_fd(fd) });
+
+            return Err(error);
+        }
+
+        let mut threads = BTreeSet::new();
+
+        loop {
+            // SAFETY: external call, on the open stream
+            let entry = unsafe { readdir(dir) };
+
+            if entry.is_null() {
+                break;
+            }
+
+            // SAFETY: a valid entry until the next readdir
+            let entry = unsafe { &*entry };
+
+            // SAFETY: the name is nul terminated
+            let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) };
+
+            // `.` and `..` aren't threads
+            if let Some(thread) = name.to_str().ok().and_then(|name| name.parse().ok()) {
+                threads.insert(thread);
+            }
+        }
+
+        // SAFETY: external call, the stream isn't used afterwards
+        unsafe {
+            closedir(dir);
+        }
+
+        Ok(threads)
+    }
+}
+
+/// A ruleset that handles every filesystem access the kernel knows of, and allows reading `readable` only. Those
+/// that don't exist are left out.
+fn create_ruleset(readable: &[PathBuf]) -> Result<OwnedFd, eyre::Report> {
+    // SAFETY: external call, asking for the version takes no attributes
+    let abi = unsafe {
+        syscall(
+            SYS_landlock_create_ruleset,
+            ptr::null::<RulesetAttr>(),
+            0_usize,
+            CREATE_RULESET_VERSION,
+        )
+    };
+
+    if abi == -1 {
+        return Err(Error::last_os_error()).wrap_err("Landlock isn't available");
+    }
+
+    let mut handled = ACCESS_FS_V1;
+
+    for (since, access) in [(2, REFER), (3, TRUNCATE), (5, IOCTL_DEV)] {
+        if abi >= since {
+            handled |= access;
+        }
+    }
+
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+
+    // SAFETY: external call, attr is valid for reads of its size
+    let fd = unsafe {
+        syscall(
+            SYS_landlock_create_ruleset,
+            &raw const attr,
+            size_of_val(&attr),
+            0_u32,
+        )
+    };
+
+    if fd == -1 {
+        return Err(Error::last_os_error()).wrap_err("Failed to create the Landlock ruleset");
+    }
+
+    // SAFETY: just created, nothing else owns it
+    let ruleset = unsafe { OwnedFd::from_raw_fd(RawFd::try_from(fd)?) };
+
+    for path in readable {
+        let file = match OpenOptions::new()
+            .read(true)
+            .custom_flags(O_PATH | O_CLOEXEC)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                event!(Level::DEBUG, path = %path.display(), "Not found, not readable in the sandbox");
+
+                continue;
+            },
+            Err(error) => {
+                return Err(error).wrap_err_with(|| format!("Failed to open `{}`", path.display()));
+            },
+        };
+
+        // directory rights on a file are invalid
+        let allowed = if file.metadata()?.is_dir() {
+            READ_FILE | READ_DIR
+        } else {
+            READ_FILE
+        };
+
+        let rule = PathBeneathAttr {
+            allowed_access: allowed,
+            parent_fd: file.as_raw_fd(),
+        };
+
+        // SAFETY: external call, on the ruleset's file descriptor, rule is valid for reads
+        let r = unsafe {
+            syscall(
+                SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &raw const rule,
+                0_u32,
+            )
+        };
+
+        if r == -1 {
+            return Err(Error::last_os_error())
+                .wrap_err_with(|| format!("Failed to allow reading `{}`", path.display()));
+        }
+    }
+
+    Ok(ruleset)
+}
+
+/// Restricts the calling thread with `ruleset`, only system calls, so it's fine in a signal handler.
+fn restrict_self(ruleset: RawFd) -> Result<(), Error> {
+    let (enable, unused): (c_ulong, c_ulong) = (1, 0);
+
+    // SAFETY: external call, with the 3 unused arguments set to 0, as required
+    if unsafe { prctl(PR_SET_NO_NEW_PRIVS, enable, unused, unused, unused) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    // SAFETY: external call, on the ruleset's file descriptor
+    if unsafe { syscall(SYS_landlock_restrict_self, ruleset, 0_u32) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// The handler of the signal the other threads get, like glibc's `setuid` reaching every thread.
+extern "C" fn on_signal(_signal: c_int) {
+    // SAFETY: external call, errno is the thread's own
+    let errno = unsafe { __errno_location() };
+
+    // SAFETY: errno is valid for reads and writes, and the interrupted code may still read it
+    let saved = unsafe { *errno };
+
+    if restrict_self(RULESET.load(Ordering::SeqCst)).is_err() {
+        SIGNALED_FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    SIGNALED_DONE.fetch_add(1, Ordering::SeqCst);
+
+    // SAFETY: see above
+    unsafe {
+        *errno = saved;
+    }
+}
+
+#[expect(
+    clippy::as_conversions,
+    clippy::fn_to_numeric_cast_any,
+    reason = "sigaction takes the handler as an integer"
+)]
+fn handler() -> sighandler_t {
+    on_signal as extern "C" fn(c_int) as sighandler_t
+}
+
+/// Restricts every thread, listed by `task_dir`, to reading `readable`. Landlock only restricts the thread asking, so
+/// the others are signaled to ask too. Threads they start before they do show up when they're listed again, those
+/// started afterwards inherit the restriction. The calling thread goes last.
+pub fn restrict_threads(readable: &[PathBuf], task_dir: &TaskDir) -> Result<(), eyre::Report> {
+    let ruleset = create_ruleset(readable)?;
+
+    // SAFETY: all zeroes is a valid sigaction, an empty one
+    let mut action: sigaction = unsafe { zeroed() };
+
+    action.sa_sigaction = handler();
+    action.sa_flags = SA_RESTART;
+
+    // SAFETY: external call, sa_mask is valid for writes
+    unsafe {
+        sigemptyset(&raw mut action.sa_mask);
+    }
+
+    // SAFETY: external call, action is valid for reads, the previous action isn't asked for
+    if unsafe { libc::sigaction(SIGRTMIN(), &raw const action, ptr::null_mut()) } == -1 {
+        return Err(Error::last_os_error()).wrap_err("Failed to set up the signal handler");
+    }
+
+    // kept open, for the signal handler
+    RULESET.store(ruleset.into_raw_fd(), Ordering::SeqCst);
+
+    // SAFETY: external call, it can't fail
+    let pid = unsafe { getpid() };
+
+    let mut restricted = BTreeSet::from([current_thread()]);
+    let mut signaled = 0;
+
+    loop {
+        let others = task_dir
+            .threads()
+            .wrap_err("Failed to list the threads")?
+            .into_iter()
+            .filter(|thread| !restricted.contains(thread))
+            .collect::<Vec<_>>();
+
+        // every thread is restricted, and so are those they start
+        if others.is_empty() {
+            break;
+        }
+
+        for thread in others {
+            restricted.insert(thread);
+
+            // SAFETY: external call, the handler is set up
+            if unsafe { tgkill(pid, thread, SIGRTMIN()) } == 0 {
+                signaled += 1;
+            } else {
+                let error = Error::last_os_error();
+
+                // it ended in the meantime
+                if error.raw_os_error() != Some(ESRCH) {
+                    return Err(error)
+                        .wrap_err_with(|| format!("Failed to signal thread {}", thread));
+                }
+            }
+        }
+
+        let started = Instant::now();
+
+        while SIGNALED_DONE.load(Ordering::SeqCst) < signaled {
+            if started.elapsed() > SIGNALED_TIMEOUT {
+                return Err(eyre::eyre!(
+                    "Threads didn't restrict their filesystem access in time"
+                ));
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // last, once restricted `/proc` is out of reach, and this thread starts none in the meantime
+    restrict_self(RULESET.load(Ordering::SeqCst)).wrap_err("Failed to restrict the filesystem")?;
+
+    match SIGNALED_FAILED.load(Ordering::SeqCst) {
+        0 => Ok(()),
+        failed => Err(eyre::eyre!(
+            "{} threads failed to restrict their filesystem access",
+            failed
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::os::fd::AsRawFd as _;
+    use std::sync::mpsc::channel;
+    use std::{env, fs, process, slice, thread};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::sandbox::current_thread;
+    use crate::sandbox::landlock::{TaskDir, create_ruleset, restrict_self};
+
+    #[test]
+    fn restricts_reads_to_the_readable_paths() {
+        let readable = env::temp_dir().join(format!("landlock-test-{}", process::id()));
+
+        fs::create_dir_all(&readable).unwrap();
+        fs::write(readable.join("allowed"), "allowed").unwrap();
+
+        // only the thread restricting itself is, not the test harness
+        let result = thread::spawn({
+            let readable = readable.clone();
+
+            move || {
+                let ruleset = create_ruleset(slice::from_ref(&readable)).unwrap();
+
+                restrict_self(ruleset.as_raw_fd()).unwrap();
+
+                (
+                    fs::read_to_string(readable.join("allowed")).unwrap(),
+                    fs::read_to_string("/etc/passwd").unwrap_err().kind(),
+                )
+            }
+        })
+        .join()
+        .unwrap();
+
+        fs::remove_dir_all(&readable).unwrap();
+
+        assert_eq!(result, ("allowed".to_owned(), ErrorKind::PermissionDenied));
+    }
+
+    #[test]
+    fn lists_threads_of_the_process() {
+        let task_dir = TaskDir::open().unwrap();
+
+        let (thread_tx, thread_rx) = channel();
+        let (done_tx, done_rx) = channel::<()>();
+
+        let other = thread::spawn(move || {
+            thread_tx.send(current_thread()).unwrap();
+
+            done_rx.recv().unwrap_err();
+        });
+
+        let other_thread = thread_rx.recv().unwrap();
+        let threads = task_dir.threads().unwrap();
+
+        drop(done_tx);
+        other.join().unwrap();
+
+        assert!(threads.contains(&current_thread()));
+        assert!(threads.contains(&other_thread));
+    }
+}
@@ -0,0 +1,257 @@
+use std::io::Error;
+
+use color_eyre::eyre::{self, WrapErr as _};
+use libc::{
+    BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W, PR_SET_NO_NEW_PRIVS,
+    SECCOMP_FILTER_FLAG_LOG, SECCOMP_FILTER_FLAG_TSYNC, SECCOMP_RET_ALLOW, SECCOMP_SET_MODE_FILTER,
+    c_long, c_ulong, prctl, sock_filter, sock_fprog, syscall,
+};
+
+/// `AUDIT_ARCH_X86_64`, syscall numbers differ from one architecture to the next.
+#[cfg(target_arch = "x86_64")]
+const ARCH: Option<u32> = Some(0xC000_003E);
+/// `AUDIT_ARCH_AARCH64`.
+#[cfg(target_arch = "aarch64")]
+const ARCH: Option<u32> = Some(0xC000_00B7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ARCH: Option<u32> = None;
+
+/// Offset of `nr` in `struct seccomp_data`.
+const NR_OFFSET: u32 = 0;
+/// Offset of `arch` in `struct seccomp_data`.
+const ARCH_OFFSET: u32 = 4;
+
+/// What the runtime, its threads, sqlx, the epoll loop in `client.rs`, DNS lookups and a reload need, nothing that
+/// starts a process or changes users.
+const ALLOWED: &[c_long] = &[
+    // memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_membarrier,
+    // threads and signals
+    libc::SYS_futex,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_set_robust_list,
+    libc::SYS_set_tid_address,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_gettid,
+    libc::SYS_getpid,
+    libc::SYS_tgkill,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_restart_syscall,
+    // no new privileges, and the Landlock ruleset, for new threads
+    libc::SYS_prctl,
+    libc::SYS_landlock_restrict_self,
+    // time
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    // files
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_close,
+    libc::SYS_lseek,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_openat,
+    libc::SYS_getdents64,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_readlinkat,
+    libc::SYS_getcwd,
+    // polling
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_eventfd2,
+    libc::SYS_ppoll,
+    libc::SYS_pipe2,
+    // sockets
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_accept4,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockopt,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvmmsg,
+    libc::SYS_shutdown,
+    // the rest
+    libc::SYS_getrandom,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_uname,
+    libc::SYS_prlimit64,
+    // the older ones glibc still uses on x86_64
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_pipe,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_accept,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_arch_prctl,
+];
+
+fn statement(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: u16::try_from(code).expect("BPF codes fit in 16 bits"),
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// A program that returns `default_action` for syscalls of another architecture or outside `allowed`, and allows the
+/// others.
+fn filter(arch: u32, allowed: &[c_long], default_action: u32) -> Vec<sock_filter> {
+    let mut filter = vec![
+        statement(BPF_LD | BPF_W | BPF_ABS, ARCH_OFFSET),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 1, 0),
+        statement(BPF_RET | BPF_K, default_action),
+        statement(BPF_LD | BPF_W | BPF_ABS, NR_OFFSET),
+    ];
+
+    for (index, &nr) in allowed.iter().enumerate() {
+        // past the remaining comparisons and the default return, to the allowing one
+        let to_allow =
+            u8::try_from(allowed.len() - index).expect("Fewer than 256 allowed syscalls");
+
+        filter.push(jump(
+            BPF_JMP | BPF_JEQ | BPF_K,
+            u32::try_from(nr).expect("Syscall numbers are positive"),
+            to_allow,
+            0,
+        ));
+    }
+
+    filter.push(statement(BPF_RET | BPF_K, default_action));
+    filter.push(statement(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+
+    filter
+}
+
+/// Installs the filter on every thread of the process, with `default_action` for syscalls outside [`ALLOWED`].
+pub fn install(default_action: u32) -> Result<(), eyre::Report> {
+    let arch = ARCH.ok_or_else(|| eyre::eyre!("Seccomp isn't supported on this architecture"))?;
+
+    let mut filter = filter(arch, ALLOWED, default_action);
+    let program = sock_fprog {
+        len: u16::try_from(filter.len())?,
+        filter: filter.as_mut_ptr(),
+    };
+
+    let (enable, unused): (c_ulong, c_ulong) = (1, 0);
+
+    // SAFETY: external call, with the 3 unused arguments set to 0, as required
+    if unsafe { prctl(PR_SET_NO_NEW_PRIVS, enable, unused, unused, unused) } == -1 {
+        return Err(Error::last_os_error()).wrap_err("Failed to set no new privileges");
+    }
+
+    // SAFETY: external call, program points to its `len` instructions, which outlive the call
+    let r = unsafe {
+        syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            SECCOMP_FILTER_FLAG_TSYNC | SECCOMP_FILTER_FLAG_LOG,
+            &raw const program,
+        )
+    };
+
+    match r {
+        0 => Ok(()),
+        -1 => Err(Error::last_os_error()).wrap_err("Failed to install the seccomp filter"),
+        // with TSYNC, the thread that couldn't take it
+        thread => Err(eyre::eyre!(
+            "Thread {} couldn't take the seccomp filter",
+            thread
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::{BPF_RET, SECCOMP_RET_ALLOW, SECCOMP_RET_KILL_PROCESS};
+    use pretty_assertions::assert_eq;
+
+    use crate::sandbox::seccomp::{ALLOWED, filter};
+
+    #[test]
+    fn allowed_syscalls_jump_to_allow() {
+        let filter = filter(0xC000_003E, &[0, 1, 60], SECCOMP_RET_KILL_PROCESS);
+
+        let returns = filter
+            .iter()
+            .enumerate()
+            // the class is in the lowest 3 bits
+            .filter(|&(_, statement)| u32::from(statement.code) & 0x07 == BPF_RET)
+            .map(|(index, statement)| (index, statement.k))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            returns,
+            [
+                (2, SECCOMP_RET_KILL_PROCESS),
+                (7, SECCOMP_RET_KILL_PROCESS),
+                (8, SECCOMP_RET_ALLOW)
+            ]
+        );
+
+        // each comparison lands on the last statement
+        for (index, statement) in filter.iter().enumerate().skip(4).take(3) {
+            assert_eq!(index + 1 + usize::from(statement.jt), filter.len() - 1);
+        }
+
+        assert!(ALLOWED.len() < 256);
+    }
+}
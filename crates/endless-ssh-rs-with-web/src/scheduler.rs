@@ -0,0 +1,864 @@
+use std::io::Error;
+use std::net::TcpStream;
+use std::num::{NonZeroU16, NonZeroU64};
+use std::os::fd::AsFd as _;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use time::SignedDuration;
+use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::time::{Instant, sleep_until};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{Level, event};
+
+use crate::admission::Evict;
+use crate::bandwidth::{Bandwidth, BandwidthStats};
+use crate::client::{ClientSlot, DisconnectReason, Trapped, release};
+use crate::delay::Delays;
+use crate::events::ClientEvent;
+use crate::line::generator::LineGenerator;
+use crate::protocol::Drip;
+use crate::protocol::ssh::ClientKex;
+use crate::sender;
+use crate::task_tracker_ext::TaskTrackerExt as _;
+use crate::tcp_metrics::TcpMetrics;
+use crate::watcher::DisconnectWatcher;
+use crate::wheel::TimerWheel;
+
+/// How often the wheels turn, a line goes out at most this late.
+pub const TICK: Duration = Duration::from_millis(10);
+
+/// A client past its handshake, everything we keep about it until it's gone.
+pub struct TrappedClient {
+    stream: TcpStream,
+    slot: ClientSlot,
+    drip: Drip,
+    delays: Delays,
+    /// The delay the client is waiting out.
+    delay: Duration,
+    waiting_since: Instant,
+    /// Registered with the [`DisconnectWatcher`], which reports it leaving during a wait.
+    watched: bool,
+    time_spent: SignedDuration,
+    bytes_sent: usize,
+    client_ident: Option<String>,
+    client_kex: Option<Box<ClientKex>>,
+    /// What this client was accounted for in [`EngineStats::memory`].
+    accounted: usize,
+}
+
+impl TrappedClient {
+    /// `handshake_time` and `sent` are what the handshake already cost the client.
+    pub fn new(
+        stream: TcpStream,
+        slot: ClientSlot,
+        lines: Arc<LineGenerator>,
+        handshake_time: SignedDuration,
+        sent: usize,
+        client_ident: Option<String>,
+        client_kex: Option<ClientKex>,
+    ) -> Self {
+        let mut delays = Delays::new(slot.config.delay_strategy, slot.config.delay);
+        let drip = Drip::new(slot.config.protocol, lines);
+
+        Self {
+            stream,
+            drip,
+            delay: delays.next(),
+            delays,
+            waiting_since: Instant::now(),
+            watched: false,
+            slot,
+            time_spent: handshake_time,
+            bytes_sent: sent,
+            client_ident,
+            client_kex: client_kex.map(Box::new),
+            accounted: 0,
+        }
+    }
+
+    /// Everything the client costs us, its slot in the scheduler and its timer included.
+    fn memory(&self) -> usize {
+        let kex = self.client_kex.as_deref().map_or(0, |client_kex| {
+            size_of::<ClientKex>()
+                + [
+                    &client_kex.hassh,
+                    &client_kex.kex_algorithms,
+                    &client_kex.host_key_algorithms,
+                    &client_kex.encryption_algorithms,
+                    &client_kex.mac_algorithms,
+                    &client_kex.compression_algorithms,
+                ]
+                .into_iter()
+                .map(String::capacity)
+                .sum::<usize>()
+        });
+
+        CLIENT_SIZE
+            + self.client_ident.as_ref().map_or(0, String::capacity)
+            + kex
+            + self.drip.heap_size()
+    }
+
+    /// Picks up the listener's reloaded configuration, the next line and delay follow it.
+    fn reconfigure(&mut self) {
+        if !self.slot.updates.has_changed().unwrap_or(false) {
+            return;
+        }
+
+        let config = Arc::clone(&self.slot.updates.borrow_and_update().config);
+
+        self.delays.reconfigure(config.delay_strategy, config.delay);
+        self.slot.config = config;
+    }
+
+    /// Why the client the watcher reported is gone, the error the kernel left on its socket tells.
+    fn disconnect_reason(&self) -> DisconnectReason {
+        match self.stream.take_error() {
+            Ok(Some(error)) => DisconnectReason::from(error.kind()),
+            Ok(None) | Err(_) => DisconnectReason::ClientGone,
+        }
+    }
+
+    /// How long the client may still stay, `None` without a max duration.
+    fn time_left(&self) -> Option<Duration> {
+        self.slot.config.max_duration.map(|max_duration| {
+            max_duration.saturating_sub(Duration::try_from(self.time_spent).unwrap_or_default())
+        })
+    }
+
+    /// Whether the client had all its listener lets it have, after its latest line or deferral.
+    fn limit_reached(&self) -> Option<DisconnectReason> {
+        let config = &self.slot.config;
+
+        if config
+            .max_duration
+            .is_some_and(|max_duration| self.time_spent >= max_duration)
+        {
+            Some(DisconnectReason::MaxDurationReached)
+        } else if config.max_bytes.is_some_and(|max_bytes| {
+            u64::try_from(self.bytes_sent).unwrap_or(u64::MAX) >= max_bytes.get()
+        }) {
+            Some(DisconnectReason::MaxBytesReached)
+        } else {
+            None
+        }
+    }
+
+    fn into_trapped(self, disconnect_reason: DisconnectReason) -> (ClientSlot, Trapped) {
+        (
+            self.slot,
+            Trapped {
+                time_spent: self.time_spent,
+                bytes_sent: self.bytes_sent,
+                client_ident: self.client_ident,
+                client_kex: self.client_kex.map(|client_kex| *client_kex),
+                auth_attempts: Vec::new(),
+                disconnect_reason,
+                tcp_metrics: TcpMetrics::read(self.stream.as_fd()),
+            },
+        )
+    }
+}
+
+/// A client's entry in the slab, and its timer in the wheel.
+const CLIENT_SIZE: usize = size_of::<Entry>() + size_of::<(u64, u64)>();
+
+// a tokio task, its timer and its stream's registration cost several times this
+const _: () = assert!(CLIENT_SIZE <= 512, "a trapped client grew past 512 bytes");
+
+/// What the schedulers are up to, returned by the `/api/engine` endpoint.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct EngineStats {
+    pub schedulers: usize,
+    /// Clients being dripped to, those still in their handshake and honeypot clients are not included.
+    pub trapped_clients: usize,
+    /// What the trapped clients cost the schedulers, in bytes.
+    pub memory: usize,
+    /// `memory`, per trapped client.
+    pub memory_per_client: usize,
+    pub bandwidth: BandwidthStats,
+}
+
+enum Message {
+    Trap(Box<TrappedClient>),
+    /// The [`DisconnectWatcher`] saw the client with this token leave.
+    Gone(u64),
+    /// The client's listener needs its slot, see [`crate::admission::Sessions::evict`].
+    Evict(u64),
+}
+
+#[derive(Default)]
+struct Counters {
+    trapped_clients: AtomicUsize,
+    memory: AtomicUsize,
+}
+
+/// Drips to every trapped client from a few scheduler tasks, each with its own timer wheel,
+/// instead of a task and a timer per client.
+pub struct Engine {
+    schedulers: Vec<UnboundedSender<Message>>,
+    next: AtomicUsize,
+    counters: Arc<Counters>,
+    bandwidth: Arc<Bandwidth>,
+    internal_events_tx: Sender<ClientEvent>,
+}
+
+impl Engine {
+    pub fn start(
+        schedulers: NonZeroU16,
+        max_bandwidth: Option<NonZeroU64>,
+        cancellation_token: &CancellationToken,
+        task_tracker: &TaskTracker,
+        internal_events_tx: &Sender<ClientEvent>,
+    ) -> Result<Self, Error> {
+        let counters = Arc::new(Counters::default());
+        let bandwidth = Arc::new(Bandwidth::new(max_bandwidth));
+        let watcher = Arc::new(DisconnectWatcher::new()?);
+
+        let schedulers = (0..schedulers.get())
+            .map(|index| {
+                let (messages_tx, messages_rx) = unbounded_channel();
+
+                task_tracker.spawn_with_name(
+                    &format!("scheduler {}", index),
+                    schedule_forever(
+                        Slab::new(index),
+                        messages_rx,
+                        Arc::clone(&watcher),
+                        Arc::clone(&bandwidth),
+                        cancellation_token.clone(),
+                        internal_events_tx.clone(),
+                        Arc::clone(&counters),
+                    ),
+                );
+
+                messages_tx
+            })
+            .collect::<Vec<_>>();
+
+        task_tracker.spawn_with_name(
+            "disconnect watcher",
+            watch_forever(watcher, schedulers.clone(), cancellation_token.clone()),
+        );
+
+        event!(
+            Level::INFO,
+            schedulers = schedulers.len(),
+            bytes_per_client = CLIENT_SIZE,
+            "Schedulers started"
+        );
+
+        Ok(Self {
+            schedulers,
+            next: AtomicUsize::new(0),
+            counters,
+            bandwidth,
+            internal_events_tx: internal_events_tx.clone(),
+        })
+    }
+
+    /// Hands the client to the next scheduler, round-robin.
+    pub fn trap(&self, mut client: TrappedClient) {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.schedulers.len();
+
+        client.accounted = client.memory();
+
+        self.counters
+            .trapped_clients
+            .fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .memory
+            .fetch_add(client.accounted, Ordering::Relaxed);
+
+        if let Err(error) = self.schedulers[index].send(Message::Trap(Box::new(client))) {
+            // the schedulers are gone, we're shutting down
+            if let Message::Trap(client) = error.0 {
+                release_client(
+                    *client,
+                    DisconnectReason::Shutdown,
+                    &self.internal_events_tx,
+                    &self.counters,
+                );
+            }
+        }
+    }
+
+    /// Ends the session of the client with `token`, if it's still there.
+    pub fn evict(&self, token: u64) {
+        if let Some(scheduler) = self.schedulers.get(scheduler_of(token)) {
+            // gone means we're shutting down
+            let _r = scheduler.send(Message::Evict(token));
+        }
+    }
+
+    /// The limiter the schedulers draw from, what's sent around them is recorded there too.
+    pub fn bandwidth(&self) -> &Arc<Bandwidth> {
+        &self.bandwidth
+    }
+
+    pub fn stats(&self) -> EngineStats {
+        let trapped_clients = self.counters.trapped_clients.load(Ordering::Relaxed);
+        let memory = self.counters.memory.load(Ordering::Relaxed);
+
+        EngineStats {
+            schedulers: self.schedulers.len(),
+            trapped_clients,
+            memory,
+            memory_per_client: memory.checked_div(trapped_clients).unwrap_or(CLIENT_SIZE),
+            bandwidth: self.bandwidth.stats(),
+        }
+    }
+}
+
+fn release_client(
+    client: TrappedClient,
+    disconnect_reason: DisconnectReason,
+    internal_events_tx: &Sender<ClientEvent>,
+    counters: &Counters,
+) {
+    counters.trapped_clients.fetch_sub(1, Ordering::Relaxed);
+    counters
+        .memory
+        .fetch_sub(client.accounted, Ordering::Relaxed);
+
+    let delay_strategy = client.slot.config.delay_strategy;
+    let (slot, trapped) = client.into_trapped(disconnect_reason);
+
+    release(slot, trapped, delay_strategy, internal_events_tx);
+}
+
+/// A slab entry, its generation tells a token for the current client from one for an earlier client.
+#[derive(Default)]
+struct Entry {
+    generation: u16,
+    client: Option<TrappedClient>,
+}
+
+/// A scheduler's clients by token. Tokens are the scheduler, the generation of the entry and its index,
+/// 16, 16 and 32 bits, so they're unique across schedulers, and stale ones (a wheel timer or a disconnect
+/// of a client that's gone) don't match the entry's next client.
+struct Slab {
+    scheduler: u16,
+    entries: Vec<Entry>,
+    free: Vec<u32>,
+}
+
+/// The scheduler `token` belongs to.
+fn scheduler_of(token: u64) -> usize {
+    usize::from(u16::try_from(token >> 48).expect("shifted to 16 bits"))
+}
+
+impl Slab {
+    fn new(scheduler: u16) -> Self {
+        Self {
+            scheduler,
+            entries: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn token(&self, index: u32, generation: u16) -> u64 {
+        (u64::from(self.scheduler) << 48) | (u64::from(generation) << 32) | u64::from(index)
+    }
+
+    /// The entry `token` points to, if it's the token's generation.
+    fn entry(&mut self, token: u64) -> Option<&mut Entry> {
+        let index = usize::try_from(token & u64::from(u32::MAX)).ok()?;
+        let generation = u16::try_from((token >> 32) & u64::from(u16::MAX)).ok()?;
+
+        self.entries
+            .get_mut(index)
+            .filter(|entry| entry.generation == generation)
+    }
+
+    fn insert(&mut self, client: TrappedClient) -> u64 {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.entries.push(Entry::default());
+
+            u32::try_from(self.entries.len() - 1).expect("less than 2^32 clients per scheduler")
+        });
+
+        let entry = &mut self.entries[usize::try_from(index).expect("u32 fits in a usize")];
+
+        entry.client = Some(client);
+
+        let generation = entry.generation;
+
+        self.token(index, generation)
+    }
+
+    fn get_mut(&mut self, token: u64) -> Option<&mut TrappedClient> {
+        self.entry(token)?.client.as_mut()
+    }
+
+    fn remove(&mut self, token: u64) -> Option<TrappedClient> {
+        let entry = self.entry(token)?;
+        let client = entry.client.take()?;
+
+        entry.generation = entry.generation.wrapping_add(1);
+
+        self.free
+            .push(u32::try_from(token & u64::from(u32::MAX)).expect("masked to 32 bits"));
+
+        Some(client)
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = TrappedClient> {
+        self.free.clear();
+
+        self.entries.drain(..).filter_map(|entry| entry.client)
+    }
+}
+
+/// Forwards the disconnects the watcher reports to the schedulers holding the clients.
+async fn watch_forever(
+    watcher: Arc<DisconnectWatcher>,
+    schedulers: Vec<UnboundedSender<Message>>,
+    cancellation_token: CancellationToken,
+) {
+    let mut tokens = Vec::new();
+
+    loop {
+        let result = tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => {
+                break;
+            },
+            result = watcher.wait(&mut tokens) => {
+                result
+            },
+        };
+
+        if let Err(error) = result {
+            event!(
+                Level::ERROR,
+                ?error,
+                "Disconnect watcher failed, disconnects are only noticed when sending"
+            );
+
+            break;
+        }
+
+        for token in tokens.drain(..) {
+            if let Some(scheduler) = schedulers.get(scheduler_of(token)) {
+                // gone means we're shutting down
+                let _r = scheduler.send(Message::Gone(token));
+            }
+        }
+    }
+}
+
+pub async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn schedule_forever(
+    mut clients: Slab,
+    mut messages_rx: UnboundedReceiver<Message>,
+    watcher: Arc<DisconnectWatcher>,
+    bandwidth: Arc<Bandwidth>,
+    cancellation_token: CancellationToken,
+    internal_events_tx: Sender<ClientEvent>,
+    counters: Arc<Counters>,
+) {
+    let mut wheel = TimerWheel::new(Instant::now(), TICK);
+    let mut due = Vec::new();
+
+    loop {
+        tokio::select! {
+            biased;
+            () = cancellation_token.cancelled() => {
+                break;
+            },
+            message = messages_rx.recv() => {
+                match message {
+                    Some(Message::Trap(client)) => {
+                        let deadline = client.waiting_since + client.delay;
+                        let token = clients.insert(*client);
+
+                        wheel.insert(deadline, token);
+
+                        if let Some(client) = clients.get_mut(token) {
+                            watch(&watcher, client, token);
+
+                            client.slot.session.evictable(Evict::Scheduled(token));
+                        }
+                    },
+                    Some(Message::Gone(token) | Message::Evict(token)) => {
+                        if let Some(mut client) = clients.remove(token) {
+                            // it was there for part of its wait
+                            client.time_spent += client.waiting_since.elapsed();
+
+                            event!(Level::TRACE, addr = %client.slot.addr, time_spent = %client.time_spent, bytes_sent = client.bytes_sent, "Client gone");
+
+                            // evicted clients are told apart by their session
+                            let disconnect_reason = client.disconnect_reason();
+
+                            release_client(client, disconnect_reason, &internal_events_tx, &counters);
+                        }
+                    },
+                    None => break,
+                }
+            },
+            () = sleep_until_deadline(wheel.next_deadline()) => {},
+        }
+
+        wheel.advance(Instant::now(), &mut due);
+
+        event!(
+            Level::TRACE,
+            due = due.len(),
+            pending = wheel.len(),
+            "Wheel advanced"
+        );
+
+        for token in due.drain(..) {
+            let Some(client) = clients.get_mut(token) else {
+                continue;
+            };
+
+            match drip(client, &bandwidth, &internal_events_tx) {
+                Ok(dripped) => {
+                    if let Some(disconnect_reason) = client.limit_reached() {
+                        event!(Level::TRACE, addr = %client.slot.addr, time_spent = %client.time_spent, bytes_sent = client.bytes_sent, %disconnect_reason, "Letting client go");
+
+                        if let Some(client) = clients.remove(token) {
+                            release_client(
+                                client,
+                                disconnect_reason,
+                                &internal_events_tx,
+                                &counters,
+                            );
+                        }
+
+                        continue;
+                    }
+
+                    client.delay = match dripped {
+                        Dripped::Sent => client.delays.next(),
+                        // a spent budget doesn't keep the client past its max duration
+                        Dripped::Deferred(wait) => client
+                            .time_left()
+                            .map_or(wait, |time_left| wait.min(time_left)),
+                    };
+                    client.waiting_since = Instant::now();
+
+                    wheel.insert(client.waiting_since + client.delay, token);
+                },
+                Err(disconnect_reason) => {
+                    if let Some(client) = clients.remove(token) {
+                        release_client(client, disconnect_reason, &internal_events_tx, &counters);
+                    }
+                },
+            }
+        }
+    }
+
+    // whoever is still trapped is stored as it is
+    for client in clients.drain() {
+        release_client(
+            client,
+            DisconnectReason::Shutdown,
+            &internal_events_tx,
+            &counters,
+        );
+    }
+}
+
+/// Registers the client with the watcher. Failures are non-fatal and disable disconnect detection
+/// for this client (i.e. falls back to send-based detection).
+fn watch(watcher: &DisconnectWatcher, client: &mut TrappedClient, token: u64) {
+    match watcher.register(client.stream.as_fd(), token) {
+        Ok(()) => client.watched = true,
+        Err(error) => {
+            event!(Level::WARN, addr = %client.slot.addr, ?error, "epoll registration failed, disconnect detection disabled");
+        },
+    }
+}
+
+/// What became of a client's line, either way its wait is over and counted.
+enum Dripped {
+    Sent,
+    /// The bandwidth budget is spent, the line waits this much longer.
+    Deferred(Duration),
+}
+
+/// Sends the client its next line, unless the bandwidth budget is spent. `Err` when it's gone.
+fn drip(
+    client: &mut TrappedClient,
+    bandwidth: &Bandwidth,
+    internal_events_tx: &Sender<ClientEvent>,
+) -> Result<Dripped, DisconnectReason> {
+    let addr = client.slot.addr;
+
+    event!(Level::DEBUG, %addr, "Processing client");
+
+    client.reconfigure();
+
+    let max_length = client.slot.config.max_line_length.get().into();
+
+    // the line is at most that long, what it doesn't use goes back
+    let reservation = match bandwidth.reserve(max_length) {
+        Ok(reservation) => reservation,
+        Err(wait) => {
+            event!(Level::TRACE, %addr, ?wait, "Bandwidth budget spent, deferring line");
+
+            // the client kept waiting, and that counts towards its time
+            client.time_spent += client.delay;
+
+            return Ok(Dripped::Deferred(wait));
+        },
+    };
+
+    let sent = sender::sendline(&mut client.stream, &mut client.drip, max_length);
+
+    reservation.settle(sent.unwrap_or_default());
+
+    match sent {
+        Ok(sent) => {
+            client.time_spent += client.delay;
+            client.bytes_sent += sent;
+
+            // try_send: a full channel drops this update, but the next one has the updated running total
+            if client.slot.recorded {
+                let _r = internal_events_tx.try_send(ClientEvent::BytesSent {
+                    addr,
+                    bytes_sent: client.bytes_sent,
+                });
+            }
+
+            Ok(Dripped::Sent)
+        },
+        Err(error) => {
+            // Send failed, ergo client is gone. If it was watched, the watcher would have
+            // reported it had the client disconnected during the wait, so we
+            // can credit the full delay. Otherwise we have no proof the
+            // client was alive through the wait, so we don't count it.
+            if client.watched {
+                client.time_spent += client.delay;
+            }
+
+            event!(Level::TRACE, %addr, time_spent = %client.time_spent, bytes_sent = client.bytes_sent, "Client gone");
+
+            Err(DisconnectReason::from(error))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::num::{NonZeroU16, NonZeroU64};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use time::{OffsetDateTime, SignedDuration};
+    use tokio::sync::Semaphore;
+    use tokio::sync::mpsc::channel;
+    use tokio::sync::watch;
+    use tokio::time::timeout;
+    use tokio_util::sync::CancellationToken;
+    use tokio_util::task::TaskTracker;
+
+    use crate::admission::Sessions;
+    use crate::client::{ClientSlot, DisconnectReason};
+    use crate::config::ListenerConfig;
+    use crate::events::ClientEvent;
+    use crate::listener::ListenerSettings;
+    use crate::scheduler::{Engine, TrappedClient};
+    use crate::source_limits::{SourceCounts, SourceLimits};
+
+    /// A connected client on a listener with `config`, its end, and the listener's slots.
+    fn trapped_client(config: ListenerConfig) -> (TcpStream, TrappedClient, Arc<Semaphore>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, addr) = listener.accept().unwrap();
+
+        stream.set_nonblocking(true).unwrap();
+
+        let semaphore = Arc::new(Semaphore::new(1));
+        let sources = Arc::new(SourceCounts::new(SourceLimits::default()));
+        let sessions = Arc::new(Sessions::default());
+        let config = Arc::new(config);
+
+        let (_settings, updates) = watch::channel(ListenerSettings {
+            config: Arc::clone(&config),
+            honeypot: None,
+        });
+
+        let slot = ClientSlot {
+            addr,
+            connected_at: OffsetDateTime::now_utc(),
+            config,
+            updates,
+            permit: Arc::clone(&semaphore).try_acquire_owned().unwrap(),
+            source: sources.admit(addr.ip()).unwrap(),
+            session: sessions.open(addr.ip()).0,
+            recorded: true,
+        };
+
+        let trapped = TrappedClient::new(
+            stream,
+            slot,
+            Arc::default(),
+            SignedDuration::ZERO,
+            0,
+            None,
+            None,
+        );
+
+        (client, trapped, semaphore)
+    }
+
+    #[tokio::test]
+    async fn drips_until_client_leaves() {
+        let (client, trapped, semaphore) = trapped_client(ListenerConfig {
+            delay: Duration::from_millis(10),
+            ..ListenerConfig::default()
+        });
+
+        let (internal_events_tx, mut internal_events_rx) = channel(100);
+
+        let engine = Engine::start(
+            NonZeroU16::MIN,
+            None,
+            &CancellationToken::new(),
+            &TaskTracker::new(),
+            &internal_events_tx,
+        )
+        .unwrap();
+
+        engine.trap(trapped);
+
+        assert!(matches!(
+            internal_events_rx.recv().await,
+            Some(ClientEvent::BytesSent { .. })
+        ));
+        assert_eq!(engine.stats().trapped_clients, 1);
+        assert_eq!(semaphore.available_permits(), 0);
+
+        drop(client);
+
+        // the next lines hit a closed socket
+        loop {
+            let event = timeout(Duration::from_secs(5), internal_events_rx.recv())
+                .await
+                .unwrap();
+
+            if let Some(ClientEvent::Disconnected { bytes_sent, .. }) = event {
+                assert!(bytes_sent > 0);
+
+                break;
+            }
+        }
+
+        assert_eq!(engine.stats().trapped_clients, 0);
+        assert_eq!(engine.stats().memory, 0);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn lets_client_go_at_max_duration() {
+        let (_client, trapped, semaphore) = trapped_client(ListenerConfig {
+            delay: Duration::from_millis(10),
+            max_duration: Some(Duration::from_millis(30)),
+            ..ListenerConfig::default()
+        });
+
+        let (internal_events_tx, mut internal_events_rx) = channel(100);
+
+        let engine = Engine::start(
+            NonZeroU16::MIN,
+            None,
+            &CancellationToken::new(),
+            &TaskTracker::new(),
+            &internal_events_tx,
+        )
+        .unwrap();
+
+        engine.trap(trapped);
+
+        // the client is still there, it had its share
+        loop {
+            let event = timeout(Duration::from_secs(5), internal_events_rx.recv())
+                .await
+                .unwrap();
+
+            if let Some(ClientEvent::Disconnected {
+                disconnect_reason,
+                time_spent,
+                tcp_metrics,
+                ..
+            }) = event
+            {
+                assert_eq!(disconnect_reason, DisconnectReason::MaxDurationReached);
+                assert_eq!(time_spent, SignedDuration::milliseconds(30));
+                // read before we hung up
+                assert!(tcp_metrics.is_some_and(|tcp_metrics| tcp_metrics.mss > 0));
+
+                break;
+            }
+        }
+
+        assert_eq!(engine.stats().trapped_clients, 0);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn lets_deferred_client_go_at_max_duration() {
+        let (_client, trapped, semaphore) = trapped_client(ListenerConfig {
+            delay: Duration::from_millis(10),
+            max_duration: Some(Duration::from_millis(30)),
+            ..ListenerConfig::default()
+        });
+
+        let (internal_events_tx, mut internal_events_rx) = channel(100);
+
+        let engine = Engine::start(
+            NonZeroU16::MIN,
+            NonZeroU64::new(1),
+            &CancellationToken::new(),
+            &TaskTracker::new(),
+            &internal_events_tx,
+        )
+        .unwrap();
+
+        // the whole burst is gone, the next line has to wait for half a minute
+        engine.bandwidth.reserve(256).unwrap().settle(256);
+
+        engine.trap(trapped);
+
+        // it never got a line, but it stayed its time
+        loop {
+            let event = timeout(Duration::from_secs(5), internal_events_rx.recv())
+                .await
+                .unwrap();
+
+            if let Some(ClientEvent::Disconnected {
+                disconnect_reason,
+                time_spent,
+                bytes_sent,
+                ..
+            }) = event
+            {
+                assert_eq!(disconnect_reason, DisconnectReason::MaxDurationReached);
+                assert_eq!(time_spent, SignedDuration::milliseconds(30));
+                assert_eq!(bytes_sent, 0);
+
+                break;
+            }
+        }
+
+        assert_eq!(engine.stats().trapped_clients, 0);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+}
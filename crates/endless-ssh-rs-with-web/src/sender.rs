@@ -1,25 +1,24 @@
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write};
 
 use tracing::{Level, event};
 
-use crate::line::randline;
+use crate::protocol::Drip;
 
-pub async fn sendline<T>(target: &mut T, max_length: usize) -> Result<usize, ()>
+/// Sends the next line without blocking. When the client's window is full, whatever doesn't fit goes back to `drip`,
+/// and goes out first next time.
+pub fn sendline<T>(target: &mut T, drip: &mut Drip, max_length: usize) -> Result<usize, ErrorKind>
 where
-    T: tokio::io::AsyncWriteExt + std::marker::Unpin + std::fmt::Debug,
+    T: Write + std::fmt::Debug,
 {
-    let bytes = randline(max_length);
+    let bytes = drip.next(max_length);
 
-    match target.write_all(bytes.as_slice()).await {
-        Ok(()) => {
-            event!(
-                Level::TRACE,
-                ?target,
-                bytes_sent = ?bytes.len(),
-                "Data sent",
-            );
+    match target.write(bytes.as_slice()) {
+        Ok(sent) => {
+            event!(Level::TRACE, ?target, bytes_sent = ?sent, "Data sent");
 
-            Ok(bytes.len())
+            drip.unsent(&bytes[sent..]);
+
+            Ok(sent)
         },
         Err(error) if error.kind() == ErrorKind::WouldBlock => {
             // EAGAIN, EWOULDBLOCK
@@ -30,6 +29,8 @@ where
                 "Couldn't send anything to client, will try later",
             );
 
+            drip.unsent(&bytes);
+
             Ok(0)
         },
         Err(error) => {
@@ -52,17 +53,19 @@ where
                 );
             }
 
-            Err(())
+            Err(error.kind())
         },
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::ErrorKind;
+    use std::io::{ErrorKind, Write};
+    use std::sync::Arc;
 
     use pretty_assertions::assert_eq;
 
+    use crate::protocol::{Drip, Protocol};
     use crate::sender::sendline;
 
     #[derive(Debug)]
@@ -70,107 +73,176 @@ mod tests {
         error: ErrorKind,
     }
 
-    impl tokio::io::AsyncWrite for ErrorWrite {
-        fn poll_write(
-            self: std::pin::Pin<&mut Self>,
-            _cx: &mut std::task::Context<'_>,
-            _buf: &[u8],
-        ) -> std::task::Poll<Result<usize, std::io::Error>> {
-            std::task::Poll::Ready(Err(std::io::Error::from(self.error)))
-        }
-
-        fn poll_flush(
-            self: std::pin::Pin<&mut Self>,
-            _cx: &mut std::task::Context<'_>,
-        ) -> std::task::Poll<Result<(), std::io::Error>> {
-            unreachable!()
+    impl Write for ErrorWrite {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::from(self.error))
         }
 
-        fn poll_shutdown(
-            self: std::pin::Pin<&mut Self>,
-            _cx: &mut std::task::Context<'_>,
-        ) -> std::task::Poll<Result<(), std::io::Error>> {
+        fn flush(&mut self) -> std::io::Result<()> {
             unreachable!()
         }
     }
 
-    #[tokio::test]
-    async fn ok() {
+    #[test]
+    fn ok() {
         #[derive(Debug)]
         struct OkWrite {
             written: usize,
         }
 
-        impl tokio::io::AsyncWrite for OkWrite {
-            fn poll_write(
-                self: std::pin::Pin<&mut Self>,
-                _cx: &mut std::task::Context<'_>,
-                buf: &[u8],
-            ) -> std::task::Poll<Result<usize, std::io::Error>> {
-                self.get_mut().written = buf.len();
-                std::task::Poll::Ready(Ok(buf.len()))
+        impl Write for OkWrite {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.written = buf.len();
+
+                Ok(buf.len())
             }
 
-            fn poll_flush(
-                self: std::pin::Pin<&mut Self>,
-                _cx: &mut std::task::Context<'_>,
-            ) -> std::task::Poll<Result<(), std::io::Error>> {
+            fn flush(&mut self) -> std::io::Result<()> {
                 unreachable!()
             }
+        }
 
-            fn poll_shutdown(
-                self: std::pin::Pin<&mut Self>,
-                _cx: &mut std::task::Context<'_>,
-            ) -> std::task::Poll<Result<(), std::io::Error>> {
-                unreachable!()
+        let mut ok_write = OkWrite { written: 0 };
+
+        let r = sendline(
+            &mut ok_write,
+            &mut Drip::new(Protocol::Ssh, Arc::default()),
+            100,
+        );
+
+        assert_eq!(Ok(ok_write.written), r);
+    }
+
+    /// Takes up to `window` bytes a write, or none with `WouldBlock` when it's 0.
+    #[derive(Debug)]
+    struct FullWrite {
+        window: usize,
+        written: Vec<u8>,
+    }
+
+    impl Write for FullWrite {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.window == 0 {
+                return Err(std::io::Error::from(ErrorKind::WouldBlock));
             }
+
+            let sent = buf.len().min(self.window);
+
+            self.written.extend_from_slice(&buf[..sent]);
+
+            Ok(sent)
         }
 
-        let ok_write = OkWrite { written: 0 };
+        fn flush(&mut self) -> std::io::Result<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn partial_write_sends_the_rest_next() {
+        let mut drip = Drip::new(Protocol::Ssh, Arc::default());
+        let mut full_write = FullWrite {
+            window: 3,
+            written: Vec::new(),
+        };
 
-        tokio::pin!(ok_write);
+        assert_eq!(Ok(3), sendline(&mut full_write, &mut drip, 100));
 
-        let r = sendline(&mut ok_write, 100).await;
+        full_write.window = usize::MAX;
 
-        assert_eq!(Ok(ok_write.written), r);
+        let rest = sendline(&mut full_write, &mut drip, 100).unwrap();
+
+        // one whole line, the first 3 bytes and then the rest
+        assert_eq!(full_write.written.len(), 3 + rest);
+        assert!(full_write.written.ends_with(b"\r\n"));
+        assert_eq!(
+            full_write
+                .written
+                .windows(2)
+                .filter(|window| window == b"\r\n")
+                .count(),
+            1
+        );
     }
 
-    #[tokio::test]
-    async fn fail_not_connected() {
-        let error_not_connected = ErrorWrite {
-            error: ErrorKind::NotConnected,
+    #[test]
+    #[expect(clippy::big_endian_bytes, reason = "SSH is big-endian on the wire")]
+    fn would_block_keeps_the_kexinit_whole() {
+        let mut drip = Drip::new(Protocol::SshKex, Arc::default());
+        let mut full_write = FullWrite {
+            window: 0,
+            written: Vec::new(),
         };
 
-        tokio::pin!(error_not_connected);
+        // the packet length, a byte at a time, with a full window in between
+        for _ in 0..4 {
+            assert_eq!(Ok(0), sendline(&mut full_write, &mut drip, 100));
+
+            full_write.window = 1;
+
+            assert_eq!(Ok(1), sendline(&mut full_write, &mut drip, 100));
+
+            full_write.window = 0;
+        }
+
+        let packet_length = u32::from_be_bytes(full_write.written.clone().try_into().unwrap());
+
+        full_write.window = 1;
 
-        let r = sendline(&mut error_not_connected, 100).await;
+        for _ in 0..packet_length {
+            assert_eq!(Ok(1), sendline(&mut full_write, &mut drip, 100));
+        }
 
-        assert_eq!(Err(()), r);
+        // SSH_MSG_KEXINIT, right after the padding length
+        assert_eq!(full_write.written[5], 20);
+        assert_eq!(
+            full_write.written.len(),
+            4 + usize::try_from(packet_length).unwrap()
+        );
     }
 
-    #[tokio::test]
-    async fn pass_would_block() {
-        let error_would_block = ErrorWrite {
-            error: ErrorKind::WouldBlock,
+    #[test]
+    fn fail_not_connected() {
+        let mut error_not_connected = ErrorWrite {
+            error: ErrorKind::NotConnected,
         };
 
-        tokio::pin!(error_would_block);
+        let r = sendline(
+            &mut error_not_connected,
+            &mut Drip::new(Protocol::Ssh, Arc::default()),
+            100,
+        );
 
-        let r = sendline(&mut error_would_block, 100).await;
+        assert_eq!(Err(ErrorKind::NotConnected), r);
+    }
+
+    #[test]
+    fn pass_would_block() {
+        let mut error_would_block = ErrorWrite {
+            error: ErrorKind::WouldBlock,
+        };
+
+        let r = sendline(
+            &mut error_would_block,
+            &mut Drip::new(Protocol::Ssh, Arc::default()),
+            100,
+        );
 
         assert_eq!(Ok(0), r);
     }
 
-    #[tokio::test]
-    async fn error_connection_reset() {
-        let error_connection_reset = ErrorWrite {
+    #[test]
+    fn error_connection_reset() {
+        let mut error_connection_reset = ErrorWrite {
             error: ErrorKind::ConnectionReset,
         };
 
-        tokio::pin!(error_connection_reset);
-
-        let r = sendline(&mut error_connection_reset, 100).await;
+        let r = sendline(
+            &mut error_connection_reset,
+            &mut Drip::new(Protocol::Ssh, Arc::default()),
+            100,
+        );
 
-        assert_eq!(Err(()), r);
+        assert_eq!(Err(ErrorKind::ConnectionReset), r);
     }
 }
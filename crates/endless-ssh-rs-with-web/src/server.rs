@@ -6,22 +6,36 @@ use tokio_util::sync::CancellationToken;
 use tracing::{Level, event};
 
 /// Set up server on socket, with a router, and a cancellation token for graceful shutdown.
+/// `socket` is the one systemd passed, or the one bound before dropping privileges, `bind_to` is only bound without it.
 ///
 /// # Errors
 /// * Couldn't bind to address
+/// * Couldn't take over the passed socket
 /// * Server failure
 pub async fn setup_server(
     bind_to: SocketAddr,
+    socket: Option<std::net::TcpListener>,
     router: Router,
     cancellation_token: CancellationToken,
 ) -> Result<(), eyre::Report> {
-    event!(Level::INFO, ?bind_to, "Trying to bind");
+    let listener = if let Some(socket) = socket {
+        event!(Level::INFO, ?socket, "Using already bound socket");
 
-    let listener = tokio::net::TcpListener::bind(bind_to)
-        .await
-        .wrap_err("Failed to bind Webserver to port")?;
+        socket
+            .set_nonblocking(true)
+            .and_then(|()| tokio::net::TcpListener::from_std(socket))
+            .wrap_err("Failed to take over Webserver socket")?
+    } else {
+        event!(Level::INFO, ?bind_to, "Trying to bind");
+
+        let listener = tokio::net::TcpListener::bind(bind_to)
+            .await
+            .wrap_err("Failed to bind Webserver to port")?;
+
+        event!(Level::INFO, ?bind_to, "Webserver bound successfully");
 
-    event!(Level::INFO, ?bind_to, "Webserver bound successfully");
+        listener
+    };
 
     axum::serve(listener, router)
         .with_graceful_shutdown(cancellation_token.cancelled_owned())
@@ -5,7 +5,7 @@ use tracing::{Level, event};
 
 /// Represents all ways the application can terminate.
 pub enum Shutdown {
-    #[expect(unused, reason = "Application is a daemon")]
+    /// Only when asked to print the configuration, the application is a daemon.
     Success,
     Signal(u8),
     OperationalFailure {
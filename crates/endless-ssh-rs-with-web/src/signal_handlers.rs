@@ -76,6 +76,46 @@ pub async fn wait_for_sigint() -> Shutdown {
     }
 }
 
+/// `SIGHUP`s, which ask us to reload the configuration.
+pub struct Sighups {
+    #[cfg(not(any(target_os = "windows", miri)))]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Sighups {
+    pub fn new() -> Self {
+        #[cfg(not(any(target_os = "windows", miri)))]
+        let signal = signal(SignalKind::hangup())
+            .inspect_err(|error| {
+                event!(
+                    Level::WARN,
+                    ?error,
+                    "Failed to register SIGHUP handler, configuration reload disabled"
+                );
+            })
+            .ok();
+
+        Self {
+            #[cfg(not(any(target_os = "windows", miri)))]
+            signal,
+        }
+    }
+
+    /// Waits for the next `SIGHUP`, forever when we can't receive them.
+    pub async fn recv(&mut self) {
+        #[cfg(not(any(target_os = "windows", miri)))]
+        if let Some(signal) = self.signal.as_mut()
+            && signal.recv().await.is_some()
+        {
+            event!(Level::INFO, "SIGHUP detected, reloading configuration");
+
+            return;
+        }
+
+        std::future::pending::<()>().await;
+    }
+}
+
 #[expect(unused, reason = "Unused")]
 /// Installs `sig_handler` for `signum` via `sigaction`.
 ///
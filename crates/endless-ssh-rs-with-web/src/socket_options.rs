@@ -0,0 +1,348 @@
+use std::io::Error;
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32};
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
+
+use libc::{
+    IP_TOS, IPPROTO_IP, IPPROTO_IPV6, IPPROTO_TCP, IPV6_TCLASS, SO_KEEPALIVE, SO_RCVBUF, SO_SNDBUF,
+    SOL_SOCKET, TCP_KEEPCNT, TCP_KEEPIDLE, TCP_KEEPINTVL, TCP_MAXSEG, TCP_NODELAY,
+    TCP_USER_TIMEOUT, TCP_WINDOW_CLAMP, c_int,
+};
+use tracing::{Level, event};
+
+use crate::ffi_wrapper::{
+    get_linger, get_socket_option, set_backlog, set_linger, set_socket_option,
+};
+
+/// The smallest possible receive buffer, the kernel rounds it up to its minimum. This reduces local
+/// resource usage and slows down the remote end.
+pub const DEFAULT_RECEIVE_BUFFER: NonZeroU32 = NonZeroU32::MIN;
+
+/// Options of a listener's socket, which the connections it accepts inherit. `None` leaves the kernel's default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// `SO_RCVBUF`, in bytes.
+    pub receive_buffer: Option<NonZeroU32>,
+    /// `SO_SNDBUF`, in bytes.
+    pub send_buffer: Option<NonZeroU32>,
+    /// `TCP_MAXSEG`, the largest segment we accept, announced in the handshake.
+    pub max_segment_size: Option<NonZeroU16>,
+    /// `TCP_WINDOW_CLAMP`, the largest window we advertise.
+    pub window_clamp: Option<NonZeroU32>,
+    /// `TCP_NODELAY`, so every line goes out as soon as it's written.
+    pub nodelay: bool,
+    /// `IP_TOS`, or `IPV6_TCLASS` on IPv6 sockets.
+    pub tos: Option<u8>,
+    /// Connections waiting to be accepted.
+    pub backlog: Option<NonZeroU32>,
+    /// `SO_LINGER`, how long closing waits for unsent data, zero resets the connection.
+    pub linger: Option<Duration>,
+    /// `SO_KEEPALIVE`, probes a connection that's been idle, to find peers that vanished without a FIN.
+    pub keepalive: bool,
+    /// `TCP_KEEPIDLE`, idle time before the first probe, in whole seconds.
+    pub keepalive_idle: Option<Duration>,
+    /// `TCP_KEEPINTVL`, time between probes, in whole seconds.
+    pub keepalive_interval: Option<Duration>,
+    /// `TCP_KEEPCNT`, unanswered probes before the connection times out.
+    pub keepalive_count: Option<NonZeroU8>,
+    /// `TCP_USER_TIMEOUT`, how long sent data may go unacknowledged before the connection times out.
+    pub user_timeout: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            receive_buffer: Some(DEFAULT_RECEIVE_BUFFER),
+            send_buffer: None,
+            max_segment_size: None,
+            window_clamp: None,
+            nodelay: false,
+            tos: None,
+            backlog: None,
+            linger: None,
+            keepalive: false,
+            keepalive_idle: None,
+            keepalive_interval: None,
+            keepalive_count: None,
+            user_timeout: None,
+        }
+    }
+}
+
+/// Sizes beyond `c_int` are capped, the kernel caps them far lower anyway.
+fn to_c_int(value: NonZeroU32) -> c_int {
+    c_int::try_from(value.get()).unwrap_or(c_int::MAX)
+}
+
+fn seconds(duration: Duration) -> c_int {
+    c_int::try_from(duration.as_secs()).unwrap_or(c_int::MAX)
+}
+
+fn milliseconds(duration: Duration) -> c_int {
+    c_int::try_from(duration.as_millis()).unwrap_or(c_int::MAX)
+}
+
+/// Sets a single option, logging when that fails.
+fn set(
+    listener: &str,
+    socket: BorrowedFd<'_>,
+    option: &str,
+    (level, name): (c_int, c_int),
+    value: c_int,
+) {
+    if let Err(error) = set_socket_option(socket, level, name, value) {
+        report(listener, option, &error);
+    }
+}
+
+impl SocketOptions {
+    /// Sets every configured option on the listening `socket`. An option that fails is logged, and doesn't keep
+    /// the others from being set.
+    pub fn apply(&self, listener: &str, socket: BorrowedFd<'_>, ipv6: bool) {
+        if let Some(size) = self.receive_buffer {
+            set(
+                listener,
+                socket,
+                "SO_RCVBUF",
+                (SOL_SOCKET, SO_RCVBUF),
+                to_c_int(size),
+            );
+        }
+
+        if let Some(size) = self.send_buffer {
+            set(
+                listener,
+                socket,
+                "SO_SNDBUF",
+                (SOL_SOCKET, SO_SNDBUF),
+                to_c_int(size),
+            );
+        }
+
+        if let Some(size) = self.max_segment_size {
+            set(
+                listener,
+                socket,
+                "TCP_MAXSEG",
+                (IPPROTO_TCP, TCP_MAXSEG),
+                size.get().into(),
+            );
+        }
+
+        if let Some(size) = self.window_clamp {
+            set(
+                listener,
+                socket,
+                "TCP_WINDOW_CLAMP",
+                (IPPROTO_TCP, TCP_WINDOW_CLAMP),
+                to_c_int(size),
+            );
+        }
+
+        if self.nodelay {
+            set(
+                listener,
+                socket,
+                "TCP_NODELAY",
+                (IPPROTO_TCP, TCP_NODELAY),
+                1,
+            );
+        }
+
+        if let Some(tos) = self.tos {
+            if ipv6 {
+                set(
+                    listener,
+                    socket,
+                    "IPV6_TCLASS",
+                    (IPPROTO_IPV6, IPV6_TCLASS),
+                    tos.into(),
+                );
+            } else {
+                set(listener, socket, "IP_TOS", (IPPROTO_IP, IP_TOS), tos.into());
+            }
+        }
+
+        self.apply_timeouts(listener, socket);
+
+        if let Some(linger) = self.linger
+            && let Err(error) = set_linger(socket, linger)
+        {
+            report(listener, "SO_LINGER", &error);
+        }
+
+        if let Some(backlog) = self.backlog
+            && let Err(error) = set_backlog(socket, to_c_int(backlog))
+        {
+            report(listener, "backlog", &error);
+        }
+    }
+
+    /// Keepalive and `TCP_USER_TIMEOUT`, which have the kernel give up on a peer that's gone silent.
+    fn apply_timeouts(&self, listener: &str, socket: BorrowedFd<'_>) {
+        if self.keepalive {
+            set(
+                listener,
+                socket,
+                "SO_KEEPALIVE",
+                (SOL_SOCKET, SO_KEEPALIVE),
+                1,
+            );
+        }
+
+        if let Some(idle) = self.keepalive_idle {
+            set(
+                listener,
+                socket,
+                "TCP_KEEPIDLE",
+                (IPPROTO_TCP, TCP_KEEPIDLE),
+                seconds(idle),
+            );
+        }
+
+        if let Some(interval) = self.keepalive_interval {
+            set(
+                listener,
+                socket,
+                "TCP_KEEPINTVL",
+                (IPPROTO_TCP, TCP_KEEPINTVL),
+                seconds(interval),
+            );
+        }
+
+        if let Some(count) = self.keepalive_count {
+            set(
+                listener,
+                socket,
+                "TCP_KEEPCNT",
+                (IPPROTO_TCP, TCP_KEEPCNT),
+                count.get().into(),
+            );
+        }
+
+        if let Some(timeout) = self.user_timeout {
+            set(
+                listener,
+                socket,
+                "TCP_USER_TIMEOUT",
+                (IPPROTO_TCP, TCP_USER_TIMEOUT),
+                milliseconds(timeout),
+            );
+        }
+    }
+
+    /// Logs the options `socket` ended up with, the kernel adjusts some, e.g. it doubles `SO_RCVBUF`.
+    pub fn log_effective(listener: &str, socket: BorrowedFd<'_>, ipv6: bool) {
+        let get = |level, name| {
+            get_socket_option(socket, level, name).map_or_else(
+                |error| format!("unknown ({})", error),
+                |value| value.to_string(),
+            )
+        };
+
+        let tos = if ipv6 {
+            get(IPPROTO_IPV6, IPV6_TCLASS)
+        } else {
+            get(IPPROTO_IP, IP_TOS)
+        };
+
+        let linger = match get_linger(socket) {
+            Ok(Some(linger)) => format!("{}s", linger.as_secs()),
+            Ok(None) => "off".to_owned(),
+            Err(error) => format!("unknown ({})", error),
+        };
+
+        event!(
+            Level::INFO,
+            listener,
+            "ReceiveBuffer: {}, SendBuffer: {}, MaxSegmentSize: {}, WindowClamp: {}, NoDelay: {}, Tos: {}, Linger: {}, KeepAlive: {}, KeepIdle: {}s, KeepInterval: {}s, KeepCount: {}, UserTimeout: {}ms",
+            get(SOL_SOCKET, SO_RCVBUF),
+            get(SOL_SOCKET, SO_SNDBUF),
+            get(IPPROTO_TCP, TCP_MAXSEG),
+            get(IPPROTO_TCP, TCP_WINDOW_CLAMP),
+            get(IPPROTO_TCP, TCP_NODELAY),
+            tos,
+            linger,
+            get(SOL_SOCKET, SO_KEEPALIVE),
+            get(IPPROTO_TCP, TCP_KEEPIDLE),
+            get(IPPROTO_TCP, TCP_KEEPINTVL),
+            get(IPPROTO_TCP, TCP_KEEPCNT),
+            get(IPPROTO_TCP, TCP_USER_TIMEOUT),
+        );
+    }
+}
+
+fn report(listener: &str, option: &str, error: &Error) {
+    event!(
+        Level::WARN,
+        listener,
+        option,
+        ?error,
+        "Failed to set socket option"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::num::{NonZeroU8, NonZeroU32};
+    use std::os::fd::AsFd as _;
+    use std::time::Duration;
+
+    use libc::{
+        IPPROTO_TCP, SO_KEEPALIVE, SO_SNDBUF, SOL_SOCKET, TCP_KEEPCNT, TCP_KEEPIDLE, TCP_NODELAY,
+        TCP_USER_TIMEOUT,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::ffi_wrapper::{get_linger, get_socket_option};
+    use crate::socket_options::SocketOptions;
+
+    #[test]
+    fn applies_options() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let options = SocketOptions {
+            send_buffer: NonZeroU32::new(8192),
+            nodelay: true,
+            linger: Some(Duration::ZERO),
+            backlog: NonZeroU32::new(16),
+            ..SocketOptions::default()
+        };
+
+        options.apply("test", listener.as_fd(), false);
+
+        // the kernel doubles it, for its own bookkeeping
+        assert!(get_socket_option(listener.as_fd(), SOL_SOCKET, SO_SNDBUF).unwrap() >= 8192);
+        assert_eq!(
+            get_socket_option(listener.as_fd(), IPPROTO_TCP, TCP_NODELAY).unwrap(),
+            1
+        );
+        assert_eq!(get_linger(listener.as_fd()).unwrap(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn accepted_connections_inherit_timeouts() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let options = SocketOptions {
+            keepalive: true,
+            keepalive_idle: Some(Duration::from_secs(60)),
+            keepalive_count: NonZeroU8::new(3),
+            user_timeout: Some(Duration::from_secs(30)),
+            ..SocketOptions::default()
+        };
+
+        options.apply("test", listener.as_fd(), false);
+
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+
+        let get = |level, name| get_socket_option(accepted.as_fd(), level, name).unwrap();
+
+        assert_eq!(get(SOL_SOCKET, SO_KEEPALIVE), 1);
+        assert_eq!(get(IPPROTO_TCP, TCP_KEEPIDLE), 60);
+        assert_eq!(get(IPPROTO_TCP, TCP_KEEPCNT), 3);
+        assert_eq!(get(IPPROTO_TCP, TCP_USER_TIMEOUT), 30_000);
+    }
+}
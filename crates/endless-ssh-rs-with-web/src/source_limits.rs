@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+
+/// IPv4 sources are grouped by /24.
+pub const IPV4_SUBNET_PREFIX: u8 = 24;
+/// IPv6 sources are grouped by /64 unless configured otherwise, one end-user network.
+pub const DEFAULT_IPV6_SUBNET_PREFIX: u8 = 64;
+
+/// What happens to a connection that would exceed its source's limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExcessAction {
+    /// Close it right away.
+    #[default]
+    Close,
+    /// Reset it, so the client doesn't even get a FIN.
+    Reset,
+    /// Drip it a few lines, without taking one of the listener's slots.
+    Tarpit,
+}
+
+impl ExcessAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExcessAction::Close => "close",
+            ExcessAction::Reset => "reset",
+            ExcessAction::Tarpit => "tarpit",
+        }
+    }
+}
+
+impl Display for ExcessAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExcessAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "close" => Ok(ExcessAction::Close),
+            "reset" => Ok(ExcessAction::Reset),
+            "tarpit" => Ok(ExcessAction::Tarpit),
+            _ => Err(format!(
+                "Unknown excess action `{}`, expected `close`, `reset` or `tarpit`",
+                s
+            )),
+        }
+    }
+}
+
+/// Per source caps on a listener's concurrent connections, so a single scanner can't take all of its slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLimits {
+    /// Connections from a single address, unlimited when `None`.
+    pub per_ip: Option<NonZeroU32>,
+    /// Connections from a single IPv4 /24, or IPv6 /`ipv6_prefix`, unlimited when `None`.
+    pub per_subnet: Option<NonZeroU32>,
+    /// 48 or 64.
+    pub ipv6_prefix: u8,
+    pub action: ExcessAction,
+}
+
+impl Default for SourceLimits {
+    fn default() -> Self {
+        Self {
+            per_ip: None,
+            per_subnet: None,
+            ipv6_prefix: DEFAULT_IPV6_SUBNET_PREFIX,
+            action: ExcessAction::Close,
+        }
+    }
+}
+
+/// The subnet `ip` counts towards. IPv4-mapped addresses, as seen on dual-stack sockets, count as IPv4.
+pub fn subnet(ip: IpAddr, ipv6_prefix: u8) -> IpNet {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => IpNet::V4(
+            Ipv4Net::new(v4, IPV4_SUBNET_PREFIX)
+                .expect("24 is a valid IPv4 prefix")
+                .trunc(),
+        ),
+        IpAddr::V6(v6) => IpNet::V6(
+            Ipv6Net::new(v6, ipv6_prefix)
+                .expect("IPv6 prefixes are validated when parsed")
+                .trunc(),
+        ),
+    }
+}
+
+/// Which limit a connection would have exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Excess {
+    Ip,
+    Subnet,
+}
+
+#[derive(Debug)]
+struct Counts {
+    limits: SourceLimits,
+    ips: BTreeMap<IpAddr, u32>,
+    subnets: BTreeMap<IpNet, u32>,
+}
+
+/// A listener's current connections, by address and by subnet.
+#[derive(Debug)]
+pub struct SourceCounts {
+    counts: Mutex<Counts>,
+}
+
+impl SourceCounts {
+    pub fn new(limits: SourceLimits) -> Self {
+        Self {
+            counts: Mutex::new(Counts {
+                limits,
+                ips: BTreeMap::new(),
+                subnets: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// From now on, connections are admitted under `limits`. Those already counted keep counting towards their subnet.
+    pub fn set_limits(&self, limits: SourceLimits) {
+        self.counts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .limits = limits;
+    }
+
+    /// Counts a connection from `ip`, unless that would exceed one of the limits.
+    pub fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<SourceSlot, Excess> {
+        let ip = ip.to_canonical();
+
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let limits = counts.limits;
+        let subnet = subnet(ip, limits.ipv6_prefix);
+
+        let from_ip = counts.ips.get(&ip).copied().unwrap_or_default();
+        let from_subnet = counts.subnets.get(&subnet).copied().unwrap_or_default();
+
+        if exceeds(from_ip, limits.per_ip) {
+            return Err(Excess::Ip);
+        }
+
+        if exceeds(from_subnet, limits.per_subnet) {
+            return Err(Excess::Subnet);
+        }
+
+        counts.ips.insert(ip, from_ip.saturating_add(1));
+        counts.subnets.insert(subnet, from_subnet.saturating_add(1));
+
+        Ok(SourceSlot {
+            counts: Arc::clone(self),
+            ip,
+            subnet,
+        })
+    }
+
+    /// The connections counted from `ip`, and from `subnet`.
+    pub fn current(&self, ip: IpAddr, subnet: IpNet) -> (u32, u32) {
+        let counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+
+        (
+            counts
+                .ips
+                .get(&ip.to_canonical())
+                .copied()
+                .unwrap_or_default(),
+            counts.subnets.get(&subnet).copied().unwrap_or_default(),
+        )
+    }
+}
+
+fn exceeds(current: u32, limit: Option<NonZeroU32>) -> bool {
+    limit.is_some_and(|limit| current >= limit.get())
+}
+
+fn release<K: Ord>(map: &mut BTreeMap<K, u32>, key: K) {
+    if let Entry::Occupied(mut entry) = map.entry(key) {
+        if *entry.get() <= 1 {
+            entry.remove();
+        } else {
+            *entry.get_mut() -= 1;
+        }
+    }
+}
+
+/// A connection counted towards its source's limits, until dropped.
+#[derive(Debug)]
+pub struct SourceSlot {
+    counts: Arc<SourceCounts>,
+    ip: IpAddr,
+    subnet: IpNet,
+}
+
+impl SourceSlot {
+    pub fn subnet(&self) -> IpNet {
+        self.subnet
+    }
+
+    /// The listener's counts, this connection's among them.
+    pub fn counts(&self) -> Arc<SourceCounts> {
+        Arc::clone(&self.counts)
+    }
+}
+
+impl Drop for SourceSlot {
+    fn drop(&mut self) {
+        let mut counts = self
+            .counts
+            .counts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        release(&mut counts.ips, self.ip);
+        release(&mut counts.subnets, self.subnet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::num::NonZeroU32;
+    use std::sync::Arc;
+
+    use ipnet::IpNet;
+    use pretty_assertions::{assert_eq, assert_matches};
+
+    use crate::source_limits::{Excess, ExcessAction, SourceCounts, SourceLimits, subnet};
+
+    #[test]
+    fn groups_by_subnet() {
+        let v4 = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+        let v6 = "2001:db8:1:2:3::4".parse::<IpAddr>().unwrap();
+        let mapped = IpAddr::V6(Ipv4Addr::new(198, 51, 100, 7).to_ipv6_mapped());
+
+        assert_eq!(subnet(v4, 64), "198.51.100.0/24".parse::<IpNet>().unwrap());
+        assert_eq!(
+            subnet(mapped, 64),
+            "198.51.100.0/24".parse::<IpNet>().unwrap()
+        );
+        assert_eq!(
+            subnet(v6, 64),
+            "2001:db8:1:2::/64".parse::<IpNet>().unwrap()
+        );
+        assert_eq!(subnet(v6, 48), "2001:db8:1::/48".parse::<IpNet>().unwrap());
+    }
+
+    #[test]
+    fn limits_connections_per_ip() {
+        let counts = Arc::new(SourceCounts::new(SourceLimits {
+            per_ip: NonZeroU32::new(2),
+            ..SourceLimits::default()
+        }));
+
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        let first = counts.admit(ip).unwrap();
+        let _second = counts.admit(ip).unwrap();
+
+        assert_matches!(counts.admit(ip), Err(Excess::Ip));
+        // same subnet, different address
+        assert_matches!(counts.admit(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2))), Ok(_));
+
+        drop(first);
+
+        assert_matches!(counts.admit(ip), Ok(_));
+    }
+
+    #[test]
+    fn counts_current_connections() {
+        let counts = Arc::new(SourceCounts::new(SourceLimits::default()));
+
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let mapped = IpAddr::V6(Ipv4Addr::new(192, 0, 2, 1).to_ipv6_mapped());
+        let subnet = subnet(ip, 64);
+
+        let first = counts.admit(ip).unwrap();
+        let _second = counts
+            .admit(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)))
+            .unwrap();
+
+        assert_eq!(counts.current(mapped, subnet), (1, 2));
+
+        drop(first);
+
+        assert_eq!(counts.current(ip, subnet), (0, 1));
+    }
+
+    #[test]
+    fn limits_connections_per_subnet() {
+        let counts = Arc::new(SourceCounts::new(SourceLimits {
+            per_subnet: NonZeroU32::new(2),
+            ipv6_prefix: 48,
+            ..SourceLimits::default()
+        }));
+
+        let _first = counts.admit("2001:db8:1:1::1".parse().unwrap()).unwrap();
+        let _second = counts.admit("2001:db8:1:2::1".parse().unwrap()).unwrap();
+
+        assert_matches!(
+            counts.admit("2001:db8:1:3::1".parse().unwrap()),
+            Err(Excess::Subnet)
+        );
+        assert_matches!(counts.admit(IpAddr::V6(Ipv6Addr::LOCALHOST)), Ok(_));
+    }
+
+    #[test]
+    fn applies_reloaded_limits() {
+        let counts = Arc::new(SourceCounts::new(SourceLimits::default()));
+
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        let first = counts.admit(ip).unwrap();
+        let _second = counts.admit(ip).unwrap();
+
+        counts.set_limits(SourceLimits {
+            per_ip: NonZeroU32::new(2),
+            ..SourceLimits::default()
+        });
+
+        // those already in count towards the new limit
+        assert_matches!(counts.admit(ip), Err(Excess::Ip));
+
+        drop(first);
+
+        assert_matches!(counts.admit(ip), Ok(_));
+    }
+
+    #[test]
+    fn parses_excess_action() {
+        for action in [
+            ExcessAction::Close,
+            ExcessAction::Reset,
+            ExcessAction::Tarpit,
+        ] {
+            assert_eq!(action.as_str().parse(), Ok(action));
+        }
+
+        assert_matches!("drop".parse::<ExcessAction>(), Err(_));
+    }
+}
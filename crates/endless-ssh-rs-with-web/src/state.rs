@@ -9,6 +9,7 @@ use tokio::sync::broadcast;
 
 use crate::events::{ActiveConnectionInfo, WsEvent};
 use crate::geoip::GeoIpReader;
+use crate::scheduler::Engine;
 use crate::states::config::Config;
 
 /// This is to be able to do:
@@ -32,6 +33,7 @@ pub struct ApplicationState {
     pub geo_ip_reader: Arc<GeoIpReader>,
     pub ws_broadcast: broadcast::Sender<WsEvent>,
     pub active_connections: Arc<DashMap<SocketAddr, ActiveConnectionInfo>>,
+    pub engine: Arc<Engine>,
 }
 
 impl ApplicationState {
@@ -41,6 +43,7 @@ impl ApplicationState {
         geo_ip_reader: Arc<GeoIpReader>,
         ws_broadcast: broadcast::Sender<WsEvent>,
         active_connections: Arc<DashMap<SocketAddr, ActiveConnectionInfo>>,
+        engine: Arc<Engine>,
     ) -> Self {
         ApplicationState {
             config: Arc::new(config),
@@ -48,6 +51,7 @@ impl ApplicationState {
             geo_ip_reader,
             ws_broadcast,
             active_connections,
+            engine,
         }
     }
 }
@@ -0,0 +1,215 @@
+use std::env;
+use std::io::Error;
+use std::net::{SocketAddr, TcpListener};
+use std::os::fd::{AsFd as _, FromRawFd as _, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt as _;
+use std::os::unix::net::{self, UnixDatagram};
+use std::process;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use tracing::{Level, event};
+
+use crate::ffi_wrapper::set_close_on_exec;
+
+/// The first file descriptor systemd passes, see `sd_listen_fds(3)`.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Name of the dashboard's socket, set with `FileDescriptorName=` in its `.socket` unit.
+pub const HTTP_SOCKET_NAME: &str = "http";
+
+/// The file descriptors, and their names, `LISTEN_FDS` and `LISTEN_FDNAMES` pass to process `pid`.
+fn parse_listen_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&str>,
+    pid: u32,
+) -> Vec<(RawFd, Option<String>)> {
+    // meant for someone else, e.g. the shell that started us
+    if listen_pid.and_then(|listen_pid| listen_pid.parse::<u32>().ok()) != Some(pid) {
+        return Vec::new();
+    }
+
+    let Some(count) = listen_fds.and_then(|listen_fds| listen_fds.parse::<RawFd>().ok()) else {
+        return Vec::new();
+    };
+
+    let mut names = listen_fdnames
+        .into_iter()
+        .flat_map(|names| names.split(':'));
+
+    (LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count))
+        .map(|fd| {
+            let name = names
+                .next()
+                .filter(|name| !name.is_empty() && *name != "unknown")
+                .map(str::to_owned);
+
+            (fd, name)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Socket {
+    name: Option<String>,
+    address: SocketAddr,
+    listener: TcpListener,
+}
+
+/// The listening sockets systemd passed us, each claimed by the listener, or the dashboard, it's meant for.
+#[derive(Debug, Default)]
+pub struct ActivatedSockets {
+    sockets: Mutex<Vec<Socket>>,
+}
+
+impl ActivatedSockets {
+    /// Takes over the sockets in `LISTEN_FDS`, none when we weren't socket activated.
+    pub fn from_env() -> Self {
+        let fds = parse_listen_fds(
+            env::var("LISTEN_PID").ok().as_deref(),
+            env::var("LISTEN_FDS").ok().as_deref(),
+            env::var("LISTEN_FDNAMES").ok().as_deref(),
+            process::id(),
+        );
+
+        let mut sockets = Vec::with_capacity(fds.len());
+
+        for (fd, name) in fds {
+            // SAFETY: systemd passed us this file descriptor, and we only take it once
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            if let Err(error) = set_close_on_exec(fd.as_fd()) {
+                event!(
+                    Level::WARN,
+                    ?fd,
+                    ?error,
+                    "Failed to set close-on-exec on passed socket"
+                );
+            }
+
+            let listener = TcpListener::from(fd);
+
+            match listener.local_addr() {
+                Ok(address) => {
+                    event!(Level::INFO, ?name, %address, "Received socket from systemd");
+
+                    sockets.push(Socket {
+                        name,
+                        address,
+                        listener,
+                    });
+                },
+                Err(error) => {
+                    event!(
+                        Level::WARN,
+                        ?name,
+                        ?error,
+                        "Passed file descriptor isn't a TCP socket, closing it"
+                    );
+                },
+            }
+        }
+
+        Self {
+            sockets: Mutex::new(sockets),
+        }
+    }
+
+    /// The socket named `name`, or else the one bound to `address`. `None` when systemd passed neither.
+    pub fn take(&self, name: &str, address: SocketAddr) -> Option<TcpListener> {
+        let mut sockets = self.sockets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let index = sockets
+            .iter()
+            .position(|socket| socket.name.as_deref() == Some(name))
+            .or_else(|| sockets.iter().position(|socket| socket.address == address))?;
+
+        Some(sockets.swap_remove(index).listener)
+    }
+
+    /// Logs the sockets nobody claimed, and closes them.
+    pub fn close_unclaimed(&self) {
+        let mut sockets = self.sockets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for socket in sockets.drain(..) {
+            event!(
+                Level::WARN,
+                name = ?socket.name,
+                address = %socket.address,
+                "No listener for socket passed by systemd, closing it"
+            );
+        }
+    }
+}
+
+/// Tells systemd about our state, see `sd_notify(3)`. Does nothing when we weren't started with `Type=notify`.
+pub fn notify(state: &str) -> Result<(), Error> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+
+    let address = match path.as_encoded_bytes().strip_prefix(b"@") {
+        Some(name) => net::SocketAddr::from_abstract_name(name)?,
+        None => net::SocketAddr::from_pathname(path)?,
+    };
+
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+
+    Ok(())
+}
+
+/// Sends `state`, logging when that fails, systemd not hearing from us is no reason to stop.
+pub fn notify_or_log(state: &str) {
+    if let Err(error) = notify(state) {
+        event!(Level::WARN, ?error, state, "Failed to notify systemd");
+    }
+}
+
+/// How often systemd wants to hear `WATCHDOG=1`, half of `WatchdogSec=`. `None` when it doesn't.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(watchdog_pid) = env::var("WATCHDOG_PID")
+        && watchdog_pid.parse::<u32>().ok() != Some(process::id())
+    {
+        return None;
+    }
+
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+        .map(|usec| Duration::from_micros(usec) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::systemd::parse_listen_fds;
+
+    #[test]
+    fn parses_listen_fds() {
+        assert_eq!(
+            parse_listen_fds(Some("42"), Some("3"), Some("ssh:http"), 42),
+            vec![
+                (3, Some("ssh".to_owned())),
+                (4, Some("http".to_owned())),
+                (5, None),
+            ]
+        );
+        assert_eq!(
+            parse_listen_fds(Some("42"), Some("1"), None, 42),
+            vec![(3, None)]
+        );
+    }
+
+    #[test]
+    fn ignores_fds_for_other_processes() {
+        assert_eq!(
+            parse_listen_fds(Some("41"), Some("2"), None, 42),
+            Vec::new()
+        );
+        assert_eq!(parse_listen_fds(None, Some("2"), None, 42), Vec::new());
+        assert_eq!(parse_listen_fds(Some("42"), None, None, 42), Vec::new());
+    }
+}
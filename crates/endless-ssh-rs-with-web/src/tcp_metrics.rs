@@ -0,0 +1,77 @@
+use std::os::fd::BorrowedFd;
+
+use serde::Serialize;
+use tracing::{Level, event};
+
+use crate::ffi_wrapper::get_tcp_info;
+
+/// What the kernel measured of a connection, from `TCP_INFO`. A client close by has a short round trip time, and one
+/// far away, or behind a congested link, a long one, and more retransmits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(test, derive(ts_rs::TS), ts(export))]
+pub struct TcpMetrics {
+    /// Smoothed round trip time, in microseconds.
+    pub rtt_us: u32,
+    /// Mean deviation of the round trip time, in microseconds.
+    pub rtt_var_us: u32,
+    /// The largest segment we send the client, in bytes.
+    pub mss: u32,
+    /// Segments retransmitted over the connection's life.
+    pub retransmits: u32,
+    /// Congestion window, in segments.
+    pub congestion_window: u32,
+}
+
+impl TcpMetrics {
+    /// Reads the metrics of the connection `fd`, which works until it's closed, even once the client is gone. `None`
+    /// when the kernel doesn't tell.
+    pub fn read(fd: BorrowedFd<'_>) -> Option<Self> {
+        match get_tcp_info(fd) {
+            Ok(info) => Some(Self {
+                rtt_us: info.tcpi_rtt,
+                rtt_var_us: info.tcpi_rttvar,
+                mss: info.tcpi_snd_mss,
+                retransmits: info.tcpi_total_retrans,
+                congestion_window: info.tcpi_snd_cwnd,
+            }),
+            Err(error) => {
+                event!(Level::DEBUG, ?error, "Failed to read TCP_INFO");
+
+                None
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+    use std::net::{TcpListener, TcpStream, UdpSocket};
+    use std::os::fd::AsFd as _;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::tcp_metrics::TcpMetrics;
+
+    #[test]
+    fn reads_metrics_of_a_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        client.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+
+        let metrics = TcpMetrics::read(server.as_fd()).unwrap();
+
+        assert!(metrics.mss > 0);
+        assert!(metrics.congestion_window > 0);
+        assert_eq!(metrics.retransmits, 0);
+    }
+
+    #[test]
+    fn no_metrics_of_a_udp_socket() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        assert_eq!(TcpMetrics::read(socket.as_fd()), None);
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use serde::Serializer;
 use time::SignedDuration;
 
@@ -7,3 +9,11 @@ where
 {
     s.serialize_i64(duration.whole_seconds())
 }
+
+pub fn as_display<T, S>(value: &T, s: S) -> Result<S::Ok, S::Error>
+where
+    T: Display,
+    S: Serializer,
+{
+    s.collect_str(value)
+}
@@ -0,0 +1,141 @@
+use std::io::Error;
+use std::os::fd::{AsRawFd as _, BorrowedFd, FromRawFd as _, OwnedFd};
+
+use tokio::io::unix::AsyncFd;
+
+/// Hangups and errors, but NOT `EPOLLIN`. This lets us detect disconnects without spending
+/// time reading what the client sends. One shot, a client only leaves once.
+const INTERESTED_EVENTS: u32 =
+    (libc::EPOLLRDHUP | libc::EPOLLERR | libc::EPOLLHUP | libc::EPOLLONESHOT).cast_unsigned();
+
+/// Events read per `epoll_wait`.
+const EVENTS: usize = 256;
+
+/// A single epoll set watching every trapped client for disconnects, instead of an epoll fd per client.
+pub struct DisconnectWatcher {
+    epoll: AsyncFd<OwnedFd>,
+}
+
+impl DisconnectWatcher {
+    pub fn new() -> Result<Self, Error> {
+        // SAFETY: syscall
+        let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epfd < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // SAFETY: epfd is a valid fd we now own; wrap it immediately so it is closed
+        // on any subsequent error path
+        let owned_epfd = unsafe { OwnedFd::from_raw_fd(epfd) };
+
+        Ok(Self {
+            epoll: AsyncFd::new(owned_epfd)?,
+        })
+    }
+
+    /// Watches `socket`, its disconnect is reported as `token`. Closing the socket removes it from the set.
+    pub fn register(&self, socket: BorrowedFd<'_>, token: u64) -> Result<(), Error> {
+        let mut ev = libc::epoll_event {
+            events: INTERESTED_EVENTS,
+            u64: token,
+        };
+
+        // SAFETY: epoll holds a valid epoll fd; socket is a valid fd owned
+        // by the caller; ev is fully initialized
+        let ret = unsafe {
+            libc::epoll_ctl(
+                self.epoll.get_ref().as_raw_fd(),
+                libc::EPOLL_CTL_ADD,
+                socket.as_raw_fd(),
+                &raw mut ev,
+            )
+        };
+        if ret != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Waits for disconnects, appending their tokens to `tokens`.
+    pub async fn wait(&self, tokens: &mut Vec<u64>) -> Result<(), Error> {
+        let mut guard = self.epoll.readable().await?;
+
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; EVENTS];
+
+        // SAFETY: epoll holds a valid epoll fd, events is a valid output buffer of EVENTS entries
+        let n = unsafe {
+            // don't block, the fd is ready based on this fut
+            const TIMEOUT: i32 = 0;
+
+            libc::epoll_wait(
+                guard.get_ref().as_raw_fd(),
+                events.as_mut_ptr(),
+                i32::try_from(EVENTS).expect("256 fits in an i32"),
+                TIMEOUT,
+            )
+        };
+
+        let Ok(n) = usize::try_from(n) else {
+            let error = Error::last_os_error();
+
+            if error.raw_os_error() == Some(libc::EINTR) {
+                // interrupted, retry on the next call
+                return Ok(());
+            }
+
+            return Err(error);
+        };
+
+        // a full buffer means there might be more
+        if n < EVENTS {
+            guard.clear_ready();
+        }
+
+        tokens.extend(events.iter().take(n).map(|event| event.u64));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::os::fd::AsFd as _;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::time::timeout;
+
+    use crate::watcher::DisconnectWatcher;
+
+    #[tokio::test]
+    async fn reports_disconnects_by_token() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let watcher = DisconnectWatcher::new().unwrap();
+
+        let first = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (first_accepted, _) = listener.accept().unwrap();
+        let second = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (second_accepted, _) = listener.accept().unwrap();
+
+        watcher.register(first_accepted.as_fd(), 1).unwrap();
+        watcher.register(second_accepted.as_fd(), 2).unwrap();
+
+        drop(second);
+
+        let mut tokens = Vec::new();
+
+        while tokens.is_empty() {
+            timeout(Duration::from_secs(5), watcher.wait(&mut tokens))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        assert_eq!(tokens, [2]);
+
+        drop(first);
+    }
+}
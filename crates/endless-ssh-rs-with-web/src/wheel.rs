@@ -0,0 +1,258 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Slots per level, as a power of two.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = (1 << SLOT_BITS) - 1;
+/// With 10ms ticks, the top level spans more than 46 hours.
+const LEVELS: usize = 4;
+/// What all 4 levels span, timers further out are pulled in to this.
+const MAX_TICKS: u64 = (1 << (SLOT_BITS * 4)) - 1;
+
+/// A hierarchical timing wheel, see Varghese and Lauck, "Hashed and Hierarchical Timing Wheels".
+///
+/// Every level has 64 slots, each spanning 64 times as many ticks as a slot on the level below.
+/// Timers are filed in the lowest level that covers their deadline, and move down a level whenever
+/// the wheel reaches their slot, so inserting is `O(1)`, and advancing is `O(1)` per tick plus the timers that fire or move.
+pub struct TimerWheel<T> {
+    tick: Duration,
+    start: Instant,
+    /// Ticks since `start` that have been processed.
+    elapsed: u64,
+    /// `levels[level][slot]`, every timer with its deadline in ticks.
+    levels: Vec<Vec<Vec<(u64, T)>>>,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new(start: Instant, tick: Duration) -> Self {
+        Self {
+            tick,
+            start,
+            elapsed: 0,
+            levels: std::iter::repeat_with(|| {
+                std::iter::repeat_with(Vec::new).take(SLOTS).collect()
+            })
+            .take(LEVELS)
+            .collect(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The whole ticks between `start` and `instant`.
+    fn ticks_at(&self, instant: Instant) -> u64 {
+        let nanos = instant.saturating_duration_since(self.start).as_nanos() / self.tick.as_nanos();
+
+        u64::try_from(nanos).unwrap_or(u64::MAX)
+    }
+
+    /// Fires `item` on the first tick at or after `deadline`, but never on a tick already processed.
+    pub fn insert(&mut self, deadline: Instant, item: T) {
+        let deadline = deadline.saturating_duration_since(self.start);
+
+        // round up, a timer never fires early
+        let when = u64::try_from(deadline.as_nanos().div_ceil(self.tick.as_nanos()))
+            .unwrap_or(u64::MAX)
+            .clamp(self.elapsed + 1, self.elapsed + MAX_TICKS);
+
+        self.file(when, item);
+
+        self.len += 1;
+    }
+
+    fn file(&mut self, when: u64, item: T) {
+        let (level, slot) = slot_for(self.elapsed, when);
+
+        self.levels[level][slot].push((when, item));
+    }
+
+    /// When the next tick with (possibly) something to do is due, `None` when there are no timers.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.len == 0 {
+            return None;
+        }
+
+        // level 0 only holds timers up to the next level boundary, after that the wheel needs to cascade
+        let boundary = (self.elapsed | SLOT_MASK) + 1;
+
+        let next = (self.elapsed + 1..boundary)
+            .find(|&tick| !self.levels[0][slot_index(tick, 0)].is_empty())
+            .unwrap_or(boundary);
+
+        Some(self.instant_at(next))
+    }
+
+    fn instant_at(&self, tick: u64) -> Instant {
+        self.start + Duration::from_nanos_u128(self.tick.as_nanos() * u128::from(tick))
+    }
+
+    /// Processes every tick up to `now`, appending the items whose timer fired to `fired`.
+    pub fn advance(&mut self, now: Instant, fired: &mut Vec<T>) {
+        let target = self.ticks_at(now);
+
+        if self.len == 0 {
+            self.elapsed = self.elapsed.max(target);
+
+            return;
+        }
+
+        while self.elapsed < target {
+            self.elapsed += 1;
+
+            let tick = self.elapsed;
+
+            // move the timers of every level whose slot just came up one level down, highest first
+            for level in (1..LEVELS).rev() {
+                if tick.trailing_zeros() < shift(level) {
+                    continue;
+                }
+
+                let timers = std::mem::take(&mut self.levels[level][slot_index(tick, level)]);
+
+                for (when, item) in timers {
+                    self.file(when, item);
+                }
+            }
+
+            let due = std::mem::take(&mut self.levels[0][slot_index(tick, 0)]);
+
+            self.len -= due.len();
+
+            fired.extend(due.into_iter().map(|(_, item)| item));
+
+            if self.len == 0 {
+                self.elapsed = target;
+            }
+        }
+    }
+}
+
+/// Ticks per slot on `level`, as a power of two.
+fn shift(level: usize) -> u32 {
+    SLOT_BITS * u32::try_from(level).expect("there are only 4 levels")
+}
+
+fn slot_index(tick: u64, level: usize) -> usize {
+    usize::try_from((tick >> shift(level)) & SLOT_MASK).expect("masked to 6 bits")
+}
+
+/// The lowest level whose current rotation still covers `when`, which is the highest level where `elapsed` and `when` differ.
+fn slot_for(elapsed: u64, when: u64) -> (usize, usize) {
+    let differing = (elapsed ^ when) | SLOT_MASK;
+    let significant = u64::BITS - 1 - differing.leading_zeros();
+
+    let level = usize::try_from(significant / SLOT_BITS)
+        .expect("at most 10 levels in 64 bits")
+        .min(LEVELS - 1);
+
+    (level, slot_index(when, level))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::time::Instant;
+
+    use crate::wheel::TimerWheel;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn fires_on_time() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start, TICK);
+
+        wheel.insert(start + Duration::from_millis(25), "a");
+        wheel.insert(start + Duration::from_millis(10), "b");
+
+        let mut fired = Vec::new();
+
+        wheel.advance(start + Duration::from_millis(20), &mut fired);
+        assert_eq!(fired, ["b"]);
+
+        // rounded up, never early
+        wheel.advance(start + Duration::from_millis(29), &mut fired);
+        assert_eq!(fired, ["b"]);
+
+        wheel.advance(start + Duration::from_millis(30), &mut fired);
+        assert_eq!(fired, ["b", "a"]);
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn cascades_from_higher_levels() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start, TICK);
+
+        // level 1, level 2 and level 3 timers
+        let delays = [
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            Duration::from_hours(1),
+        ];
+
+        for (index, delay) in delays.into_iter().enumerate() {
+            wheel.insert(start + delay, index);
+        }
+
+        for (index, delay) in delays.into_iter().enumerate() {
+            let mut fired = Vec::new();
+
+            wheel.advance(start + delay - TICK, &mut fired);
+            assert_eq!(fired.len(), 0, "{} fired early", index);
+
+            wheel.advance(start + delay, &mut fired);
+            assert_eq!(fired, [index]);
+        }
+    }
+
+    #[test]
+    fn never_fires_in_the_past() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start, TICK);
+
+        let mut fired = Vec::new();
+
+        wheel.advance(start + Duration::from_secs(1), &mut fired);
+        wheel.insert(start, "late");
+
+        assert_eq!(
+            wheel.next_deadline(),
+            Some(start + Duration::from_millis(1010))
+        );
+
+        wheel.advance(start + Duration::from_millis(1010), &mut fired);
+        assert_eq!(fired, ["late"]);
+    }
+
+    #[test]
+    fn next_deadline_stops_at_level_boundary() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start, TICK);
+
+        assert_eq!(wheel.next_deadline(), None);
+
+        wheel.insert(start + Duration::from_millis(50), ());
+        assert_eq!(
+            wheel.next_deadline(),
+            Some(start + Duration::from_millis(50))
+        );
+
+        let mut wheel = TimerWheel::new(start, TICK);
+
+        wheel.insert(start + Duration::from_secs(10), ());
+        // 64 ticks in, level 1 gets cascaded
+        assert_eq!(
+            wheel.next_deadline(),
+            Some(start + Duration::from_millis(640))
+        );
+    }
+}
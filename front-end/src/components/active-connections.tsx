@@ -37,14 +37,18 @@ function secondsConnected(connectedAt: string, now: Temporal.Instant): number {
 
 const ConnectionRow: React.FC<{ connection: ActiveConnectionInfo; now: Temporal.Instant }> = ({ connection, now }) => {
     const ip = formatIp(connection.ip);
+    const sources = `${connection.connections_from_ip.toString()} from this address, ${connection.connections_from_subnet.toString()} from ${connection.subnet}`;
 
     return (
         <div className="col-span-full grid grid-cols-subgrid items-center rounded-sm bg-gray-800 px-3 py-2 text-sm">
             <CountryFlag countryCode={connection.country_code} countryName={connection.country_name} />
             <span className="truncate text-gray-400">{connection.city ?? "Unknown"}</span>
-            <span className="truncate font-mono text-gray-300" title={ip}>
+            <span className="truncate font-mono text-gray-300" title={`${connection.client_ident ?? ip} (${sources})`}>
                 {ip}
             </span>
+            <span className="font-mono text-xs text-gray-500 uppercase" title={connection.listener}>
+                {connection.protocol}
+            </span>
             <span className="text-right text-green-400">
                 {formatDuration(secondsConnected(connection.connected_at, now))}
             </span>
@@ -60,7 +64,7 @@ export const ActiveConnections: React.FC<Properties> = ({ activeConnections }) =
         <div className="flex h-[350px] flex-col rounded-lg bg-gray-900 p-3">
             <h3 className="mb-2 text-sm font-semibold text-gray-400">Active connections</h3>
 
-            <div className="grid min-h-0 flex-1 grid-cols-[auto_minmax(0,max-content)_minmax(3rem,1fr)_max-content_max-content] content-start gap-x-3 gap-y-1 overflow-y-auto">
+            <div className="grid min-h-0 flex-1 grid-cols-[auto_minmax(0,max-content)_minmax(3rem,1fr)_max-content_max-content_max-content] content-start gap-x-3 gap-y-1 overflow-y-auto">
                 {activeConnections.length === 0 && (
                     <p className="col-span-full py-6 text-center text-gray-500">No active connections</p>
                 )}
@@ -56,7 +56,12 @@ const EventRow: React.FC<{ event: DisconnectedEvent }> = ({ event }) => {
         <div className="col-span-full grid grid-cols-subgrid items-center rounded-sm bg-gray-800 px-3 py-2 text-sm">
             <CountryFlag countryCode={event.country_code} countryName={event.country_name} />
             <span className="truncate text-gray-400">{event.city ?? "Unknown"}</span>
-            <span className="truncate font-mono text-gray-300">{formatIp(event.ip)}</span>
+            <span className="truncate font-mono text-gray-300" title={event.client_ident ?? undefined}>
+                {formatIp(event.ip)}
+            </span>
+            <span className="font-mono text-xs text-gray-500 uppercase" title={event.listener ?? undefined}>
+                {event.protocol}
+            </span>
             <span className="truncate pl-3 text-right font-mono text-gray-300">{disconnectedAt}</span>
             <span className="text-right text-red-400">{formatDuration(event.time_spent)}</span>
             <span className="text-right text-gray-500">{formatBytes(event.bytes_sent)}</span>
@@ -70,7 +75,7 @@ export const EventFeed: React.FC<Properties> = ({ events }) => {
             <h2 className="mb-2 text-lg font-semibold text-gray-300">Recent disconnections (times in {TIMEZONE})</h2>
             <div
                 role="log"
-                className="grid max-h-100 grid-cols-[auto_minmax(0,12rem)_minmax(0,max-content)_minmax(0,1fr)_max-content_max-content] gap-x-3 gap-y-1 overflow-y-auto"
+                className="grid max-h-100 grid-cols-[auto_minmax(0,12rem)_minmax(0,max-content)_max-content_minmax(0,1fr)_max-content_max-content] gap-x-3 gap-y-1 overflow-y-auto"
             >
                 {events.length === 0 && (
                     <p className="col-span-full py-6 text-center text-gray-500">Waiting for connections…</p>
@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Protocol } from "./Protocol";
 
 /**
  * In-memory representation of currently connected clients.
@@ -8,6 +9,22 @@
 export type ActiveConnectionInfo = {
   ip: string;
   port: number;
+  /**
+   * The subnet the listener's per-subnet limit counts this connection towards.
+   */
+  subnet: string;
+  /**
+   * Connections to the same listener from this address, this one included.
+   */
+  connections_from_ip: number;
+  /**
+   * Connections to the same listener from this subnet, this one included.
+   */
+  connections_from_subnet: number;
+  listener: string;
+  protocol: Protocol;
+  client_ident: string | null;
+  hassh: string | null;
   connected_at: string;
   bytes_sent: number;
   latitude: number | null;
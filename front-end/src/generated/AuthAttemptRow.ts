@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuthMethod } from "./AuthMethod";
+
+/**
+ * A single row returned by the `/api/auth-attempts` endpoint.
+ */
+export type AuthAttemptRow = {
+  /**
+   * The `connections` row this attempt belongs to.
+   */
+  connection_id: number;
+  attempted_at: string;
+  ip: string;
+  method: AuthMethod;
+  username: string;
+  password: string | null;
+  key_fingerprint: string | null;
+};
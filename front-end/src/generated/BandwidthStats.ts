@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What the bandwidth limiter is up to, part of [`crate::scheduler::EngineStats`].
+ */
+export type BandwidthStats = {
+  /**
+   * Bytes per second, `null` when unlimited.
+   */
+  limit: number | null;
+  /**
+   * Bytes per second sent to clients, over the last second, what isn't limited included.
+   */
+  bytes_per_second: number;
+  /**
+   * `bytes_per_second`, in percent of `limit`, `null` when unlimited.
+   */
+  utilisation: number | null;
+  /**
+   * Lines held back because the budget was spent, since we started.
+   */
+  deferred_lines: number;
+};
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Per-client-software totals returned by the `/api/stats/clients` endpoint.
+ */
+export type ClientSoftwareStatsRow = {
+  /**
+   * The software part of the identification line, e.g. `OpenSSH_9.6` out of `SSH-2.0-OpenSSH_9.6 Ubuntu`.
+   * `None` for clients that didn't identify as SSH, or weren't asked to.
+   */
+  client_software: string | null;
+  connects: number;
+  time_spent: number;
+  bytes_sent: number;
+};
@@ -0,0 +1,36 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Protocol } from "./Protocol";
+import type { TcpMetrics } from "./TcpMetrics";
+
+/**
+ * A single connection returned by the `/api/connections/{id}` endpoint.
+ */
+export type ConnectionRow = {
+  id: number;
+  ip: string;
+  port: number;
+  /**
+   * `None` for connections stored before listeners had names.
+   */
+  listener: string | null;
+  protocol: Protocol;
+  client_ident: string | null;
+  hassh: string | null;
+  connected_at: string;
+  disconnected_at: string;
+  time_spent: number;
+  bytes_sent: number;
+  /**
+   * `None` for connections stored before it was.
+   */
+  disconnect_reason: string | null;
+  /**
+   * `None` when the kernel couldn't tell, and for connections stored before they were.
+   */
+  tcp_metrics: TcpMetrics | null;
+  country_code: string | null;
+  country_name: string | null;
+  city: string | null;
+  latitude: number | null;
+  longitude: number | null;
+};
@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Per delay strategy totals returned by the `/api/stats/delays` endpoint.
+ */
+export type DelayStatsRow = {
+  /**
+   * E.g. `pareto:1.5`.
+   */
+  delay_strategy: string;
+  delay: number;
+  connects: number;
+  time_spent: number;
+  /**
+   * How long the average client stayed trapped.
+   */
+  average_time_spent: number;
+  bytes_sent: number;
+};
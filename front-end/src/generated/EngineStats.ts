@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BandwidthStats } from "./BandwidthStats";
+
+/**
+ * What the schedulers are up to, returned by the `/api/engine` endpoint.
+ */
+export type EngineStats = {
+  schedulers: number;
+  /**
+   * Clients being dripped to, those still in their handshake and honeypot clients are not included.
+   */
+  trapped_clients: number;
+  /**
+   * What the trapped clients cost the schedulers, in bytes.
+   */
+  memory: number;
+  /**
+   * `memory`, per trapped client.
+   */
+  memory_per_client: number;
+  bandwidth: BandwidthStats;
+};
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Per-HASSH totals returned by the `/api/stats/hassh` endpoint.
+ */
+export type HasshStatsRow = {
+  hassh: string;
+  /**
+   * Every identification line seen with this fingerprint.
+   */
+  client_idents: Array<string>;
+  connects: number;
+  time_spent: number;
+  bytes_sent: number;
+};
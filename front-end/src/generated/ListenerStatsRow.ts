@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Protocol } from "./Protocol";
+
+/**
+ * Per-listener totals returned by the `/api/stats/listeners` endpoint.
+ */
+export type ListenerStatsRow = {
+  /**
+   * `None` for connections stored before listeners had names.
+   */
+  listener: string | null;
+  protocol: Protocol;
+  connects: number;
+  time_spent: number;
+  bytes_sent: number;
+};
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What a tarpit pretends to be.
+ */
+export type Protocol =
+  | "ssh"
+  | "ssh-kex"
+  | "ssh-honeypot"
+  | "http"
+  | "smtp"
+  | "telnet";
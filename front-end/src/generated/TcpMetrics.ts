@@ -0,0 +1,28 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What the kernel measured of a connection, from `TCP_INFO`. A client close by has a short round trip time, and one
+ * far away, or behind a congested link, a long one, and more retransmits.
+ */
+export type TcpMetrics = {
+  /**
+   * Smoothed round trip time, in microseconds.
+   */
+  rtt_us: number;
+  /**
+   * Mean deviation of the round trip time, in microseconds.
+   */
+  rtt_var_us: number;
+  /**
+   * The largest segment we send the client, in bytes.
+   */
+  mss: number;
+  /**
+   * Segments retransmitted over the connection's life.
+   */
+  retransmits: number;
+  /**
+   * Congestion window, in segments.
+   */
+  congestion_window: number;
+};
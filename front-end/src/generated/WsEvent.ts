@@ -1,5 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { ActiveConnectionInfo } from "./ActiveConnectionInfo";
+import type { AuthMethod } from "./AuthMethod";
+import type { Protocol } from "./Protocol";
+import type { TcpMetrics } from "./TcpMetrics";
 
 /**
  * WebSocket broadcast.
@@ -22,6 +25,17 @@ export type WsEvent =
     "type": "connected";
     ip: string;
     port: number;
+    subnet: string;
+    listener: string;
+    protocol: Protocol;
+    /**
+     * The identification line the client sent, when captured.
+     */
+    client_ident: string | null;
+    /**
+     * The fingerprint of the client's KEXINIT, in key exchange mode.
+     */
+    hassh: string | null;
     connected_at: string;
     country_code: string | null;
     country_name: string | null;
@@ -30,15 +44,44 @@ export type WsEvent =
     longitude: number | null;
   }
   | { "type": "bytes_sent"; ip: string; port: number; bytes_sent: number }
+  | {
+    "type": "auth_attempt";
+    ip: string;
+    port: number;
+    listener: string;
+    method: AuthMethod;
+    username: string;
+    password: string | null;
+    key_fingerprint: string | null;
+    attempted_at: string;
+  }
   | {
     "type": "disconnected";
     sequence: number;
     ip: string;
     port: number;
+    /**
+     * `None` for connections stored before listeners had names.
+     */
+    listener: string | null;
+    protocol: Protocol;
+    /**
+     * The identification line the client sent, when captured.
+     */
+    client_ident: string | null;
+    /**
+     * The fingerprint of the client's KEXINIT, in key exchange mode.
+     */
+    hassh: string | null;
     connected_at: string;
     disconnected_at: string;
     time_spent: number;
     bytes_sent: number;
+    /**
+     * What the kernel measured of the connection as it ended, `None` when it couldn't tell, and for connections
+     * stored before.
+     */
+    tcp_metrics: TcpMetrics | null;
     country_code: string | null;
     country_name: string | null;
     city: string | null;
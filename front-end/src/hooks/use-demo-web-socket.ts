@@ -1,6 +1,7 @@
 import { useEffect } from "react";
 import { Temporal } from "temporal-polyfill";
 
+import type { Protocol } from "../generated/Protocol";
 import type { WsEvent } from "../generated/WsEvent";
 
 interface Options {
@@ -33,6 +34,17 @@ const LOCATIONS: DemoLocation[] = [
     { city: "Hanoi", country_code: "VN", country_name: "Vietnam", latitude: 21.03, longitude: 105.85 },
 ];
 
+// weighted towards ssh, like the real traffic
+const PROTOCOLS: Protocol[] = ["ssh", "ssh", "ssh", "ssh-kex", "ssh-honeypot", "http", "smtp", "telnet"];
+
+// what the usual scanners identify as
+const CLIENT_IDENTS = [
+    "SSH-2.0-Go",
+    "SSH-2.0-libssh_0.9.6",
+    "SSH-2.0-paramiko_3.4.0",
+    "SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13",
+];
+
 const SPAWN_MIN_MS = 500;
 const SPAWN_MAX_MS = 3000;
 const LIFETIME_MIN_MS = 5000;
@@ -55,6 +67,21 @@ function randomIp(): string {
     return `${randomInt(1, 223).toString()}.${randomInt(0, 255).toString()}.${randomInt(0, 255).toString()}.${randomInt(1, 254).toString()}`;
 }
 
+// the /24 or /64 the server would group the address by
+function subnetOf(ip: string): string {
+    if (ip.includes(":")) {
+        return `${ip.split(":").slice(0, 4).join(":")}::/64`;
+    }
+
+    return `${ip.split(".").slice(0, 3).join(".")}.0/24`;
+}
+
+function randomHassh(): string {
+    return Array.from({ length: 32 }, () => {
+        return randomInt(0, 15).toString(16);
+    }).join("");
+}
+
 // spread markers from the same city so they don't stack on one pixel
 function jitter(coordinate: number): number {
     return coordinate + (Math.random() - 0.5) * 1.5;
@@ -88,6 +115,13 @@ export function useDemoWebSocket({ onEvent }: Options): { status: "demo" } {
 
             const ip = randomIp();
             const port = randomInt(1024, 65_535);
+            const protocol = PROTOCOLS[randomInt(0, PROTOCOLS.length - 1)] ?? "ssh";
+            const listener = `demo-${protocol}`;
+            const clientIdent =
+                protocol === "ssh" || protocol === "ssh-kex"
+                    ? (CLIENT_IDENTS[randomInt(0, CLIENT_IDENTS.length - 1)] ?? null)
+                    : null;
+            const hassh = protocol === "ssh-kex" ? randomHassh() : null;
             const connectedAt = Temporal.Now.instant();
             const lifetimeMs = randomInt(LIFETIME_MIN_MS, LIFETIME_MAX_MS);
 
@@ -95,6 +129,11 @@ export function useDemoWebSocket({ onEvent }: Options): { status: "demo" } {
                 type: "connected",
                 ip,
                 port,
+                subnet: subnetOf(ip),
+                listener,
+                protocol,
+                client_ident: clientIdent,
+                hassh,
                 connected_at: connectedAt.toString(),
                 ...geo,
             });
@@ -123,10 +162,15 @@ export function useDemoWebSocket({ onEvent }: Options): { status: "demo" } {
                     sequence,
                     ip,
                     port,
+                    listener,
+                    protocol,
+                    client_ident: clientIdent,
+                    hassh,
                     connected_at: connectedAt.toString(),
                     disconnected_at: disconnectedAt.toString(),
                     time_spent: timeSpent,
                     bytes_sent: bytesSent,
+                    tcp_metrics: null,
                     ...geo,
                 });
             });
@@ -22,11 +22,16 @@ function init(overrides?: Partial<Omit<InitEvent, "type">>): InitEvent {
 
 const READY: ReadyEvent = { type: "ready" };
 
-function connected(ip: string, port: number): ConnectedEvent {
+function connected(ip: string, port: number, subnet = `${ip}/32`): ConnectedEvent {
     return {
         type: "connected",
         ip,
         port,
+        subnet,
+        listener: "[::]:2223",
+        protocol: "ssh",
+        client_ident: null,
+        hassh: null,
         connected_at: "2026-07-27T10:00:00Z",
         country_code: null,
         country_name: null,
@@ -45,10 +50,15 @@ function disconnected(
         sequence,
         ip: `192.0.2.${sequence.toString()}`,
         port: 50_000,
+        listener: "[::]:2223",
+        protocol: "ssh",
+        client_ident: null,
+        hassh: null,
         connected_at: "2026-07-27T10:00:00Z",
         disconnected_at: "2026-07-27T10:01:00Z",
         time_spent: 60,
         bytes_sent: 1000,
+        tcp_metrics: null,
         country_code: null,
         country_name: null,
         city: null,
@@ -62,6 +72,13 @@ function activeConnection(ip: string, overrides?: Partial<Omit<ActiveConnectionI
     return {
         ip,
         port: 50_000,
+        subnet: `${ip}/32`,
+        connections_from_ip: 1,
+        connections_from_subnet: 1,
+        listener: "[::]:2223",
+        protocol: "ssh",
+        client_ident: null,
+        hassh: null,
         connected_at: "2026-07-27T09:00:00Z",
         bytes_sent: 0,
         latitude: null,
@@ -152,6 +169,28 @@ describe("wsReducer", () => {
         });
     });
 
+    describe("source counts", () => {
+        it("counts connections from the same ip and subnet", () => {
+            const state = applyEvents(INITIAL_WS_STATE, [
+                init(),
+                READY,
+                connected("198.51.100.7", 1111, "198.51.100.0/24"),
+                connected("198.51.100.7", 2222, "198.51.100.0/24"),
+                connected("198.51.100.8", 3333, "198.51.100.0/24"),
+                disconnected(1, { ip: "198.51.100.7", port: 1111 }),
+            ]);
+
+            expect(
+                state.activeConnections.map((c) => {
+                    return [c.port, c.connections_from_ip, c.connections_from_subnet];
+                }),
+            ).toEqual([
+                [2222, 1, 2],
+                [3333, 1, 2],
+            ]);
+        });
+    });
+
     describe("bytes_sent", () => {
         it("updates only the matching connection", () => {
             const state = applyEvents(INITIAL_WS_STATE, [
@@ -35,6 +35,25 @@ function isSameConnection(active: ActiveConnectionInfo, event: DisconnectedEvent
     return Temporal.Instant.compare(active.connected_at, event.disconnected_at) < 0;
 }
 
+// mirrors the server's per listener counts, which only arrive with init
+function withSourceCounts(connections: ActiveConnectionInfo[]): ActiveConnectionInfo[] {
+    return connections.map((c) => {
+        const sameListener = connections.filter((other) => {
+            return other.listener === c.listener;
+        });
+
+        return {
+            ...c,
+            connections_from_ip: sameListener.filter((other) => {
+                return other.ip === c.ip;
+            }).length,
+            connections_from_subnet: sameListener.filter((other) => {
+                return other.subnet === c.subnet;
+            }).length,
+        };
+    });
+}
+
 export function wsReducer(state: WsState, event: WsEvent): WsState {
     switch (event.type) {
         case "init": {
@@ -66,11 +85,18 @@ export function wsReducer(state: WsState, event: WsEvent): WsState {
 
             return {
                 ...state,
-                activeConnections: [
+                activeConnections: withSourceCounts([
                     ...state.activeConnections,
                     {
                         ip: event.ip,
                         port: event.port,
+                        subnet: event.subnet,
+                        connections_from_ip: 1,
+                        connections_from_subnet: 1,
+                        listener: event.listener,
+                        protocol: event.protocol,
+                        client_ident: event.client_ident,
+                        hassh: event.hassh,
                         connected_at: event.connected_at,
                         bytes_sent: 0,
                         latitude: event.latitude,
@@ -79,7 +105,7 @@ export function wsReducer(state: WsState, event: WsEvent): WsState {
                         country_name: event.country_name,
                         city: event.city,
                     },
-                ],
+                ]),
             };
         }
         case "bytes_sent": {
@@ -90,6 +116,10 @@ export function wsReducer(state: WsState, event: WsEvent): WsState {
                 }),
             };
         }
+        case "auth_attempt": {
+            // attempts are only shown through the API for now
+            return state;
+        }
         case "disconnected": {
             // the sequence is monotonic and delivery is in-order, so anything at
             // or below the high-water mark is a replay duplicate
@@ -99,9 +129,11 @@ export function wsReducer(state: WsState, event: WsEvent): WsState {
 
             const next: WsState = {
                 ...state,
-                activeConnections: state.activeConnections.filter((c) => {
-                    return !isSameConnection(c, event);
-                }),
+                activeConnections: withSourceCounts(
+                    state.activeConnections.filter((c) => {
+                        return !isSameConnection(c, event);
+                    }),
+                ),
                 events: [...state.events, event].slice(-MAX_EVENTS),
                 maxSeenSequence: event.sequence,
             };